@@ -0,0 +1,70 @@
+/*
+ * The ecall service interface riscmon offers to the programs it launches with
+ * `jump` and `ujump` (machine-mode builds only). Mirrors the riscmon-abi crate.
+ *
+ * Put the call number in a7 and the arguments in a0/a1, then `ecall`. Results
+ * come back in a0; every other register is preserved.
+ */
+
+#ifndef RISCMON_H
+#define RISCMON_H
+
+#define RISCMON_PUTC    1 /* a0 = byte */
+#define RISCMON_GETC    2 /* returns byte in a0 */
+#define RISCMON_PUTS    3 /* a0 = buffer, a1 = length; returns 0 or RISCMON_ERROR */
+#define RISCMON_PUT_HEX 4 /* a0 = value, printed as 16 hex digits */
+#define RISCMON_CYCLES  5 /* returns the cycle counter in a0 */
+#define RISCMON_EXIT    6 /* a0 = exit code; does not return */
+#define RISCMON_MONITOR 7 /* stop until the monitor's `continue` */
+
+#define RISCMON_ERROR ((unsigned long)-1)
+
+static inline unsigned long riscmon_ecall(unsigned long num, unsigned long a0, unsigned long a1)
+{
+    register unsigned long r_a7 __asm__("a7") = num;
+    register unsigned long r_a0 __asm__("a0") = a0;
+    register unsigned long r_a1 __asm__("a1") = a1;
+    __asm__ volatile("ecall" : "+r"(r_a0) : "r"(r_a7), "r"(r_a1) : "memory");
+    return r_a0;
+}
+
+static inline void riscmon_putc(char c)
+{
+    riscmon_ecall(RISCMON_PUTC, (unsigned char)c, 0);
+}
+
+static inline char riscmon_getc(void)
+{
+    return (char)riscmon_ecall(RISCMON_GETC, 0, 0);
+}
+
+static inline int riscmon_puts(const char *s)
+{
+    unsigned long len = 0;
+    while (s[len])
+        len++;
+    return riscmon_ecall(RISCMON_PUTS, (unsigned long)s, len) == RISCMON_ERROR ? -1 : 0;
+}
+
+static inline void riscmon_put_hex(unsigned long v)
+{
+    riscmon_ecall(RISCMON_PUT_HEX, v, 0);
+}
+
+static inline unsigned long riscmon_cycles(void)
+{
+    return riscmon_ecall(RISCMON_CYCLES, 0, 0);
+}
+
+static inline void __attribute__((noreturn)) riscmon_exit(unsigned long code)
+{
+    riscmon_ecall(RISCMON_EXIT, code, 0);
+    __builtin_unreachable();
+}
+
+static inline void riscmon_monitor(void)
+{
+    riscmon_ecall(RISCMON_MONITOR, 0, 0);
+}
+
+#endif /* RISCMON_H */
@@ -0,0 +1,101 @@
+//! The `ecall` service interface riscmon offers to the programs it launches
+//! with `jump` and `ujump` (machine-mode builds only).
+//!
+//! A program puts the call number in `a7` and its arguments in `a0`/`a1`, then
+//! executes `ecall`. Results come back in `a0`; every other register is
+//! preserved. The same numbers are available to C in `riscmon.h`.
+//!
+//! The wrappers below are only compiled for RISC-V targets, so the constants
+//! can also be used from host tools.
+
+#![no_std]
+
+// -----------------------------------------------------------------------------
+// Call Numbers
+// -----------------------------------------------------------------------------
+
+/// Write the byte in `a0` to the console.
+pub const PUTC: usize = 1;
+
+/// Wait for a byte from the console and return it in `a0`.
+pub const GETC: usize = 2;
+
+/// Write the `a1` bytes at address `a0` to the console. Returns 0, or
+/// [`ERROR`] if the buffer is not memory the program may access.
+pub const PUTS: usize = 3;
+
+/// Write `a0` to the console as sixteen lowercase hex digits.
+pub const PUT_HEX: usize = 4;
+
+/// Return the hart's cycle counter in `a0`.
+pub const CYCLES: usize = 5;
+
+/// End the program with the exit code in `a0`. Does not return.
+pub const EXIT: usize = 6;
+
+/// Stop and return to the monitor's prompt. The monitor's `continue` command
+/// resumes the program after the call.
+pub const MONITOR: usize = 7;
+
+/// Returned in `a0` by calls that fail.
+pub const ERROR: usize = usize::MAX;
+
+// -----------------------------------------------------------------------------
+// Wrappers
+// -----------------------------------------------------------------------------
+
+#[cfg(target_arch = "riscv64")]
+mod calls {
+    use core::arch::asm;
+
+    // Make a service call with up to two arguments.
+    #[inline(always)]
+    fn ecall(num: usize, a0: usize, a1: usize) -> usize {
+        let ret;
+        unsafe {
+            asm!("ecall", in("a7") num, inlateout("a0") a0 => ret, in("a1") a1);
+        }
+        ret
+    }
+
+    /// Write a byte to the console.
+    pub fn putc(c: u8) {
+        ecall(super::PUTC, c as usize, 0);
+    }
+
+    /// Wait for a byte from the console.
+    pub fn getc() -> u8 {
+        ecall(super::GETC, 0, 0) as u8
+    }
+
+    /// Write a string to the console. Returns false if riscmon rejected the
+    /// buffer.
+    pub fn puts(s: &str) -> bool {
+        ecall(super::PUTS, s.as_ptr() as usize, s.len()) != super::ERROR
+    }
+
+    /// Write a value to the console as sixteen lowercase hex digits.
+    pub fn put_hex(v: u64) {
+        ecall(super::PUT_HEX, v as usize, 0);
+    }
+
+    /// Read the hart's cycle counter.
+    pub fn cycles() -> u64 {
+        ecall(super::CYCLES, 0, 0) as u64
+    }
+
+    /// End the program with `code`.
+    pub fn exit(code: usize) -> ! {
+        ecall(super::EXIT, code, 0);
+        unreachable!("riscmon does not resume exited programs")
+    }
+
+    /// Stop and return to the monitor's prompt until the user types
+    /// `continue`.
+    pub fn monitor() {
+        ecall(super::MONITOR, 0, 0);
+    }
+}
+
+#[cfg(target_arch = "riscv64")]
+pub use calls::*;
@@ -0,0 +1,13 @@
+// Select the linker script: riscmon is linked at the start of RAM when it runs
+// in machine mode, and 2 MiB in (after the firmware) when it runs as a
+// supervisor-mode payload under SBI firmware.
+fn main() {
+    let script = match std::env::var_os("CARGO_FEATURE_SUPERVISOR") {
+        Some(_) => "linker-supervisor.ld",
+        None => "linker.ld",
+    };
+
+    println!("cargo:rustc-link-arg-bins=-T{script}");
+    println!("cargo:rerun-if-changed=linker.ld");
+    println!("cargo:rerun-if-changed=linker-supervisor.ld");
+}
@@ -0,0 +1,245 @@
+// -----------------------------------------------------------------------------
+// Checksums and Digests
+// -----------------------------------------------------------------------------
+
+// Incremental, so ranges of any size can be fed in pieces. Each matches the
+// usual host tool: `crc32` (zlib/IEEE 802.3), a plain byte sum, zlib's
+// Adler-32 and SHA-256 (FIPS 180-4).
+
+/// Longest digest produced (SHA-256).
+pub(crate) const MAX_DIGEST: usize = 32;
+
+/// A checksum algorithm.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Kind {
+    Crc32,
+    Sum,
+    Adler32,
+    Sha256,
+}
+
+impl Kind {
+    /// Name, as used for the command.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Kind::Crc32 => "crc32",
+            Kind::Sum => "sum",
+            Kind::Adler32 => "adler32",
+            Kind::Sha256 => "sha256",
+        }
+    }
+}
+
+/// A checksum being computed.
+pub(crate) enum Hasher {
+    Crc32(u32),
+    Sum(u32),
+    Adler32 { a: u32, b: u32 },
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(kind: Kind) -> Hasher {
+        match kind {
+            Kind::Crc32 => Hasher::Crc32(!0),
+            Kind::Sum => Hasher::Sum(0),
+            Kind::Adler32 => Hasher::Adler32 { a: 1, b: 0 },
+            Kind::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Feed in the next bytes.
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32(crc) => {
+                for &b in data {
+                    *crc = CRC32_TABLE[((*crc ^ b as u32) & 0xff) as usize] ^ (*crc >> 8);
+                }
+            }
+            Hasher::Sum(sum) => {
+                for &b in data {
+                    *sum = sum.wrapping_add(b as u32);
+                }
+            }
+            Hasher::Adler32 { a, b } => {
+                // Reduce only as often as needed to stay within u32.
+                for chunk in data.chunks(ADLER_NMAX) {
+                    for &byte in chunk {
+                        *a += byte as u32;
+                        *b += *a;
+                    }
+                    *a %= ADLER_MOD;
+                    *b %= ADLER_MOD;
+                }
+            }
+            Hasher::Sha256(sha) => sha.update(data),
+        }
+    }
+
+    /// The result, big-endian (as printed by host tools). Returns the digest
+    /// buffer and how many bytes of it are used.
+    pub(crate) fn finish(self) -> ([u8; MAX_DIGEST], usize) {
+        let mut out = [0u8; MAX_DIGEST];
+        let word = match self {
+            Hasher::Crc32(crc) => !crc,
+            Hasher::Sum(sum) => sum,
+            Hasher::Adler32 { a, b } => (b << 16) | a,
+            Hasher::Sha256(sha) => {
+                out = sha.finish();
+                return (out, MAX_DIGEST);
+            }
+        };
+        out[..4].copy_from_slice(&word.to_be_bytes());
+        (out, 4)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// CRC-32
+// -----------------------------------------------------------------------------
+
+// Reflected polynomial 0x04c11db7.
+const CRC32_POLY: u32 = 0xedb8_8320;
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                CRC32_POLY ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+};
+
+// -----------------------------------------------------------------------------
+// Adler-32
+// -----------------------------------------------------------------------------
+
+const ADLER_MOD: u32 = 65521;
+
+// Most bytes summed before `b` could overflow (as in zlib).
+const ADLER_NMAX: usize = 5552;
+
+// -----------------------------------------------------------------------------
+// SHA-256
+// -----------------------------------------------------------------------------
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 state.
+pub(crate) struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    // Bytes in `block`.
+    used: usize,
+    // Total bytes fed in.
+    len: u64,
+}
+
+impl Sha256 {
+    fn new() -> Sha256 {
+        Sha256 {
+            state: SHA256_INIT,
+            block: [0; 64],
+            used: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.used).min(data.len());
+            self.block[self.used..self.used + n].copy_from_slice(&data[..n]);
+            self.used += n;
+            data = &data[n..];
+            if self.used == 64 {
+                self.compress();
+                self.used = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        // Append 0x80, zeros, then the length in bits, to a whole block.
+        let bits = self.len.wrapping_mul(8);
+        self.block[self.used] = 0x80;
+        self.block[self.used + 1..].fill(0);
+        if self.used >= 56 {
+            self.compress();
+            self.block.fill(0);
+        }
+        self.block[56..].copy_from_slice(&bits.to_be_bytes());
+        self.compress();
+
+        let mut out = [0u8; 32];
+        for (o, s) in out.chunks_exact_mut(4).zip(self.state) {
+            o.copy_from_slice(&s.to_be_bytes());
+        }
+        out
+    }
+
+    // Mix in the full block.
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, c) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([c[0], c[1], c[2], c[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
@@ -0,0 +1,127 @@
+mod ns16550;
+mod sbi;
+mod sifive;
+
+use crate::fdt::Fdt;
+use core::sync::atomic::{AtomicU8, Ordering};
+use ns16550::Ns16550;
+use sbi::SbiConsole;
+use sifive::SifiveUart;
+
+// -----------------------------------------------------------------------------
+// Console Driver
+// -----------------------------------------------------------------------------
+
+/// A byte-oriented console device.
+///
+/// Everything the monitor prints or reads goes through the active console; the
+/// formatting helpers in `uart` sit on top of it.
+pub(crate) trait Console: Sync {
+    /// Short driver name, shown by `info`.
+    fn name(&self) -> &'static str;
+
+    /// MMIO base address, if the console is memory-mapped.
+    fn base(&self) -> Option<usize> {
+        None
+    }
+
+    /// Bring the device into a usable state.
+    fn init(&self) {}
+
+    /// Write a single byte (blocking).
+    fn putc(&self, c: u8);
+
+    /// Read a single byte if one is available (non-blocking).
+    fn try_getc(&self) -> Option<u8>;
+
+    /// Reprogram the baud rate. Returns false if unsupported.
+    fn set_baud(&self, _baud: u32) -> bool {
+        false
+    }
+
+    /// The currently programmed baud rate, if the device has one.
+    fn baud(&self) -> Option<u32> {
+        None
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Console Selection
+// -----------------------------------------------------------------------------
+
+// QEMU virt 16550 (also the fallback when nothing else is known).
+static NS16550: Ns16550 = Ns16550::new(0x1000_0000, 3_686_400);
+
+// QEMU sifive_u UART0.
+static SIFIVE: SifiveUart = SifiveUart::new(0x1001_0000);
+
+// SBI debug console (only usable under SBI firmware).
+static SBI: SbiConsole = SbiConsole::new();
+
+const KIND_NS16550: u8 = 0;
+const KIND_SIFIVE: u8 = 1;
+const KIND_SBI: u8 = 2;
+
+// The selected backend.
+static ACTIVE: AtomicU8 = AtomicU8::new(KIND_NS16550);
+
+/// Choose and initialize the console.
+///
+/// The `console-sbi` and `console-sifive` features force a backend. Otherwise
+/// the device tree's `stdout-path` decides, falling back to the QEMU virt 16550.
+/// Either way, a matching `stdout-path` node supplies the MMIO base.
+pub(crate) fn init(fdt: Option<Fdt>) {
+    let kind = match fdt {
+        _ if cfg!(feature = "console-sbi") => KIND_SBI,
+        Some(fdt) => from_device_tree(&fdt),
+        None if cfg!(feature = "console-sifive") => KIND_SIFIVE,
+        None => KIND_NS16550,
+    };
+
+    ACTIVE.store(kind, Ordering::Relaxed);
+    active().init();
+}
+
+/// The active console.
+pub(crate) fn active() -> &'static dyn Console {
+    match ACTIVE.load(Ordering::Relaxed) {
+        KIND_SIFIVE => &SIFIVE,
+        KIND_SBI => &SBI,
+        _ => &NS16550,
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Pick a backend from the device tree's stdout-path, configuring its base.
+fn from_device_tree(fdt: &Fdt) -> u8 {
+    let forced_sifive = cfg!(feature = "console-sifive");
+    let default = match forced_sifive {
+        true => KIND_SIFIVE,
+        false => KIND_NS16550,
+    };
+
+    let Some(node) = fdt.stdout() else {
+        return default;
+    };
+    let Some((base, _)) = fdt.reg(node, 0) else {
+        return default;
+    };
+
+    if fdt.is_compatible(node, "sifive,uart0") {
+        SIFIVE.configure(base as usize);
+        return KIND_SIFIVE;
+    }
+
+    if !forced_sifive && (fdt.is_compatible(node, "ns16550a") || fdt.is_compatible(node, "ns16550"))
+    {
+        let shift = fdt.property_u32(node, "reg-shift").unwrap_or(0);
+        let clock = fdt.property_u32(node, "clock-frequency");
+        NS16550.configure(base as usize, shift, clock);
+        return KIND_NS16550;
+    }
+
+    default
+}
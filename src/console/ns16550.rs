@@ -0,0 +1,204 @@
+use super::Console;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// UART register offsets and bitfields (16550-compatible)
+// -----------------------------------------------------------------------------
+
+// Register indices (scaled by the register shift to get a byte offset).
+
+// Offset 0 is *multiplexed*.
+// - read  @ +0 => RHR (Receive Holding Register: next received byte)
+// - write @ +0 => THR (Transmit Holding Register: byte to send)
+const UART_RHR_THR: usize = 0x00;
+
+// Divisor latch (LSB/MSB).
+//
+// 16550 quirk: these alias RHR/THR and IER while LCR.DLAB is set.
+const UART_DLL: usize = 0x00;
+const UART_DLM: usize = 0x01;
+
+// IER (Interrupt Enable Register).
+const UART_IER: usize = 0x01;
+
+// FCR (FIFO Control Register, write-only).
+const UART_FCR: usize = 0x02;
+
+// LCR (Line Control Register).
+const UART_LCR: usize = 0x03;
+
+// MCR (Modem Control Register).
+const UART_MCR: usize = 0x04;
+
+// LSR (Line Status Register).
+//
+// We poll LSR bits to implement blocking RX/TX without interrupts.
+const UART_LSR: usize = 0x05;
+
+// LCR value: 8 data bits, no parity, 1 stop bit (8N1).
+const LCR_8N1: u8 = 0x03;
+
+// LCR bit mask: DLAB (Divisor Latch Access Bit).
+const LCR_DLAB: u8 = 1 << 7;
+
+// FCR value: enable the FIFOs and reset both the RX and TX FIFOs.
+const FCR_ENABLE_RESET: u8 = 0x07;
+
+// MCR value: assert DTR and RTS, and OUT2 (which gates the IRQ line on most boards).
+const MCR_DTR_RTS_OUT2: u8 = 0x0b;
+
+// LSR bit mask: DR (Data Ready). When set, RHR has at least one byte to read.
+const LSR_DATA_READY: u8 = 1 << 0;
+
+// LSR bit mask: THRE (THR Empty). When set, THR can accept the next TX byte.
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+// LSR bit mask: TEMT (Transmitter Empty). When set, the last TX byte has fully
+// left the shift register.
+const LSR_TX_IDLE: u8 = 1 << 6;
+
+/// Baud rate programmed at boot.
+pub(crate) const DEFAULT_BAUD: u32 = 115_200;
+
+// How far the programmed rate may be from the one asked for.
+const MAX_BAUD_ERROR_PERCENT: u64 = 3;
+
+// -----------------------------------------------------------------------------
+// Driver
+// -----------------------------------------------------------------------------
+
+/// A 16550-compatible UART (QEMU virt, most PC-derived boards).
+pub(crate) struct Ns16550 {
+    base: AtomicUsize,
+    // log2 of the stride between registers (device tree `reg-shift`).
+    shift: AtomicU32,
+    // Input clock, from which the baud divisor is derived.
+    clock_hz: AtomicU32,
+    baud: AtomicU32,
+}
+
+impl Ns16550 {
+    pub(crate) const fn new(base: usize, clock_hz: u32) -> Self {
+        Self {
+            base: AtomicUsize::new(base),
+            shift: AtomicU32::new(0),
+            clock_hz: AtomicU32::new(clock_hz),
+            baud: AtomicU32::new(DEFAULT_BAUD),
+        }
+    }
+
+    /// Override the base, register shift and (if known) input clock.
+    pub(crate) fn configure(&self, base: usize, shift: u32, clock_hz: Option<u32>) {
+        self.base.store(base, Ordering::Relaxed);
+        self.shift.store(shift, Ordering::Relaxed);
+        if let Some(hz) = clock_hz {
+            self.clock_hz.store(hz, Ordering::Relaxed);
+        }
+    }
+
+    // Compute the divisor latch value for a baud rate, if it is representable.
+    fn divisor_for(&self, baud: u32) -> Option<u16> {
+        if baud == 0 {
+            return None;
+        }
+        let clock = u64::from(self.clock_hz.load(Ordering::Relaxed));
+        match clock / (16 * u64::from(baud)) {
+            0 => None,
+            d => u16::try_from(d).ok(),
+        }
+    }
+
+    // The baud rate a divisor latch value actually gives.
+    fn rate_for(&self, divisor: u16) -> u32 {
+        self.clock_hz.load(Ordering::Relaxed) / (16 * u32::from(divisor))
+    }
+
+    // Write the divisor latch and leave the line configured as 8N1.
+    fn program_divisor(&self, divisor: u16) {
+        self.write_reg(UART_LCR, LCR_8N1 | LCR_DLAB);
+        self.write_reg(UART_DLL, divisor as u8);
+        self.write_reg(UART_DLM, (divisor >> 8) as u8);
+        self.write_reg(UART_LCR, LCR_8N1);
+    }
+
+    // Byte address of a register.
+    fn reg(&self, index: usize) -> usize {
+        self.base.load(Ordering::Relaxed) + (index << self.shift.load(Ordering::Relaxed))
+    }
+
+    // Read a UART register.
+    fn read_reg(&self, index: usize) -> u8 {
+        // Volatile is required for MMIO: the compiler must not optimize this away.
+        unsafe { (self.reg(index) as *const u8).read_volatile() }
+    }
+
+    // Write a UART register.
+    fn write_reg(&self, index: usize, v: u8) {
+        unsafe { (self.reg(index) as *mut u8).write_volatile(v) }
+    }
+}
+
+impl Console for Ns16550 {
+    fn name(&self) -> &'static str {
+        "ns16550"
+    }
+
+    fn base(&self) -> Option<usize> {
+        Some(self.base.load(Ordering::Relaxed))
+    }
+
+    /// Initialize the UART: 8N1 at [`DEFAULT_BAUD`] (or as close as the clock
+    /// allows), FIFOs enabled, interrupts off.
+    ///
+    /// QEMU comes up usable without this, but real 16550-compatible parts do not.
+    fn init(&self) {
+        self.write_reg(UART_IER, 0);
+        let divisor = self.divisor_for(DEFAULT_BAUD).unwrap_or(1);
+        self.program_divisor(divisor);
+        self.baud.store(self.rate_for(divisor), Ordering::Relaxed);
+        self.write_reg(UART_FCR, FCR_ENABLE_RESET);
+        self.write_reg(UART_MCR, MCR_DTR_RTS_OUT2);
+    }
+
+    /// Write a single byte: spin until LSR indicates THR is empty, then write THR.
+    fn putc(&self, c: u8) {
+        while self.read_reg(UART_LSR) & LSR_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_reg(UART_RHR_THR, c);
+    }
+
+    /// Read RHR if LSR indicates a byte is ready.
+    fn try_getc(&self) -> Option<u8> {
+        if self.read_reg(UART_LSR) & LSR_DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read_reg(UART_RHR_THR))
+    }
+
+    /// Waits for pending output to drain first so it isn't garbled, and
+    /// records the rate the divisor actually gives.
+    fn set_baud(&self, baud: u32) -> bool {
+        let Some(divisor) = self.divisor_for(baud) else {
+            return false;
+        };
+        // Refuse rates the clock can only approximate badly; a few percent
+        // off is still within what a receiver tolerates.
+        let actual = self.rate_for(divisor);
+        if u64::from(actual.abs_diff(baud)) * 100 > u64::from(baud) * MAX_BAUD_ERROR_PERCENT {
+            return false;
+        }
+
+        while self.read_reg(UART_LSR) & LSR_TX_IDLE == 0 {
+            core::hint::spin_loop();
+        }
+
+        self.program_divisor(divisor);
+        self.baud.store(actual, Ordering::Relaxed);
+        true
+    }
+
+    fn baud(&self) -> Option<u32> {
+        Some(self.baud.load(Ordering::Relaxed))
+    }
+}
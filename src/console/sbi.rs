@@ -0,0 +1,59 @@
+use super::Console;
+use crate::sbi;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// -----------------------------------------------------------------------------
+// Driver
+// -----------------------------------------------------------------------------
+
+/// The SBI debug console, for running under firmware such as OpenSBI.
+///
+/// Uses the DBCN extension when the firmware has it, and the legacy
+/// putchar/getchar calls otherwise.
+pub(crate) struct SbiConsole {
+    dbcn: AtomicBool,
+    // Landing buffer for DBCN reads (passed to firmware by physical address).
+    rx: AtomicU8,
+}
+
+impl SbiConsole {
+    pub(crate) const fn new() -> Self {
+        Self {
+            dbcn: AtomicBool::new(false),
+            rx: AtomicU8::new(0),
+        }
+    }
+}
+
+impl Console for SbiConsole {
+    fn name(&self) -> &'static str {
+        "sbi"
+    }
+
+    fn init(&self) {
+        self.dbcn
+            .store(sbi::probe_extension(sbi::EID_DBCN), Ordering::Relaxed);
+    }
+
+    fn putc(&self, c: u8) {
+        match self.dbcn.load(Ordering::Relaxed) {
+            true => sbi::call(sbi::EID_DBCN, sbi::FID_DBCN_WRITE_BYTE, [c as usize, 0, 0]),
+            false => sbi::call(sbi::EID_LEGACY_PUTCHAR, 0, [c as usize, 0, 0]),
+        };
+    }
+
+    fn try_getc(&self) -> Option<u8> {
+        if !self.dbcn.load(Ordering::Relaxed) {
+            // Legacy calls return the byte (or -1) in a0, i.e. the error slot.
+            let ret = sbi::call(sbi::EID_LEGACY_GETCHAR, 0, [0; 3]);
+            return u8::try_from(ret.error).ok();
+        }
+
+        let buf = self.rx.as_ptr() as usize;
+        let ret = sbi::call(sbi::EID_DBCN, sbi::FID_DBCN_READ, [1, buf, 0]);
+        match (ret.error, ret.value) {
+            (0, 1) => Some(self.rx.load(Ordering::Relaxed)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use super::Console;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// SiFive UART register offsets and bitfields
+// -----------------------------------------------------------------------------
+
+// Offset (in bytes) of txdata. Bit 31 is set while the TX FIFO is full.
+const UART_TXDATA: usize = 0x00;
+
+// Offset (in bytes) of rxdata. Bit 31 is set while the RX FIFO is empty; a
+// read pops the FIFO, so the byte and the flag must come from a single read.
+const UART_RXDATA: usize = 0x04;
+
+// Offsets (in bytes) of txctrl and rxctrl. Bit 0 enables the direction.
+const UART_TXCTRL: usize = 0x08;
+const UART_RXCTRL: usize = 0x0c;
+
+// Offset (in bytes) of ie (interrupt enable).
+const UART_IE: usize = 0x10;
+
+// txdata/rxdata bit mask: FIFO full (TX) or empty (RX).
+const FIFO_FLAG: u32 = 1 << 31;
+
+// txctrl/rxctrl bit mask: enable.
+const CTRL_ENABLE: u32 = 1 << 0;
+
+// -----------------------------------------------------------------------------
+// Driver
+// -----------------------------------------------------------------------------
+
+/// The SiFive UART (QEMU sifive_u, HiFive boards).
+///
+/// The baud divisor is left as firmware programmed it: the input clock comes
+/// from the PRCI and is not known to the monitor.
+pub(crate) struct SifiveUart {
+    base: AtomicUsize,
+}
+
+impl SifiveUart {
+    pub(crate) const fn new(base: usize) -> Self {
+        Self {
+            base: AtomicUsize::new(base),
+        }
+    }
+
+    /// Override the MMIO base.
+    pub(crate) fn configure(&self, base: usize) {
+        self.base.store(base, Ordering::Relaxed);
+    }
+
+    // Pointer to a 32-bit register.
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base.load(Ordering::Relaxed) + offset) as *mut u32
+    }
+}
+
+impl Console for SifiveUart {
+    fn name(&self) -> &'static str {
+        "sifive-uart"
+    }
+
+    fn base(&self) -> Option<usize> {
+        Some(self.base.load(Ordering::Relaxed))
+    }
+
+    fn init(&self) {
+        unsafe {
+            self.reg(UART_IE).write_volatile(0);
+            self.reg(UART_TXCTRL).write_volatile(CTRL_ENABLE);
+            self.reg(UART_RXCTRL).write_volatile(CTRL_ENABLE);
+        }
+    }
+
+    fn putc(&self, c: u8) {
+        while unsafe { self.reg(UART_TXDATA).read_volatile() } & FIFO_FLAG != 0 {
+            core::hint::spin_loop();
+        }
+        unsafe { self.reg(UART_TXDATA).write_volatile(c as u32) }
+    }
+
+    fn try_getc(&self) -> Option<u8> {
+        let v = unsafe { self.reg(UART_RXDATA).read_volatile() };
+        match v & FIFO_FLAG {
+            0 => Some(v as u8),
+            _ => None,
+        }
+    }
+}
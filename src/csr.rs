@@ -0,0 +1,115 @@
+// -----------------------------------------------------------------------------
+// Privilege Mode
+// -----------------------------------------------------------------------------
+
+// riscmon runs in machine mode by default, or in supervisor mode under SBI
+// firmware (e.g. OpenSBI) with the `supervisor` feature. The trap CSRs have the
+// same layout in both modes, so only their numbers, a few bit positions and the
+// trap return instruction differ. Assembly takes the numbers as `const`
+// operands (`csrw {tvec}, t0`), which the assembler accepts in place of names.
+
+#[cfg(not(feature = "supervisor"))]
+mod mode {
+    pub(crate) const STATUS: usize = 0x300; // mstatus
+    pub(crate) const IE: usize = 0x304; // mie
+    pub(crate) const TVEC: usize = 0x305; // mtvec
+    pub(crate) const SCRATCH: usize = 0x340; // mscratch
+    pub(crate) const EPC: usize = 0x341; // mepc
+    pub(crate) const CAUSE: usize = 0x342; // mcause
+    pub(crate) const TVAL: usize = 0x343; // mtval
+
+    pub(crate) const STATUS_IE: usize = 1 << 3; // mstatus.MIE
+    pub(crate) const STATUS_PIE: usize = 1 << 7; // mstatus.MPIE
+    pub(crate) const STATUS_PP: usize = 3 << 11; // mstatus.MPP = M
+    pub(crate) const STATUS_PP_S: usize = 1 << 11; // mstatus.MPP = S
+    pub(crate) const STATUS_PP_U: usize = 0; // mstatus.MPP = U
+
+    pub(crate) const IRQ_TIMER: usize = 7; // machine timer interrupt
+    pub(crate) const IRQ_EXTERNAL: usize = 11; // machine external interrupt
+
+    /// Name of the mode the monitor runs in.
+    pub(crate) const NAME: &str = "machine";
+}
+
+#[cfg(feature = "supervisor")]
+mod mode {
+    pub(crate) const STATUS: usize = 0x100; // sstatus
+    pub(crate) const IE: usize = 0x104; // sie
+    pub(crate) const TVEC: usize = 0x105; // stvec
+    pub(crate) const SCRATCH: usize = 0x140; // sscratch
+    pub(crate) const EPC: usize = 0x141; // sepc
+    pub(crate) const CAUSE: usize = 0x142; // scause
+    pub(crate) const TVAL: usize = 0x143; // stval
+
+    pub(crate) const STATUS_IE: usize = 1 << 1; // sstatus.SIE
+    pub(crate) const STATUS_PIE: usize = 1 << 5; // sstatus.SPIE
+    pub(crate) const STATUS_PP: usize = 1 << 8; // sstatus.SPP = S
+
+    pub(crate) const IRQ_TIMER: usize = 5; // supervisor timer interrupt
+    pub(crate) const IRQ_EXTERNAL: usize = 9; // supervisor external interrupt
+
+    /// Name of the mode the monitor runs in.
+    pub(crate) const NAME: &str = "supervisor";
+}
+
+pub(crate) use mode::*;
+
+/// Interrupt flag of the cause register.
+pub(crate) const CAUSE_INTERRUPT: usize = 1 << (usize::BITS - 1);
+
+/// The trap return instruction for the monitor's privilege mode.
+#[cfg(not(feature = "supervisor"))]
+macro_rules! xret {
+    () => {
+        "mret"
+    };
+}
+
+/// The trap return instruction for the monitor's privilege mode.
+#[cfg(feature = "supervisor")]
+macro_rules! xret {
+    () => {
+        "sret"
+    };
+}
+
+pub(crate) use xret;
+
+// -----------------------------------------------------------------------------
+// CSR Access
+// -----------------------------------------------------------------------------
+
+/// Read a CSR by number.
+macro_rules! read {
+    ($csr:expr) => {{
+        let v: usize;
+        unsafe { core::arch::asm!("csrr {0}, {csr}", out(reg) v, csr = const $csr) };
+        v
+    }};
+}
+
+/// Write a CSR by number.
+macro_rules! write {
+    ($csr:expr, $v:expr) => {{
+        let v: usize = $v;
+        unsafe { core::arch::asm!("csrw {csr}, {0}", in(reg) v, csr = const $csr) };
+    }};
+}
+
+/// Set bits in a CSR by number.
+macro_rules! set {
+    ($csr:expr, $bits:expr) => {{
+        let bits: usize = $bits;
+        unsafe { core::arch::asm!("csrs {csr}, {0}", in(reg) bits, csr = const $csr) };
+    }};
+}
+
+/// Clear bits in a CSR by number.
+macro_rules! clear {
+    ($csr:expr, $bits:expr) => {{
+        let bits: usize = $bits;
+        unsafe { core::arch::asm!("csrc {csr}, {0}", in(reg) bits, csr = const $csr) };
+    }};
+}
+
+pub(crate) use {clear, read, set, write};
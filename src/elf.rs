@@ -0,0 +1,160 @@
+// -----------------------------------------------------------------------------
+// ELF Symbol Tables
+// -----------------------------------------------------------------------------
+
+// Just enough ELF64 (little-endian) to read the symbol table of an image that
+// has been loaded into memory as a file: the section headers, the SYMTAB
+// section and the string table it links to. Every offset is checked against
+// the image, so a truncated or corrupt file yields an error, not a fault.
+
+const MAGIC: &[u8; 4] = b"\x7fELF";
+const CLASS_64: u8 = 2;
+const DATA_LE: u8 = 1;
+
+// ELF header field offsets.
+const E_SHOFF: usize = 0x28;
+const E_SHENTSIZE: usize = 0x3a;
+const E_SHNUM: usize = 0x3c;
+
+// Section header field offsets.
+const SH_TYPE: usize = 0x04;
+const SH_OFFSET: usize = 0x18;
+const SH_SIZE: usize = 0x20;
+const SH_LINK: usize = 0x28;
+const SHDR_SIZE: usize = 0x40;
+const SHT_SYMTAB: u32 = 2;
+
+// Symbol entry layout.
+const SYM_SIZE: usize = 24;
+const ST_NAME: usize = 0x00;
+const ST_INFO: usize = 0x04;
+const ST_SHNDX: usize = 0x06;
+const ST_VALUE: usize = 0x08;
+const ST_SIZE: usize = 0x10;
+
+// Symbol types worth keeping: NOTYPE (plain labels), OBJECT and FUNC. SECTION
+// and FILE symbols, and anything else, are skipped.
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+// Section indexes of symbols that name no address in the image: undefined
+// ones, and absolute values such as linker-script constants.
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+/// A defined symbol.
+#[derive(Copy, Clone)]
+pub(crate) struct Symbol<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) value: u64,
+    /// Size in bytes (0 = unknown).
+    pub(crate) size: u64,
+}
+
+/// Whether `image` starts like an ELF file.
+pub(crate) fn is_elf(image: &[u8]) -> bool {
+    image.starts_with(MAGIC)
+}
+
+/// Call `f` with each defined function, object and label in the image's
+/// symbol table, skipping absolute symbols, compiler-generated local labels
+/// (`.L*`) and mapping symbols (`$x`, `$d`). Returns how many were visited.
+pub(crate) fn for_each_symbol(
+    image: &[u8],
+    mut f: impl FnMut(Symbol<'_>) -> Result<(), &'static str>,
+) -> Result<usize, &'static str> {
+    if !is_elf(image) {
+        return Err("not an ELF file");
+    }
+    if image.get(4) != Some(&CLASS_64) || image.get(5) != Some(&DATA_LE) {
+        return Err("not a 64-bit little-endian ELF file");
+    }
+
+    let shoff = read_u64(image, E_SHOFF)? as usize;
+    let shentsize = read_u16(image, E_SHENTSIZE)? as usize;
+    let shnum = read_u16(image, E_SHNUM)? as usize;
+    if shentsize < SHDR_SIZE {
+        return Err("bad section header size");
+    }
+    let shdr = |i: usize| -> Result<&[u8], &'static str> {
+        let start = i
+            .checked_mul(shentsize)
+            .and_then(|o| o.checked_add(shoff))
+            .ok_or("truncated ELF file")?;
+        slice(image, start, SHDR_SIZE)
+    };
+
+    let symtab = (0..shnum)
+        .map(shdr)
+        .find(|h| h.is_ok_and(|h| read_u32(h, SH_TYPE) == Ok(SHT_SYMTAB)))
+        .ok_or("no symbol table (stripped?)")??;
+    let strtab = shdr(read_u32(symtab, SH_LINK)? as usize)?;
+    let syms = section(image, symtab)?;
+    let strs = section(image, strtab)?;
+
+    let mut count = 0;
+    for sym in syms.chunks_exact(SYM_SIZE) {
+        let kind = sym[ST_INFO] & 0xf;
+        if !matches!(kind, STT_NOTYPE | STT_OBJECT | STT_FUNC)
+            || matches!(read_u16(sym, ST_SHNDX)?, SHN_UNDEF | SHN_ABS)
+        {
+            continue;
+        }
+        let name = name_at(strs, read_u32(sym, ST_NAME)? as usize)?;
+        if name.is_empty() || name.starts_with(".L") || name.starts_with('$') {
+            continue;
+        }
+        f(Symbol {
+            name,
+            value: read_u64(sym, ST_VALUE)?,
+            size: read_u64(sym, ST_SIZE)?,
+        })?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The contents of the section described by header `h`.
+fn section<'a>(image: &'a [u8], h: &[u8]) -> Result<&'a [u8], &'static str> {
+    slice(
+        image,
+        read_u64(h, SH_OFFSET)? as usize,
+        read_u64(h, SH_SIZE)? as usize,
+    )
+}
+
+// The NUL-terminated string at `off` in a string table.
+fn name_at(strs: &[u8], off: usize) -> Result<&str, &'static str> {
+    let rest = strs.get(off..).ok_or("truncated ELF file")?;
+    let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+    core::str::from_utf8(&rest[..len]).map_err(|_| "symbol name is not UTF-8")
+}
+
+fn slice(image: &[u8], start: usize, len: usize) -> Result<&[u8], &'static str> {
+    start
+        .checked_add(len)
+        .and_then(|end| image.get(start..end))
+        .ok_or("truncated ELF file")
+}
+
+fn read_u16(b: &[u8], off: usize) -> Result<u16, &'static str> {
+    let b = slice(b, off, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn read_u32(b: &[u8], off: usize) -> Result<u32, &'static str> {
+    let b = slice(b, off, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn read_u64(b: &[u8], off: usize) -> Result<u64, &'static str> {
+    let b = slice(b, off, 8)?;
+    let mut v = [0u8; 8];
+    v.copy_from_slice(b);
+    Ok(u64::from_le_bytes(v))
+}
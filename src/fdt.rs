@@ -0,0 +1,350 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// Flattened Device Tree (FDT)
+// -----------------------------------------------------------------------------
+
+// Magic number at the start of every device tree blob.
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+// Structure block tokens.
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+// Deepest node nesting the walker tracks.
+const MAX_DEPTH: usize = 16;
+
+// Address of the blob handed over by the previous boot stage (0 = none).
+static BLOB: AtomicUsize = AtomicUsize::new(0);
+
+/// Record the device tree passed in a1 at boot, if it looks valid.
+pub(crate) fn init(addr: usize) {
+    if Fdt::from_addr(addr).is_some() {
+        BLOB.store(addr, Ordering::Relaxed);
+    }
+}
+
+/// The device tree passed at boot, if any.
+pub(crate) fn get() -> Option<Fdt> {
+    Fdt::from_addr(BLOB.load(Ordering::Relaxed))
+}
+
+/// A validated, read-only view of a device tree blob.
+#[derive(Copy, Clone)]
+pub(crate) struct Fdt {
+    blob: &'static [u8],
+    structs: usize,
+    strings: usize,
+}
+
+/// A node located in the structure block.
+///
+/// `addr_cells`/`size_cells` are the parent's `#address-cells` and
+/// `#size-cells`, which govern how this node's `reg` is encoded.
+#[derive(Copy, Clone)]
+pub(crate) struct Node {
+    pub(crate) name: &'static str,
+    off: usize,
+    addr_cells: u32,
+    size_cells: u32,
+}
+
+impl Node {
+    /// The parent's `#address-cells`, which also sizes the parent side of
+    /// this node's `ranges`.
+    pub(crate) fn parent_address_cells(&self) -> u32 {
+        self.addr_cells
+    }
+}
+
+// A single structure block token.
+enum Token {
+    Begin(&'static str),
+    End,
+    Prop(&'static str, &'static [u8]),
+    Nop,
+    Done,
+}
+
+impl Fdt {
+    /// Validate the header of the blob at `addr`.
+    pub(crate) fn from_addr(addr: usize) -> Option<Fdt> {
+        if addr == 0 || !addr.is_multiple_of(4) {
+            return None;
+        }
+
+        let header = unsafe { core::slice::from_raw_parts(addr as *const u8, 40) };
+        if be32(header, 0)? != FDT_MAGIC {
+            return None;
+        }
+
+        let total = be32(header, 4)? as usize;
+        let blob = unsafe { core::slice::from_raw_parts(addr as *const u8, total) };
+        let structs = be32(blob, 8)? as usize;
+        let strings = be32(blob, 12)? as usize;
+        if structs >= total || strings >= total {
+            return None;
+        }
+
+        Some(Fdt {
+            blob,
+            structs,
+            strings,
+        })
+    }
+
+    /// Address of the blob in memory.
+    pub(crate) fn addr(&self) -> usize {
+        self.blob.as_ptr() as usize
+    }
+
+    /// Total size of the blob in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.blob.len()
+    }
+
+    /// Find a node by absolute path (e.g. `/chosen` or `/soc/serial@10000000`).
+    ///
+    /// A path component without a unit address matches any node of that name.
+    pub(crate) fn find_node(&self, path: &str) -> Option<Node> {
+        let mut comps = [""; MAX_DEPTH];
+        let mut n = 0;
+        for c in path.split('/').filter(|c| !c.is_empty()) {
+            if n == MAX_DEPTH - 1 {
+                return None;
+            }
+            comps[n] = c;
+            n += 1;
+        }
+
+        let mut matched = 0;
+        self.walk(|depth, node| {
+            // Only direct children of the deepest matched node can match.
+            if depth == 0 {
+                return (n == 0).then_some(node);
+            }
+            if depth > matched + 1 {
+                return None;
+            }
+            matched = depth - 1;
+            if !name_matches(node.name, comps[matched]) {
+                return None;
+            }
+            matched += 1;
+            (matched == n).then_some(node)
+        })
+    }
+
+    /// Find the first node whose `compatible` list contains `compat`.
+    #[cfg_attr(feature = "supervisor", allow(dead_code))]
+    pub(crate) fn find_compatible(&self, compat: &str) -> Option<Node> {
+        self.walk(|_, node| self.is_compatible(node, compat).then_some(node))
+    }
+
+    /// Visit the nodes whose `compatible` list contains `compat`, in document
+    /// order, until `f` returns a value.
+    pub(crate) fn search_compatible<T>(
+        &self,
+        compat: &str,
+        mut f: impl FnMut(Node) -> Option<T>,
+    ) -> Option<T> {
+        self.walk(|_, node| match self.is_compatible(node, compat) {
+            true => f(node),
+            false => None,
+        })
+    }
+
+    /// Find the node a phandle (e.g. a `regmap` property) refers to.
+    #[cfg_attr(feature = "supervisor", allow(dead_code))]
+    pub(crate) fn find_phandle(&self, phandle: u32) -> Option<Node> {
+        self.walk(|_, node| {
+            let p = self
+                .property_u32(node, "phandle")
+                .or_else(|| self.property_u32(node, "linux,phandle"));
+            (p == Some(phandle)).then_some(node)
+        })
+    }
+
+    /// Whether `node`'s `compatible` list contains `compat`.
+    pub(crate) fn is_compatible(&self, node: Node, compat: &str) -> bool {
+        match self.property(node, "compatible") {
+            Some(v) => strings(v).any(|s| s == compat),
+            None => false,
+        }
+    }
+
+    /// Raw value of one of `node`'s properties.
+    pub(crate) fn property(&self, node: Node, name: &str) -> Option<&'static [u8]> {
+        let mut off = node.off;
+        loop {
+            let (token, next) = self.token(off)?;
+            match token {
+                Token::Prop(pname, value) if pname == name => return Some(value),
+                Token::Prop(..) | Token::Nop => off = next,
+                _ => return None,
+            }
+        }
+    }
+
+    /// A property holding a single 32-bit cell.
+    pub(crate) fn property_u32(&self, node: Node, name: &str) -> Option<u32> {
+        be32(self.property(node, name)?, 0)
+    }
+
+    /// A property holding a NUL-terminated string.
+    pub(crate) fn property_str(&self, node: Node, name: &str) -> Option<&'static str> {
+        strings(self.property(node, name)?).next()
+    }
+
+    /// The `index`th (address, size) pair of `node`'s `reg` property.
+    pub(crate) fn reg(&self, node: Node, index: usize) -> Option<(u64, u64)> {
+        let reg = self.property(node, "reg")?;
+        let cells = (node.addr_cells + node.size_cells) as usize;
+        let entry = reg.get(index * cells * 4..(index + 1) * cells * 4)?;
+        let addr = cells_value(entry, 0, node.addr_cells)?;
+        let size = cells_value(entry, node.addr_cells as usize * 4, node.size_cells)?;
+        Some((addr, size))
+    }
+
+    /// Resolve `/chosen/stdout-path` (following an alias if needed).
+    pub(crate) fn stdout(&self) -> Option<Node> {
+        let chosen = self.find_node("/chosen")?;
+        let path = self.property_str(chosen, "stdout-path")?;
+        // Options such as ":115200n8" follow the path.
+        let path = path.split(':').next()?;
+        match path.starts_with('/') {
+            true => self.find_node(path),
+            false => {
+                let aliases = self.find_node("/aliases")?;
+                self.find_node(self.property_str(aliases, path)?)
+            }
+        }
+    }
+
+    // Visit every node in document order with its depth (root = 0) until `f`
+    // returns a value.
+    fn walk<T>(&self, mut f: impl FnMut(usize, Node) -> Option<T>) -> Option<T> {
+        // #address-cells / #size-cells of each open node (defaults per spec).
+        let mut addr_cells = [2u32; MAX_DEPTH];
+        let mut size_cells = [1u32; MAX_DEPTH];
+        let mut depth = 0usize;
+        // Offset of the node whose properties are being read, not yet visited.
+        let mut pending: Option<Node> = None;
+
+        let mut off = self.structs;
+        loop {
+            let (token, next) = self.token(off)?;
+            match token {
+                Token::Begin(name) => {
+                    // The previous node's properties are complete.
+                    if let Some(node) = pending.take()
+                        && let Some(v) = f(depth - 1, node)
+                    {
+                        return Some(v);
+                    }
+                    if depth == MAX_DEPTH {
+                        return None;
+                    }
+                    let parent = depth.saturating_sub(1);
+                    pending = Some(Node {
+                        name,
+                        off: next,
+                        addr_cells: addr_cells[parent],
+                        size_cells: size_cells[parent],
+                    });
+                    addr_cells[depth] = 2;
+                    size_cells[depth] = 1;
+                    depth += 1;
+                }
+                Token::End => {
+                    if let Some(node) = pending.take()
+                        && let Some(v) = f(depth - 1, node)
+                    {
+                        return Some(v);
+                    }
+                    depth = depth.checked_sub(1)?;
+                }
+                Token::Prop(name, value) => {
+                    if depth > 0 {
+                        match name {
+                            "#address-cells" => addr_cells[depth - 1] = be32(value, 0)?,
+                            "#size-cells" => size_cells[depth - 1] = be32(value, 0)?,
+                            _ => {}
+                        }
+                    }
+                }
+                Token::Nop => {}
+                Token::Done => return None,
+            }
+            off = next;
+        }
+    }
+
+    // Decode the token at `off`, returning it and the offset of the next one.
+    fn token(&self, off: usize) -> Option<(Token, usize)> {
+        match be32(self.blob, off)? {
+            FDT_BEGIN_NODE => {
+                let name = cstr(self.blob, off + 4)?;
+                Some((Token::Begin(name), align4(off + 4 + name.len() + 1)))
+            }
+            FDT_END_NODE => Some((Token::End, off + 4)),
+            FDT_PROP => {
+                let len = be32(self.blob, off + 4)? as usize;
+                let nameoff = be32(self.blob, off + 8)? as usize;
+                let name = cstr(self.blob, self.strings + nameoff)?;
+                let value = self.blob.get(off + 12..off + 12 + len)?;
+                Some((Token::Prop(name, value), align4(off + 12 + len)))
+            }
+            FDT_NOP => Some((Token::Nop, off + 4)),
+            FDT_END => Some((Token::Done, off + 4)),
+            _ => None,
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Read a big-endian u32 at `off`.
+fn be32(b: &[u8], off: usize) -> Option<u32> {
+    let bytes = b.get(off..off + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// Combine `cells` big-endian 32-bit cells starting at `off` into one value.
+fn cells_value(b: &[u8], off: usize, cells: u32) -> Option<u64> {
+    let mut v = 0u64;
+    for i in 0..cells as usize {
+        v = (v << 32) | be32(b, off + i * 4)? as u64;
+    }
+    Some(v)
+}
+
+// Read a NUL-terminated string at `off`.
+fn cstr(b: &'static [u8], off: usize) -> Option<&'static str> {
+    let rest = b.get(off..)?;
+    let len = rest.iter().position(|&c| c == 0)?;
+    core::str::from_utf8(&rest[..len]).ok()
+}
+
+// Iterate the NUL-separated strings of a string-list property.
+fn strings(v: &'static [u8]) -> impl Iterator<Item = &'static str> {
+    v.split(|&c| c == 0)
+        .filter(|s| !s.is_empty())
+        .filter_map(|s| core::str::from_utf8(s).ok())
+}
+
+// Whether a node name matches a path component, ignoring the unit address
+// when the component has none.
+fn name_matches(name: &str, comp: &str) -> bool {
+    name == comp || (!comp.contains('@') && name.split('@').next() == Some(comp))
+}
+
+// Round up to the next multiple of 4.
+fn align4(v: usize) -> usize {
+    (v + 3) & !3
+}
@@ -0,0 +1,263 @@
+use crate::{csr, memory, pmp, sbi, system, trap::TrapFrame, uart};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+// -----------------------------------------------------------------------------
+// Minimal SBI Firmware
+// -----------------------------------------------------------------------------
+
+// When riscmon runs in machine mode it can boot a supervisor-mode payload
+// (`boot ADDR [DTB]`) and act as its SBI implementation: traps are delegated to
+// the payload, and its ecalls land in riscmon's trap handler, which services
+// them here.
+
+// Machine-mode CSRs used to hand the hart over to supervisor mode.
+const CSR_MEDELEG: usize = 0x302;
+const CSR_MIDELEG: usize = 0x303;
+const CSR_MCOUNTEREN: usize = 0x306;
+const CSR_MIP: usize = 0x344;
+const CSR_MVENDORID: usize = 0xf11;
+const CSR_MARCHID: usize = 0xf12;
+const CSR_MIMPID: usize = 0xf13;
+
+// Exceptions the payload handles itself: misaligned fetch, illegal instruction,
+// breakpoint, ecall from U-mode and page faults. Access faults and ecalls from
+// S-mode stay with riscmon.
+const DELEGATED_EXCEPTIONS: usize =
+    (1 << 0) | (1 << 2) | (1 << 3) | (1 << 8) | (1 << 12) | (1 << 13) | (1 << 15);
+
+// mip/mideleg bits: supervisor software, timer and external interrupts.
+const MIP_SSIP: usize = 1 << 1;
+const MIP_STIP: usize = 1 << 5;
+const MIP_SEIP: usize = 1 << 9;
+
+// mcounteren: let supervisor mode read cycle, time and instret.
+const COUNTEREN_CY_TM_IR: usize = 0x7;
+
+// Reported SBI specification version (v2.0) and implementation.
+const SPEC_VERSION: usize = 2 << 24;
+const IMPL_ID: usize = 0x7269_7363; // "risc", unregistered
+const IMPL_VERSION: usize = 1;
+
+// Extensions implemented below.
+const EXTENSIONS: [usize; 7] = [
+    sbi::EID_BASE,
+    sbi::EID_TIME,
+    sbi::EID_IPI,
+    sbi::EID_RFENCE,
+    sbi::EID_SRST,
+    sbi::EID_DBCN,
+    sbi::EID_LEGACY_PUTCHAR,
+];
+
+// The payload's next timer event (u64::MAX = none).
+static TIMER_DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// What the trap handler should do after an SBI call.
+pub(crate) enum Ecall {
+    /// Return to the payload.
+    Resume,
+    /// The payload asked for a reboot; return to the monitor.
+    Reboot,
+}
+
+// -----------------------------------------------------------------------------
+// Hart Setup
+// -----------------------------------------------------------------------------
+
+/// Delegate traps and open up memory for a supervisor-mode payload.
+pub(crate) fn prepare() {
+    csr::write!(CSR_MEDELEG, DELEGATED_EXCEPTIONS);
+    csr::write!(CSR_MIDELEG, MIP_SSIP | MIP_STIP | MIP_SEIP);
+    csr::write!(CSR_MCOUNTEREN, COUNTEREN_CY_TM_IR);
+
+    // Entry 0 hides riscmon itself from the payload; entry 1 opens the rest.
+    pmp::set_napot(0, memory::RAM_BASE, memory::MONITOR_SIZE, 0);
+    pmp::set_all(1, pmp::RWX);
+}
+
+/// Undo `prepare` once control is back in the monitor.
+///
+/// The PMP entries and satp are left alone: they only affect lower privilege
+/// modes, and a payload stopped with Ctrl+C needs them when it is resumed.
+pub(crate) fn teardown() {
+    csr::write!(CSR_MEDELEG, 0);
+    csr::write!(CSR_MIDELEG, 0);
+    csr::clear!(CSR_MIP, MIP_SSIP | MIP_STIP);
+}
+
+// -----------------------------------------------------------------------------
+// Timer
+// -----------------------------------------------------------------------------
+
+/// The payload's pending timer event, if any.
+pub(crate) fn timer_deadline() -> Option<u64> {
+    match TIMER_DEADLINE.load(Ordering::Relaxed) {
+        u64::MAX => None,
+        deadline => Some(deadline),
+    }
+}
+
+/// Forward an expired payload timer event as a supervisor timer interrupt.
+pub(crate) fn timer_tick(now: u64) {
+    if now >= TIMER_DEADLINE.load(Ordering::Relaxed) {
+        TIMER_DEADLINE.store(u64::MAX, Ordering::Relaxed);
+        csr::set!(CSR_MIP, MIP_STIP);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// SBI Calls
+// -----------------------------------------------------------------------------
+
+/// Service an ecall from the payload, writing the result into its registers
+/// and stepping past the `ecall`.
+pub(crate) fn handle_ecall(frame: &mut TrapFrame) -> Ecall {
+    let eid = frame.regs[17];
+    let fid = frame.regs[16];
+    let args = [frame.regs[10], frame.regs[11], frame.regs[12]];
+    frame.pc += 4;
+
+    // Legacy calls return a single value in a0 and leave a1 alone.
+    let legacy = match eid {
+        sbi::EID_LEGACY_SET_TIMER => Some(set_timer(args[0] as u64)),
+        sbi::EID_LEGACY_PUTCHAR => {
+            uart::putc(args[0] as u8);
+            Some(0)
+        }
+        sbi::EID_LEGACY_GETCHAR => Some(uart::try_getc().map_or(usize::MAX, |b| b as usize)),
+        sbi::EID_LEGACY_CLEAR_IPI => {
+            csr::clear!(CSR_MIP, MIP_SSIP);
+            Some(0)
+        }
+        sbi::EID_LEGACY_SEND_IPI => {
+            // The only running hart is this one.
+            csr::set!(CSR_MIP, MIP_SSIP);
+            Some(0)
+        }
+        sbi::EID_LEGACY_REMOTE_FENCE_I
+        | sbi::EID_LEGACY_REMOTE_SFENCE_VMA
+        | sbi::EID_LEGACY_REMOTE_SFENCE_VMA_ASID => {
+            Some(local_fence(eid == sbi::EID_LEGACY_REMOTE_FENCE_I))
+        }
+        sbi::EID_LEGACY_SHUTDOWN => system::poweroff(),
+        _ => None,
+    };
+    if let Some(v) = legacy {
+        frame.regs[10] = v;
+        return Ecall::Resume;
+    }
+
+    let (error, value) = match (eid, fid) {
+        (sbi::EID_BASE, _) => base(fid, args[0]),
+        (sbi::EID_TIME, sbi::FID_SET_TIMER) => (sbi::SUCCESS, set_timer(args[0] as u64)),
+        (sbi::EID_IPI, sbi::FID_SEND_IPI) => send_ipi(args[0], args[1]),
+        // Functions 0..2 are fence.i, sfence.vma and sfence.vma with an ASID.
+        (sbi::EID_RFENCE, 0..=2) => (sbi::SUCCESS, local_fence(fid == 0)),
+        (sbi::EID_SRST, sbi::FID_SYSTEM_RESET) => match args[0] {
+            sbi::RESET_SHUTDOWN => system::poweroff(),
+            sbi::RESET_COLD_REBOOT | sbi::RESET_WARM_REBOOT => return Ecall::Reboot,
+            _ => (sbi::ERR_INVALID_PARAM, 0),
+        },
+        (sbi::EID_DBCN, sbi::FID_DBCN_WRITE) => console_write(args[0], args[1]),
+        (sbi::EID_DBCN, sbi::FID_DBCN_READ) => console_read(args[0], args[1]),
+        (sbi::EID_DBCN, sbi::FID_DBCN_WRITE_BYTE) => {
+            uart::putc(args[0] as u8);
+            (sbi::SUCCESS, 0)
+        }
+        _ => (sbi::ERR_NOT_SUPPORTED, 0),
+    };
+
+    frame.regs[10] = error as usize;
+    frame.regs[11] = value;
+    Ecall::Resume
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Base extension.
+fn base(fid: usize, arg: usize) -> (isize, usize) {
+    let value = match fid {
+        sbi::FID_GET_SPEC_VERSION => SPEC_VERSION,
+        sbi::FID_GET_IMPL_ID => IMPL_ID,
+        sbi::FID_GET_IMPL_VERSION => IMPL_VERSION,
+        sbi::FID_PROBE_EXTENSION => EXTENSIONS.contains(&arg) as usize,
+        sbi::FID_GET_MVENDORID => csr::read!(CSR_MVENDORID),
+        sbi::FID_GET_MARCHID => csr::read!(CSR_MARCHID),
+        sbi::FID_GET_MIMPID => csr::read!(CSR_MIMPID),
+        _ => return (sbi::ERR_NOT_SUPPORTED, 0),
+    };
+    (sbi::SUCCESS, value)
+}
+
+// Program the payload's next timer event and clear any pending one.
+fn set_timer(deadline: u64) -> usize {
+    csr::clear!(CSR_MIP, MIP_STIP);
+    TIMER_DEADLINE.store(deadline, Ordering::Relaxed);
+    0
+}
+
+// Raise a supervisor software interrupt if the mask includes this hart.
+fn send_ipi(mask: usize, mask_base: usize) -> (isize, usize) {
+    let hart = system::boot_hart();
+    let targeted = match mask_base {
+        usize::MAX => true,
+        base => {
+            hart >= base && hart - base < usize::BITS as usize && mask >> (hart - base) & 1 != 0
+        }
+    };
+    if targeted {
+        csr::set!(CSR_MIP, MIP_SSIP);
+    }
+    (sbi::SUCCESS, 0)
+}
+
+// Remote fences reduce to local ones with a single running hart.
+fn local_fence(fence_i: bool) -> usize {
+    match fence_i {
+        true => unsafe { core::arch::asm!("fence.i") },
+        false => unsafe { core::arch::asm!("sfence.vma") },
+    }
+    0
+}
+
+// DBCN write: print `len` bytes from physical address `addr`.
+fn console_write(len: usize, addr: usize) -> (isize, usize) {
+    let Some(bytes) = payload_buffer(addr, len) else {
+        return (sbi::ERR_INVALID_PARAM, 0);
+    };
+    for b in bytes.iter() {
+        uart::putc(*b);
+    }
+    (sbi::SUCCESS, len)
+}
+
+// DBCN read: copy up to `len` pending input bytes to physical address `addr`.
+fn console_read(len: usize, addr: usize) -> (isize, usize) {
+    let Some(bytes) = payload_buffer(addr, len) else {
+        return (sbi::ERR_INVALID_PARAM, 0);
+    };
+    let mut n = 0;
+    while n < len {
+        let Some(b) = uart::try_getc() else {
+            break;
+        };
+        bytes[n] = b;
+        n += 1;
+    }
+    (sbi::SUCCESS, n)
+}
+
+// Validate a payload buffer: it must lie in RAM outside the monitor region.
+fn payload_buffer(addr: usize, len: usize) -> Option<&'static mut [u8]> {
+    let end = addr.checked_add(len.checked_sub(1)?)?;
+    let monitor_end = memory::RAM_BASE + memory::MONITOR_SIZE - 1;
+    if !memory::is_in_ram(addr)
+        || !memory::is_in_ram(end)
+        || memory::ranges_overlap(addr, end, memory::RAM_BASE, monitor_end)
+    {
+        return None;
+    }
+    Some(unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) })
+}
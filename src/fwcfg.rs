@@ -0,0 +1,201 @@
+use crate::fdt;
+use core::sync::atomic::{Ordering, fence};
+
+// -----------------------------------------------------------------------------
+// QEMU Firmware Configuration (fw_cfg)
+// -----------------------------------------------------------------------------
+
+// fw_cfg is a set of items selected by a 16-bit key: a few well-known ones
+// (signature, feature bits, the `-kernel`/`-initrd` images) and a directory of
+// named files (`-fw_cfg name=opt/...,file=...`). On `virt` it is memory-mapped:
+// data at +0, selector at +8 (big-endian) and the DMA address at +16.
+
+// QEMU virt's fw_cfg, when the device tree does not say.
+const DEFAULT_BASE: usize = 0x1010_0000;
+
+// Register offsets.
+const REG_DATA: usize = 0x00;
+const REG_SELECTOR: usize = 0x08;
+const REG_DMA: usize = 0x10;
+
+// Well-known keys.
+const KEY_SIGNATURE: u16 = 0x0000;
+const KEY_ID: u16 = 0x0001;
+const KEY_FILE_DIR: u16 = 0x0019;
+
+// Contents of the signature item.
+const SIGNATURE: [u8; 4] = *b"QEMU";
+
+// Feature bit (in the ID item) for the DMA interface.
+const ID_DMA: u32 = 1 << 1;
+
+// DMA control bits.
+const DMA_ERROR: u32 = 1 << 0;
+const DMA_READ: u32 = 1 << 1;
+const DMA_SELECT: u32 = 1 << 3;
+
+// Bytes of a file directory entry, and of the name within it.
+const DIR_ENTRY_SIZE: usize = 64;
+const NAME_SIZE: usize = 56;
+
+// Legacy items that carry images from the QEMU command line: name, size key
+// and data key. Their sizes are little-endian, unlike the file directory.
+const LEGACY_ITEMS: [(&str, u16, u16); 3] = [
+    ("kernel", 0x0008, 0x0011),
+    ("initrd", 0x000b, 0x0012),
+    ("cmdline", 0x0014, 0x0015),
+];
+
+// A DMA request, read by the device (all fields big-endian).
+#[repr(C)]
+struct DmaAccess {
+    control: u32,
+    length: u32,
+    address: u64,
+}
+
+/// An item that can be loaded: a file directory entry, or one of the images
+/// passed with `-kernel`, `-initrd` or `-append`.
+#[derive(Copy, Clone)]
+pub(crate) struct File {
+    name: [u8; NAME_SIZE],
+    /// Size in bytes.
+    pub(crate) size: u32,
+    // Selector key.
+    key: u16,
+}
+
+impl File {
+    /// The item's name.
+    pub(crate) fn name(&self) -> &str {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(NAME_SIZE);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// A fw_cfg device.
+#[derive(Copy, Clone)]
+pub(crate) struct FwCfg {
+    /// MMIO base address.
+    pub(crate) base: usize,
+    /// Whether the DMA interface is available.
+    pub(crate) dma: bool,
+}
+
+impl FwCfg {
+    /// Find the device in the device tree, or at QEMU virt's address if there
+    /// is no device tree.
+    pub(crate) fn find() -> Option<FwCfg> {
+        let base = match fdt::get() {
+            Some(fdt) => fdt.reg(fdt.find_compatible("qemu,fw-cfg-mmio")?, 0)?.0 as usize,
+            None => DEFAULT_BASE,
+        };
+
+        let mut fw = FwCfg { base, dma: false };
+        let mut sig = [0u8; 4];
+        fw.select(KEY_SIGNATURE);
+        fw.read_bytes(&mut sig);
+        if sig != SIGNATURE {
+            return None;
+        }
+
+        let mut id = [0u8; 4];
+        fw.select(KEY_ID);
+        fw.read_bytes(&mut id);
+        fw.dma = u32::from_le_bytes(id) & ID_DMA != 0;
+        Some(fw)
+    }
+
+    /// Visit every loadable item: the legacy images QEMU was given, then the
+    /// file directory.
+    pub(crate) fn for_each_file(&self, mut f: impl FnMut(&File)) {
+        for (name, size_key, key) in LEGACY_ITEMS {
+            let mut size = [0u8; 4];
+            self.select(size_key);
+            self.read_bytes(&mut size);
+            let size = u32::from_le_bytes(size);
+            if size != 0 {
+                let mut file = File {
+                    name: [0; NAME_SIZE],
+                    size,
+                    key,
+                };
+                file.name[..name.len()].copy_from_slice(name.as_bytes());
+                f(&file);
+            }
+        }
+
+        // The directory is a big-endian count followed by the entries, and is
+        // read through the data register one entry at a time.
+        let mut count = [0u8; 4];
+        self.select(KEY_FILE_DIR);
+        self.read_bytes(&mut count);
+        for _ in 0..u32::from_be_bytes(count) {
+            let mut entry = [0u8; DIR_ENTRY_SIZE];
+            self.read_bytes(&mut entry);
+            let mut file = File {
+                name: [0; NAME_SIZE],
+                size: u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]]),
+                key: u16::from_be_bytes([entry[4], entry[5]]),
+            };
+            file.name.copy_from_slice(&entry[8..]);
+            f(&file);
+        }
+    }
+
+    /// Find an item by name.
+    pub(crate) fn find_file(&self, name: &str) -> Option<File> {
+        let mut found = None;
+        self.for_each_file(|file| {
+            if found.is_none() && file.name() == name {
+                found = Some(*file);
+            }
+        });
+        found
+    }
+
+    /// Copy an item into memory at `addr`.
+    pub(crate) fn load(&self, file: &File, addr: usize) -> Result<(), &'static str> {
+        let dst = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, file.size as usize) };
+        if !self.dma {
+            self.select(file.key);
+            self.read_bytes(dst);
+            return Ok(());
+        }
+
+        let mut req = DmaAccess {
+            control: (((file.key as u32) << 16) | DMA_SELECT | DMA_READ).to_be(),
+            length: file.size.to_be(),
+            address: (addr as u64).to_be(),
+        };
+        // The request must be in memory before the device is told about it,
+        // and the data is only valid once the device has cleared `control`.
+        fence(Ordering::SeqCst);
+        let req_addr = (&raw mut req) as u64;
+        unsafe { ((self.base + REG_DMA) as *mut u64).write_volatile(req_addr.to_be()) };
+        loop {
+            let control = u32::from_be(unsafe { (&raw const req.control).read_volatile() });
+            if control & DMA_ERROR != 0 {
+                return Err("fw_cfg DMA failed");
+            }
+            if control == 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        fence(Ordering::SeqCst);
+        Ok(())
+    }
+
+    // Select the item subsequent data reads come from.
+    fn select(&self, key: u16) {
+        unsafe { ((self.base + REG_SELECTOR) as *mut u16).write_volatile(key.to_be()) };
+    }
+
+    // Read the next bytes of the selected item.
+    fn read_bytes(&self, buf: &mut [u8]) {
+        for b in buf.iter_mut() {
+            *b = unsafe { ((self.base + REG_DATA) as *const u8).read_volatile() };
+        }
+    }
+}
@@ -1,11 +1,38 @@
 #![no_std]
 #![no_main]
 
+mod checksum;
+mod console;
+mod csr;
+mod elf;
+mod fdt;
+#[cfg(not(feature = "supervisor"))]
+mod firmware;
+mod fwcfg;
 mod hex;
 mod memory;
+mod memtest;
+mod net;
+mod paging;
+mod pci;
+mod plic;
+#[cfg(not(feature = "supervisor"))]
+mod pmp;
+mod random;
 mod repl;
+mod rtc;
+// The SBI definitions serve both the client calls and the firmware riscmon
+// implements in machine mode; each build only uses part of them.
+#[allow(dead_code)]
+mod sbi;
+mod semihost;
+#[cfg(not(feature = "supervisor"))]
+mod services;
 mod system;
+mod timer;
+mod trap;
 mod uart;
+mod virtio;
 
 use core::arch::naked_asm;
 use core::panic::PanicInfo;
@@ -14,7 +41,14 @@ use core::panic::PanicInfo;
 // Stack
 // -----------------------------------------------------------------------------
 
+// In machine mode riscmon is loaded at the start of RAM. Under SBI firmware it
+// is loaded 2 MiB in (the firmware owns the first 2 MiB), so its stack moves up
+// by the same amount.
+#[cfg(not(feature = "supervisor"))]
 pub(crate) const STACK_TOP: usize = 0x8010_0000;
+#[cfg(feature = "supervisor")]
+pub(crate) const STACK_TOP: usize = 0x8030_0000;
+
 pub(crate) const STACK_SIZE: usize = 16 * 1024; // 16 KiB reserved for riscmon's stack
 pub(crate) const STACK_BOTTOM: usize = STACK_TOP - STACK_SIZE;
 
@@ -26,6 +60,7 @@ pub(crate) const STACK_BOTTOM: usize = STACK_TOP - STACK_SIZE;
 pub(crate) const INFO_BANNER: &str = concat!("riscmon v", env!("CARGO_PKG_VERSION"));
 
 /// Entry point.
+#[cfg(not(feature = "supervisor"))]
 #[unsafe(naked)]
 #[unsafe(no_mangle)]
 #[unsafe(link_section = ".text.entry")]
@@ -34,6 +69,30 @@ pub(crate) unsafe extern "C" fn _start() {
     // initialize it first and then call main. We use naked_asm to avoid the
     // compiler generating a prologue that would utilize the sp before we set
     // it up, as it would contain garbage data.
+    //
+    // Every hart starts here (QEMU sifive_u always has at least two), but
+    // there is only one monitor stack, so all harts other than hart 0 park.
+    naked_asm!(
+        "csrr t0, mhartid", // read this hart's id
+        "bnez t0, 2f", // park secondary harts
+        "li sp, {stack_top}", // set stack pointer
+        "call main", // jump to our rust main
+        "1: j 1b", // halt if main ever returns (it shouldn't)
+        "2: wfi", // secondary harts sleep forever
+        "j 2b",
+        stack_top = const STACK_TOP,
+    );
+}
+
+/// Entry point (supervisor mode, under SBI firmware).
+#[cfg(feature = "supervisor")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+#[unsafe(link_section = ".text.entry")]
+pub(crate) unsafe extern "C" fn _start() {
+    // As above, but the firmware only starts the boot hart (which need not be
+    // hart 0, and mhartid is not readable here); the rest stay stopped until
+    // started through the SBI HSM extension.
     naked_asm!(
         "li sp, {stack_top}", // set stack pointer
         "call main", // jump to our rust main
@@ -44,9 +103,17 @@ pub(crate) unsafe extern "C" fn _start() {
 
 /// Main entry point (called from _start after stack setup).
 ///
+/// The previous boot stage (QEMU's reset vector, or firmware) passes the hart
+/// id in a0 and the device tree address in a1; _start leaves both untouched.
+///
 /// Returns `!` because the monitor REPL loops until poweroff.
 #[unsafe(no_mangle)]
-pub(crate) extern "C" fn main() -> ! {
+pub(crate) extern "C" fn main(hartid: usize, dtb: usize) -> ! {
+    system::set_boot_hart(hartid);
+    fdt::init(dtb);
+    plic::init();
+    uart::init();
+    trap::init();
     repl::run()
 }
 
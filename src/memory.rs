@@ -4,11 +4,24 @@ pub(crate) use crate::{STACK_BOTTOM, STACK_TOP};
 // Memory Map
 // -----------------------------------------------------------------------------
 
-pub(crate) const RAM_BASE: usize = 0x8000_0000;
+// Physical RAM of the QEMU virt machine.
+const PHYS_RAM_BASE: usize = 0x8000_0000;
 pub(crate) const RAM_SIZE: usize = 128 * 1024 * 1024; // 128 MiB (QEMU "virt" default)
-pub(crate) const RAM_END_INCLUSIVE: usize = RAM_BASE + RAM_SIZE - 1;
+pub(crate) const RAM_END_INCLUSIVE: usize = PHYS_RAM_BASE + RAM_SIZE - 1;
+
+// First RAM address offered to the user. Under SBI firmware the first 2 MiB
+// belong to the firmware (and are typically PMP-protected).
+#[cfg(not(feature = "supervisor"))]
+pub(crate) const RAM_BASE: usize = PHYS_RAM_BASE;
+#[cfg(feature = "supervisor")]
+pub(crate) const RAM_BASE: usize = PHYS_RAM_BASE + 0x20_0000;
 pub(crate) const BYTES_PER_LINE: usize = 16;
 
+/// In machine mode riscmon's image, trap state and stack occupy the first 2 MiB
+/// of RAM, which programs run in a lower privilege mode are kept out of.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) const MONITOR_SIZE: usize = 2 * 1024 * 1024;
+
 // -----------------------------------------------------------------------------
 // Address Validation
 // -----------------------------------------------------------------------------
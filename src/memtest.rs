@@ -0,0 +1,205 @@
+use crate::uart;
+
+// -----------------------------------------------------------------------------
+// Memory Tests
+// -----------------------------------------------------------------------------
+
+// Classic bring-up tests over a range of 64-bit words: walking ones and zeros
+// on the data bus, a power-of-two address-line test, March C-, and a plain
+// fill with a data pattern. Every access is volatile so the compiler cannot
+// keep values in registers.
+
+// Words between checks for Ctrl+C.
+const BREAK_POLL_WORDS: usize = 1 << 16;
+
+/// Data word for the fill and address-line tests when none is given.
+pub(crate) const DEFAULT_PATTERN: u64 = 0xaaaa_aaaa_aaaa_aaaa;
+
+/// A test algorithm.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Test {
+    /// Walking ones and zeros through one word (the data lines).
+    Walk,
+    /// Writes at power-of-two word offsets (the address lines).
+    Address,
+    /// March C- over every word.
+    March,
+    /// Every word written with the pattern, then read back.
+    Fill,
+}
+
+impl Test {
+    /// All tests, in the order they run.
+    pub(crate) const ALL: [Test; 4] = [Test::Walk, Test::Address, Test::March, Test::Fill];
+
+    /// Short name, as given on the command line.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Test::Walk => "walk",
+            Test::Address => "addr",
+            Test::March => "march",
+            Test::Fill => "fill",
+        }
+    }
+}
+
+/// The first mismatch a test found.
+#[derive(Copy, Clone)]
+pub(crate) struct Failure {
+    pub(crate) addr: usize,
+    pub(crate) expected: u64,
+    pub(crate) actual: u64,
+}
+
+/// Why a test stopped early.
+#[derive(Copy, Clone)]
+pub(crate) enum Stop {
+    Failed(Failure),
+    Interrupted,
+}
+
+/// Run one test over `words` 64-bit words at `base` (8-byte aligned), with
+/// `pattern` as the data word of the fill and address-line tests. The range's
+/// contents are destroyed.
+pub(crate) fn run(test: Test, base: usize, words: usize, pattern: u64) -> Result<(), Stop> {
+    // Check before every test too, so even tests too short to poll on their
+    // own (or passes over a few words) can be stopped.
+    if uart::poll_break() {
+        return Err(Stop::Interrupted);
+    }
+    let mem = Words { base, words };
+    match test {
+        Test::Walk => walk(&mem),
+        Test::Address => address(&mem, pattern),
+        Test::March => march(&mem),
+        Test::Fill => fill(&mem, pattern),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Algorithms
+// -----------------------------------------------------------------------------
+
+// A one and then a zero walked through every bit of the first word.
+fn walk(mem: &Words) -> Result<(), Stop> {
+    for bit in 0..64 {
+        for v in [1u64 << bit, !(1u64 << bit)] {
+            mem.write(0, v);
+            mem.check(0, v)?;
+        }
+    }
+    Ok(())
+}
+
+// Michael Barr's address bus test: with every power-of-two offset holding
+// the pattern, writing its complement anywhere must not show up elsewhere.
+fn address(mem: &Words, pattern: u64) -> Result<(), Stop> {
+    let antipattern = !pattern;
+    let offsets = || {
+        (0..usize::BITS)
+            .map(|b| 1usize << b)
+            .take_while(|&o| o < mem.words)
+    };
+
+    for off in offsets() {
+        mem.write(off, pattern);
+    }
+    // An address line stuck high aliases offset 0 onto another.
+    mem.write(0, antipattern);
+    for off in offsets() {
+        mem.check(off, pattern)?;
+    }
+    mem.write(0, pattern);
+
+    // One stuck low, or two shorted together, aliases one offset onto another.
+    for test in offsets() {
+        if uart::poll_break() {
+            return Err(Stop::Interrupted);
+        }
+        mem.write(test, antipattern);
+        mem.check(0, pattern)?;
+        for off in offsets().filter(|&o| o != test) {
+            mem.check(off, pattern)?;
+        }
+        mem.write(test, pattern);
+    }
+    Ok(())
+}
+
+// March C-: up(w0); up(r0,w1); up(r1,w0); down(r0,w1); down(r1,w0); up(r0).
+fn march(mem: &Words) -> Result<(), Stop> {
+    const ZERO: u64 = 0;
+    const ONES: u64 = u64::MAX;
+
+    mem.sweep(false, |i| {
+        mem.write(i, ZERO);
+        Ok(())
+    })?;
+    for (down, from, to) in [
+        (false, ZERO, ONES),
+        (false, ONES, ZERO),
+        (true, ZERO, ONES),
+        (true, ONES, ZERO),
+    ] {
+        mem.sweep(down, |i| {
+            mem.check(i, from)?;
+            mem.write(i, to);
+            Ok(())
+        })?;
+    }
+    mem.sweep(false, |i| mem.check(i, ZERO))
+}
+
+// Every word set to the pattern, then every word read back.
+fn fill(mem: &Words, pattern: u64) -> Result<(), Stop> {
+    mem.sweep(false, |i| {
+        mem.write(i, pattern);
+        Ok(())
+    })?;
+    mem.sweep(false, |i| mem.check(i, pattern))
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The range under test.
+struct Words {
+    base: usize,
+    words: usize,
+}
+
+impl Words {
+    fn addr(&self, i: usize) -> usize {
+        self.base + i * 8
+    }
+
+    fn write(&self, i: usize, v: u64) {
+        unsafe { (self.addr(i) as *mut u64).write_volatile(v) }
+    }
+
+    // Read word `i` and compare it with what should be there.
+    fn check(&self, i: usize, expected: u64) -> Result<(), Stop> {
+        let actual = unsafe { (self.addr(i) as *const u64).read_volatile() };
+        match actual == expected {
+            true => Ok(()),
+            false => Err(Stop::Failed(Failure {
+                addr: self.addr(i),
+                expected,
+                actual,
+            })),
+        }
+    }
+
+    // Visit every word, upwards or downwards, checking for Ctrl+C now and
+    // then.
+    fn sweep(&self, down: bool, mut f: impl FnMut(usize) -> Result<(), Stop>) -> Result<(), Stop> {
+        for n in 0..self.words {
+            if n % BREAK_POLL_WORDS == 0 && uart::poll_break() {
+                return Err(Stop::Interrupted);
+            }
+            f(if down { self.words - 1 - n } else { n })?;
+        }
+        Ok(())
+    }
+}
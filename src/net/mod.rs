@@ -0,0 +1,275 @@
+pub(crate) mod tftp;
+
+use crate::{timer, virtio::net as nic};
+use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, Ordering};
+
+// -----------------------------------------------------------------------------
+// Network Stack (Ethernet, ARP, IPv4, UDP)
+// -----------------------------------------------------------------------------
+
+// Just enough to talk UDP to a host on the local link: no routing, no
+// fragments, no IP options on sent packets, and one ARP cache entry.
+
+/// Our address: QEMU user-mode networking always gives the guest 10.0.2.15.
+pub(crate) const LOCAL_IP: [u8; 4] = [10, 0, 2, 15];
+
+/// The host side of QEMU user-mode networking, which serves TFTP.
+pub(crate) const SERVER_IP: [u8; 4] = [10, 0, 2, 2];
+
+// Ethernet.
+const ETH_HEADER: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_ARP: u16 = 0x0806;
+const BROADCAST: [u8; 6] = [0xff; 6];
+
+// ARP (for IPv4 over Ethernet).
+const ARP_SIZE: usize = 28;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+const ARP_TRIES: usize = 4;
+const ARP_TIMEOUT_MS: u64 = 250;
+
+// IPv4 and UDP.
+const IP_HEADER: usize = 20;
+const IP_TTL: u8 = 64;
+const IP_PROTO_UDP: u8 = 17;
+const UDP_HEADER: usize = 8;
+
+/// Most UDP payload that fits in one frame.
+pub(crate) const MAX_UDP_PAYLOAD: usize = nic::FRAME_SIZE - ETH_HEADER - IP_HEADER - UDP_HEADER;
+
+// The one cached ARP entry (IP 0 = empty).
+static ARP_IP: AtomicU32 = AtomicU32::new(0);
+static ARP_MAC: AtomicU64 = AtomicU64::new(0);
+
+// Identification of the next IPv4 packet sent.
+static IP_ID: AtomicU16 = AtomicU16::new(1);
+
+// The frame being sent.
+static mut TX_FRAME: [u8; nic::FRAME_SIZE] = [0; nic::FRAME_SIZE];
+
+/// Send a UDP datagram.
+pub(crate) fn udp_send(
+    dst: [u8; 4],
+    src_port: u16,
+    dst_port: u16,
+    payload: &[u8],
+) -> Result<(), &'static str> {
+    if payload.len() > MAX_UDP_PAYLOAD {
+        return Err("datagram too large");
+    }
+    let dst_mac = resolve(dst)?;
+    let src_mac = nic::mac()?;
+
+    let frame = &raw mut TX_FRAME;
+    let frame = unsafe { &mut *frame };
+    let udp_len = UDP_HEADER + payload.len();
+    let ip_len = IP_HEADER + udp_len;
+
+    eth_header(frame, dst_mac, src_mac, ETHERTYPE_IPV4);
+    let ip = &mut frame[ETH_HEADER..ETH_HEADER + IP_HEADER];
+    ip.fill(0);
+    ip[0] = 0x45; // version 4, 5-word header
+    put16(ip, 2, ip_len as u16);
+    put16(ip, 4, IP_ID.fetch_add(1, Ordering::Relaxed));
+    ip[8] = IP_TTL;
+    ip[9] = IP_PROTO_UDP;
+    ip[12..16].copy_from_slice(&LOCAL_IP);
+    ip[16..20].copy_from_slice(&dst);
+    let sum = checksum(ip);
+    put16(ip, 10, sum);
+
+    // The UDP checksum is optional over IPv4 and left as 0 (none).
+    let udp = &mut frame[ETH_HEADER + IP_HEADER..ETH_HEADER + ip_len];
+    put16(udp, 0, src_port);
+    put16(udp, 2, dst_port);
+    put16(udp, 4, udp_len as u16);
+    put16(udp, 6, 0);
+    udp[UDP_HEADER..].copy_from_slice(payload);
+
+    nic::send(&frame[..ETH_HEADER + ip_len])
+}
+
+/// Wait until `deadline` (in `mtime` ticks) for a UDP datagram to `port` that
+/// `f` accepts. `f` gets the sender's address and port and the payload.
+///
+/// ARP requests for our address are answered while waiting.
+pub(crate) fn udp_recv<T>(
+    port: u16,
+    deadline: u64,
+    mut f: impl FnMut([u8; 4], u16, &[u8]) -> Option<T>,
+) -> Result<Option<T>, &'static str> {
+    loop {
+        match nic::recv(|frame| classify(frame, port, &mut f))? {
+            Some(Received::Udp(v)) => return Ok(Some(v)),
+            Some(Received::ArpRequest { mac, ip }) => arp_send(ARP_REPLY, mac, mac, ip)?,
+            Some(Received::Other) => {}
+            None if timer::now() >= deadline => return Ok(None),
+            None => core::hint::spin_loop(),
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// What a received frame turned out to be.
+enum Received<T> {
+    // A datagram `f` accepted.
+    Udp(T),
+    // Someone asked for our MAC address.
+    ArpRequest { mac: [u8; 6], ip: [u8; 4] },
+    // Anything else (ARP replies are cached on the way).
+    Other,
+}
+
+// Sort a received frame, passing UDP datagrams to `port` on to `f`.
+fn classify<T>(
+    frame: &[u8],
+    port: u16,
+    f: &mut impl FnMut([u8; 4], u16, &[u8]) -> Option<T>,
+) -> Received<T> {
+    let Some(ethertype) = be16(frame, 12) else {
+        return Received::Other;
+    };
+    let payload = &frame[ETH_HEADER..];
+
+    match ethertype {
+        ETHERTYPE_ARP if payload.len() >= ARP_SIZE => {
+            let (mac, ip) = (array(&payload[8..14]), array(&payload[14..18]));
+            if payload[24..28] != LOCAL_IP {
+                return Received::Other;
+            }
+            match be16(payload, 6) {
+                Some(ARP_REQUEST) => Received::ArpRequest { mac, ip },
+                Some(ARP_REPLY) => {
+                    ARP_MAC.store(mac_to_u64(mac), Ordering::Relaxed);
+                    ARP_IP.store(u32::from_be_bytes(ip), Ordering::Relaxed);
+                    Received::Other
+                }
+                _ => Received::Other,
+            }
+        }
+        ETHERTYPE_IPV4 => match udp_payload(payload, port) {
+            Some((src, src_port, data)) => {
+                f(src, src_port, data).map_or(Received::Other, Received::Udp)
+            }
+            None => Received::Other,
+        },
+        _ => Received::Other,
+    }
+}
+
+// The sender and payload of an IPv4 packet carrying a UDP datagram for us at
+// `port`.
+fn udp_payload(ip: &[u8], port: u16) -> Option<([u8; 4], u16, &[u8])> {
+    if ip.len() < IP_HEADER {
+        return None;
+    }
+    let ihl = (ip[0] & 0x0f) as usize * 4;
+    let total = be16(ip, 2)? as usize;
+    let fragment = be16(ip, 6)? & 0x3fff; // MF flag and fragment offset
+    if ip[0] >> 4 != 4 || ip[9] != IP_PROTO_UDP || fragment != 0 {
+        return None;
+    }
+    if ihl < IP_HEADER || total > ip.len() || total < ihl + UDP_HEADER || ip[16..20] != LOCAL_IP {
+        return None;
+    }
+
+    let udp = &ip[ihl..total];
+    let udp_len = be16(udp, 4)? as usize;
+    if be16(udp, 2)? != port || udp_len < UDP_HEADER || udp_len > udp.len() {
+        return None;
+    }
+    Some((array(&ip[12..16]), be16(udp, 0)?, &udp[UDP_HEADER..udp_len]))
+}
+
+// The MAC address of a host on the local link, asking with ARP if needed.
+fn resolve(ip: [u8; 4]) -> Result<[u8; 6], &'static str> {
+    let cached = || {
+        (ARP_IP.load(Ordering::Relaxed) == u32::from_be_bytes(ip))
+            .then(|| u64_to_mac(ARP_MAC.load(Ordering::Relaxed)))
+    };
+
+    for _ in 0..ARP_TRIES {
+        if let Some(mac) = cached() {
+            return Ok(mac);
+        }
+        arp_send(ARP_REQUEST, BROADCAST, [0; 6], ip)?;
+        // Replies are cached by `classify`; nothing listens on port 0.
+        let deadline = timer::now() + timer::ms_to_ticks(ARP_TIMEOUT_MS);
+        while cached().is_none() && timer::now() < deadline {
+            udp_recv(0, timer::now(), |_, _, _| Some(()))?;
+        }
+    }
+    cached().ok_or("no ARP reply from the server")
+}
+
+// Send an ARP packet asking for (or telling) `target_ip` the MAC address of
+// `LOCAL_IP`.
+fn arp_send(
+    op: u16,
+    dst_mac: [u8; 6],
+    target_mac: [u8; 6],
+    target_ip: [u8; 4],
+) -> Result<(), &'static str> {
+    let src_mac = nic::mac()?;
+    let mut frame = [0u8; ETH_HEADER + ARP_SIZE];
+    eth_header(&mut frame, dst_mac, src_mac, ETHERTYPE_ARP);
+
+    let arp = &mut frame[ETH_HEADER..];
+    put16(arp, 0, 1); // Ethernet
+    put16(arp, 2, ETHERTYPE_IPV4);
+    arp[4] = 6;
+    arp[5] = 4;
+    put16(arp, 6, op);
+    arp[8..14].copy_from_slice(&src_mac);
+    arp[14..18].copy_from_slice(&LOCAL_IP);
+    arp[18..24].copy_from_slice(&target_mac);
+    arp[24..28].copy_from_slice(&target_ip);
+
+    nic::send(&frame)
+}
+
+// Fill in an Ethernet header.
+fn eth_header(frame: &mut [u8], dst: [u8; 6], src: [u8; 6], ethertype: u16) {
+    frame[0..6].copy_from_slice(&dst);
+    frame[6..12].copy_from_slice(&src);
+    put16(frame, 12, ethertype);
+}
+
+// The Internet checksum of `data` (of even length).
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|w| u16::from_be_bytes([w[0], *w.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+// Read a big-endian u16 at `off`.
+fn be16(b: &[u8], off: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*b.get(off)?, *b.get(off + 1)?]))
+}
+
+// Write a big-endian u16 at `off`.
+fn put16(b: &mut [u8], off: usize, v: u16) {
+    b[off..off + 2].copy_from_slice(&v.to_be_bytes());
+}
+
+// Copy a slice of known length into an array.
+fn array<const N: usize>(b: &[u8]) -> [u8; N] {
+    core::array::from_fn(|i| b[i])
+}
+
+fn mac_to_u64(mac: [u8; 6]) -> u64 {
+    mac.iter().fold(0, |v, &b| (v << 8) | b as u64)
+}
+
+fn u64_to_mac(v: u64) -> [u8; 6] {
+    core::array::from_fn(|i| (v >> (40 - 8 * i)) as u8)
+}
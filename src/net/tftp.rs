@@ -0,0 +1,195 @@
+use super::{MAX_UDP_PAYLOAD, udp_recv, udp_send};
+use crate::{timer, uart};
+use core::sync::atomic::{AtomicU16, Ordering};
+
+// -----------------------------------------------------------------------------
+// TFTP Client (RFC 1350, with the RFC 2348 block size option)
+// -----------------------------------------------------------------------------
+
+// Well-known server port; the transfer itself moves to a port the server picks.
+const SERVER_PORT: u16 = 69;
+
+// Opcodes.
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+const OP_OACK: u16 = 6;
+
+// Block size without the option, and the one asked for (the most that fits in
+// one Ethernet frame).
+const DEFAULT_BLOCK_SIZE: usize = 512;
+const BLOCK_SIZE: usize = MAX_UDP_PAYLOAD - 4;
+const BLOCK_SIZE_STR: &str = "1468";
+const _: () = assert!(BLOCK_SIZE == 1468, "BLOCK_SIZE_STR is out of date");
+
+// Longest filename accepted.
+const MAX_FILENAME: usize = 255;
+
+// How long to wait for the server before resending, and how often.
+const TIMEOUT_MS: u64 = 1000;
+const RETRIES: usize = 5;
+
+// The first local port used; each transfer takes the next one, so stray
+// packets from an abandoned transfer are not mistaken for the current one.
+const LOCAL_PORT_BASE: u16 = 49152;
+static NEXT_PORT: AtomicU16 = AtomicU16::new(0);
+
+// What the server sent.
+enum Reply {
+    // A data block, copied into memory if it was the expected one.
+    Data { block: u16, len: usize },
+    // The server accepted our options; the block size to use.
+    OptionAck { block_size: usize },
+    Error(&'static str),
+}
+
+/// Download `filename` from the TFTP server at `server` into memory at `addr`,
+/// writing nothing beyond `limit` (inclusive). Returns the file size.
+///
+/// Ctrl+C abandons the transfer.
+pub(crate) fn get(
+    server: [u8; 4],
+    filename: &str,
+    addr: usize,
+    limit: usize,
+) -> Result<usize, &'static str> {
+    if filename.is_empty() || filename.len() > MAX_FILENAME || filename.contains('\0') {
+        return Err("invalid filename");
+    }
+
+    // Read request: filename, mode and the block size option, each
+    // NUL-terminated.
+    let mut packet = [0u8; 2 + MAX_FILENAME + 32];
+    let mut len = 2;
+    packet[..2].copy_from_slice(&OP_RRQ.to_be_bytes());
+    for field in [filename, "octet", "blksize", BLOCK_SIZE_STR] {
+        packet[len..len + field.len()].copy_from_slice(field.as_bytes());
+        len += field.len() + 1;
+    }
+
+    let local = next_local_port();
+    let mut peer_port = None;
+    let mut block_size = DEFAULT_BLOCK_SIZE;
+    let mut expected: u16 = 1;
+    let mut received = 0usize;
+    let mut retries = RETRIES;
+
+    udp_send(server, local, SERVER_PORT, &packet[..len])?;
+    loop {
+        if uart::poll_break() {
+            return Err("interrupted");
+        }
+
+        let deadline = timer::now() + timer::ms_to_ticks(TIMEOUT_MS);
+        let reply = udp_recv(local, deadline, |ip, port, data| {
+            if ip != server || peer_port.is_some_and(|p| p != port) {
+                return None;
+            }
+            let reply = parse(data, expected, addr + received, limit)?;
+            Some((port, reply))
+        })?;
+
+        let Some((port, reply)) = reply else {
+            // Nothing came: resend whatever was sent last.
+            retries = retries.checked_sub(1).ok_or("timed out")?;
+            let dst_port = peer_port.unwrap_or(SERVER_PORT);
+            udp_send(server, local, dst_port, &packet[..len])?;
+            continue;
+        };
+        peer_port = Some(port);
+        retries = RETRIES;
+
+        let ack = match reply {
+            Reply::Error(e) => return Err(e),
+            Reply::OptionAck { block_size: size } => {
+                block_size = size;
+                0
+            }
+            Reply::Data { block, len: n } if block == expected => {
+                received += n;
+                expected = expected.wrapping_add(1);
+                if n < block_size {
+                    // The last block: acknowledge it once and finish.
+                    ack_packet(&mut packet, block);
+                    udp_send(server, local, port, &packet[..4])?;
+                    return Ok(received);
+                }
+                block
+            }
+            // A duplicate: our acknowledgment was lost, so send it again.
+            Reply::Data { block, .. } if block == expected.wrapping_sub(1) => block,
+            Reply::Data { .. } => continue,
+        };
+        ack_packet(&mut packet, ack);
+        len = 4;
+        udp_send(server, local, port, &packet[..len])?;
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Make sense of a packet from the server, copying the data of block `expected`
+// to `dst` (but not past `limit`).
+fn parse(data: &[u8], expected: u16, dst: usize, limit: usize) -> Option<Reply> {
+    let op = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+    let arg = u16::from_be_bytes([*data.get(2)?, *data.get(3)?]);
+    let payload = &data[4..];
+
+    match op {
+        OP_DATA => {
+            if arg == expected && !payload.is_empty() {
+                match dst.checked_add(payload.len() - 1) {
+                    Some(end) if end <= limit => {}
+                    _ => return Some(Reply::Error("file too large for the space at ADDR")),
+                }
+                unsafe {
+                    core::ptr::copy_nonoverlapping(payload.as_ptr(), dst as *mut u8, payload.len())
+                };
+            }
+            Some(Reply::Data {
+                block: arg,
+                len: payload.len(),
+            })
+        }
+        OP_OACK => {
+            // Options come as NUL-separated name/value pairs.
+            let mut fields = data[2..].split(|&b| b == 0);
+            let mut block_size = DEFAULT_BLOCK_SIZE;
+            while let (Some(name), Some(value)) = (fields.next(), fields.next()) {
+                if name.eq_ignore_ascii_case(b"blksize") {
+                    block_size = core::str::from_utf8(value).ok()?.parse().ok()?;
+                }
+            }
+            match block_size {
+                8..=BLOCK_SIZE => Some(Reply::OptionAck { block_size }),
+                _ => Some(Reply::Error("server chose an unusable block size")),
+            }
+        }
+        OP_ERROR => Some(Reply::Error(match arg {
+            1 => "file not found",
+            2 => "access violation",
+            3 => "disk full",
+            4 => "illegal TFTP operation",
+            5 => "unknown transfer ID",
+            6 => "file already exists",
+            7 => "no such user",
+            8 => "option negotiation failed",
+            _ => "server error",
+        })),
+        _ => None,
+    }
+}
+
+// Write an acknowledgment of `block` to the start of `packet`.
+fn ack_packet(packet: &mut [u8], block: u16) {
+    packet[..2].copy_from_slice(&OP_ACK.to_be_bytes());
+    packet[2..4].copy_from_slice(&block.to_be_bytes());
+}
+
+// A local port for a new transfer.
+fn next_local_port() -> u16 {
+    LOCAL_PORT_BASE + NEXT_PORT.fetch_add(1, Ordering::Relaxed) % 16384
+}
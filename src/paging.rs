@@ -0,0 +1,465 @@
+use crate::memory;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// Page Tables (Sv39 / Sv48)
+// -----------------------------------------------------------------------------
+
+// riscmon builds page tables for the programs it launches; it never translates
+// its own accesses. Tables are allocated from a pool in ordinary RAM (not the
+// monitor's own memory), since the hardware page walker of a supervisor- or
+// user-mode program has to be able to read them.
+
+/// Size of a page and of a page table.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+// Entries per table and bits of virtual page number per level.
+const ENTRIES: usize = 512;
+const VPN_BITS: usize = 9;
+
+/// PTE flag bits.
+pub(crate) const PTE_V: u64 = 1 << 0;
+pub(crate) const PTE_R: u64 = 1 << 1;
+pub(crate) const PTE_W: u64 = 1 << 2;
+pub(crate) const PTE_X: u64 = 1 << 3;
+pub(crate) const PTE_U: u64 = 1 << 4;
+pub(crate) const PTE_G: u64 = 1 << 5;
+pub(crate) const PTE_A: u64 = 1 << 6;
+pub(crate) const PTE_D: u64 = 1 << 7;
+
+// The flags a user can set, in the order they are printed.
+const FLAG_NAMES: [(u8, u64); 7] = [
+    (b'r', PTE_R),
+    (b'w', PTE_W),
+    (b'x', PTE_X),
+    (b'u', PTE_U),
+    (b'g', PTE_G),
+    (b'a', PTE_A),
+    (b'd', PTE_D),
+];
+
+// The PPN field of a PTE (bits 10..53).
+const PTE_PPN_SHIFT: usize = 10;
+const PTE_PPN_MASK: u64 = (1 << 44) - 1;
+
+// satp fields.
+const SATP_MODE_SHIFT: usize = 60;
+
+/// Default pool for page tables: 4 MiB below the top of RAM, which keeps clear
+/// of the device tree QEMU places in the top 2 MiB.
+pub(crate) const DEFAULT_POOL: usize = memory::RAM_END_INCLUSIVE + 1 - 4 * 1024 * 1024;
+
+// Most tables a pool holds (512 KiB).
+const POOL_TABLES: usize = 128;
+
+// The current table set: root table (0 = none), levels, and pool bounds.
+static ROOT: AtomicUsize = AtomicUsize::new(0);
+static LEVELS: AtomicU8 = AtomicU8::new(0);
+static POOL_NEXT: AtomicUsize = AtomicUsize::new(0);
+static POOL_END: AtomicUsize = AtomicUsize::new(0);
+
+// Whether launched S/U-mode programs run with translation on.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// A supported translation mode.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Sv39,
+    Sv48,
+}
+
+impl Mode {
+    /// Parse a mode name (`sv39` or `sv48`).
+    pub(crate) fn from_name(s: &str) -> Option<Mode> {
+        match s {
+            "sv39" => Some(Mode::Sv39),
+            "sv48" => Some(Mode::Sv48),
+            _ => None,
+        }
+    }
+
+    /// The mode's name.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Mode::Sv39 => "sv39",
+            Mode::Sv48 => "sv48",
+        }
+    }
+
+    // Number of table levels.
+    fn levels(self) -> usize {
+        match self {
+            Mode::Sv39 => 3,
+            Mode::Sv48 => 4,
+        }
+    }
+
+    // The satp MODE field value.
+    fn satp_mode(self) -> usize {
+        match self {
+            Mode::Sv39 => 8,
+            Mode::Sv48 => 9,
+        }
+    }
+}
+
+/// One step of a page-table walk.
+#[derive(Copy, Clone)]
+pub(crate) struct Step {
+    /// Table level (the root is `levels - 1`, leaves of 4 KiB pages are 0).
+    pub(crate) level: usize,
+    /// Index of the entry within its table.
+    pub(crate) index: usize,
+    /// Physical address of the entry.
+    pub(crate) addr: usize,
+    /// The entry's value.
+    pub(crate) pte: u64,
+}
+
+/// The result of walking a virtual address through the current tables.
+pub(crate) struct Walk {
+    /// The entries visited, root first.
+    pub(crate) steps: [Step; 4],
+    pub(crate) len: usize,
+    /// The translated physical address, if the walk reached a valid leaf.
+    pub(crate) pa: Option<usize>,
+}
+
+// -----------------------------------------------------------------------------
+// Table Set
+// -----------------------------------------------------------------------------
+
+/// Start a new, empty table set of `mode`, allocating tables from `pool`.
+///
+/// Returns the root table's address. Translation is switched off, since the
+/// previous tables are gone.
+pub(crate) fn new(mode: Mode, pool: usize) -> Result<usize, &'static str> {
+    if !pool.is_multiple_of(PAGE_SIZE) {
+        return Err("pool must be 4 KiB aligned");
+    }
+    if !memory::is_in_ram(pool) {
+        return Err("pool out of range");
+    }
+    // In machine mode the whole PMP-protected monitor region is off limits,
+    // since the hardware walks the tables with the program's privilege.
+    #[cfg(not(feature = "supervisor"))]
+    let monitor_end = memory::RAM_BASE + memory::MONITOR_SIZE - 1;
+    #[cfg(feature = "supervisor")]
+    let monitor_end = crate::STACK_TOP - 1;
+    if memory::ranges_overlap(pool, pool + PAGE_SIZE - 1, memory::RAM_BASE, monitor_end) {
+        return Err("pool overlaps riscmon");
+    }
+
+    let end = pool
+        .saturating_add(POOL_TABLES * PAGE_SIZE)
+        .min(memory::RAM_END_INCLUSIVE + 1);
+    ENABLED.store(false, Ordering::Relaxed);
+    LEVELS.store(mode.levels() as u8, Ordering::Relaxed);
+    POOL_NEXT.store(pool, Ordering::Relaxed);
+    POOL_END.store(end, Ordering::Relaxed);
+
+    let root = alloc_table().ok_or("pool exhausted")?;
+    ROOT.store(root, Ordering::Relaxed);
+    Ok(root)
+}
+
+/// The current mode and root table, if a table set exists.
+pub(crate) fn current() -> Option<(Mode, usize)> {
+    let root = ROOT.load(Ordering::Relaxed);
+    let mode = match LEVELS.load(Ordering::Relaxed) {
+        3 => Mode::Sv39,
+        4 => Mode::Sv48,
+        _ => return None,
+    };
+    (root != 0).then_some((mode, root))
+}
+
+/// Number of tables allocated from the pool so far, and how many it holds.
+pub(crate) fn pool_usage() -> (usize, usize) {
+    let root = ROOT.load(Ordering::Relaxed);
+    let used = (POOL_NEXT.load(Ordering::Relaxed) - root) / PAGE_SIZE;
+    let total = (POOL_END.load(Ordering::Relaxed) - root) / PAGE_SIZE;
+    (used, total)
+}
+
+// -----------------------------------------------------------------------------
+// Mapping
+// -----------------------------------------------------------------------------
+
+/// Map `size` bytes at virtual `va` to physical `pa` with `flags` (V is added).
+///
+/// Uses the largest pages alignment allows. Returns the number of pages mapped.
+/// On error, pages mapped before the failure stay mapped.
+pub(crate) fn map(va: usize, pa: usize, size: usize, flags: u64) -> Result<usize, &'static str> {
+    let Some((mode, root)) = current() else {
+        return Err("no page table (use 'pt new')");
+    };
+    if !va.is_multiple_of(PAGE_SIZE) || !pa.is_multiple_of(PAGE_SIZE) {
+        return Err("VA and PA must be 4 KiB aligned");
+    }
+    if size == 0 || !size.is_multiple_of(PAGE_SIZE) {
+        return Err("SIZE must be a non-zero multiple of 4 KiB");
+    }
+    if flags & (PTE_R | PTE_X) == 0 {
+        return Err("FLAGS must include r or x");
+    }
+    if flags & (PTE_W | PTE_R) == PTE_W {
+        return Err("w needs r");
+    }
+    let last = va.checked_add(size - 1).ok_or("range overflows")?;
+    if !is_canonical(mode, va) || !is_canonical(mode, last) {
+        return Err("VA range is not canonical for this mode");
+    }
+
+    let mut pages = 0;
+    let mut off = 0;
+    while off < size {
+        let (v, p) = (va + off, pa + off);
+        // The largest page (up to 1 GiB) that fits alignment and the remainder.
+        let mut level = (mode.levels() - 1).min(2);
+        while level > 0
+            && !(v.is_multiple_of(page_size(level))
+                && p.is_multiple_of(page_size(level))
+                && size - off >= page_size(level))
+        {
+            level -= 1;
+        }
+
+        let entry = table_entry(mode, root, v, level)?;
+        if read_pte(entry) & PTE_V != 0 {
+            return Err("VA is already mapped");
+        }
+        write_pte(entry, (((p >> 12) as u64) << PTE_PPN_SHIFT) | flags | PTE_V);
+
+        off += page_size(level);
+        pages += 1;
+    }
+    Ok(pages)
+}
+
+/// Walk `va` through the current tables.
+pub(crate) fn walk(va: usize) -> Option<Walk> {
+    let (mode, root) = current()?;
+    let mut walk = Walk {
+        steps: [Step {
+            level: 0,
+            index: 0,
+            addr: 0,
+            pte: 0,
+        }; 4],
+        len: 0,
+        pa: None,
+    };
+    if !is_canonical(mode, va) {
+        return Some(walk);
+    }
+
+    let mut table = root;
+    for level in (0..mode.levels()).rev() {
+        let index = vpn(va, level);
+        let addr = table + index * 8;
+        let pte = read_pte(addr);
+        walk.steps[walk.len] = Step {
+            level,
+            index,
+            addr,
+            pte,
+        };
+        walk.len += 1;
+
+        if pte & PTE_V == 0 {
+            break;
+        }
+        if is_leaf(pte) {
+            walk.pa = Some(pte_addr(pte) + (va & (page_size(level) - 1)));
+            break;
+        }
+        table = pte_addr(pte);
+    }
+    Some(walk)
+}
+
+/// Call `f(va, pa, page_size, pte)` for every valid leaf in the current
+/// tables, in address order.
+pub(crate) fn for_each_leaf(mut f: impl FnMut(usize, usize, usize, u64)) {
+    let Some((mode, root)) = current() else {
+        return;
+    };
+    visit(mode, root, mode.levels() - 1, 0, &mut f);
+}
+
+// -----------------------------------------------------------------------------
+// Flags
+// -----------------------------------------------------------------------------
+
+/// Parse a flag string such as `rwx` or `rw-ad` (`-` is ignored).
+pub(crate) fn parse_flags(s: &str) -> Option<u64> {
+    let mut flags = 0;
+    for c in s.bytes() {
+        if c == b'-' {
+            continue;
+        }
+        let (_, bit) = FLAG_NAMES.iter().find(|(name, _)| *name == c)?;
+        flags |= bit;
+    }
+    Some(flags)
+}
+
+/// Render a PTE's flags as `rwxugad`, with `-` for clear bits.
+pub(crate) fn flag_string(pte: u64) -> [u8; 7] {
+    let mut s = [b'-'; 7];
+    for (i, (name, bit)) in FLAG_NAMES.iter().enumerate() {
+        if pte & bit != 0 {
+            s[i] = *name;
+        }
+    }
+    s
+}
+
+/// Whether a valid PTE is a leaf (rather than a pointer to the next level).
+pub(crate) fn is_leaf(pte: u64) -> bool {
+    pte & (PTE_R | PTE_W | PTE_X) != 0
+}
+
+/// The physical address a PTE points to.
+pub(crate) fn pte_addr(pte: u64) -> usize {
+    (((pte >> PTE_PPN_SHIFT) & PTE_PPN_MASK) << 12) as usize
+}
+
+/// Size of the region a leaf at `level` maps.
+pub(crate) fn page_size(level: usize) -> usize {
+    PAGE_SIZE << (level * VPN_BITS)
+}
+
+// -----------------------------------------------------------------------------
+// Translation Control
+// -----------------------------------------------------------------------------
+
+/// Turn translation for launched S/U-mode programs on or off. Returns false if
+/// there are no tables to turn it on with.
+pub(crate) fn set_enabled(on: bool) -> bool {
+    if on && current().is_none() {
+        return false;
+    }
+    ENABLED.store(on, Ordering::Relaxed);
+    true
+}
+
+/// The satp value launched S/U-mode programs start with (0 = bare).
+pub(crate) fn satp() -> usize {
+    match (ENABLED.load(Ordering::Relaxed), current()) {
+        (true, Some((mode, root))) => (mode.satp_mode() << SATP_MODE_SHIFT) | (root >> 12),
+        _ => 0,
+    }
+}
+
+/// Load `satp` into the hart's satp CSR and flush the TLB.
+///
+/// Only meaningful in machine mode, where the monitor's own accesses are never
+/// translated.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) fn activate(satp: usize) {
+    crate::csr::write!(CSR_SATP, satp);
+    unsafe { core::arch::asm!("sfence.vma") };
+}
+
+/// The hart's satp CSR: the tables S/U-mode code is running with right now.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) fn active_satp() -> usize {
+    crate::csr::read!(CSR_SATP)
+}
+
+// The `satp` CSR.
+#[cfg(not(feature = "supervisor"))]
+const CSR_SATP: usize = 0x180;
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Take a zeroed table from the pool.
+fn alloc_table() -> Option<usize> {
+    let table = POOL_NEXT.load(Ordering::Relaxed);
+    if table + PAGE_SIZE > POOL_END.load(Ordering::Relaxed) {
+        return None;
+    }
+    POOL_NEXT.store(table + PAGE_SIZE, Ordering::Relaxed);
+    for i in 0..ENTRIES {
+        write_pte(table + i * 8, 0);
+    }
+    Some(table)
+}
+
+// Find (creating intermediate tables as needed) the entry for `va` at `level`.
+fn table_entry(mode: Mode, root: usize, va: usize, level: usize) -> Result<usize, &'static str> {
+    let mut table = root;
+    for l in (level + 1..mode.levels()).rev() {
+        let entry = table + vpn(va, l) * 8;
+        let pte = read_pte(entry);
+        table = match pte & PTE_V != 0 {
+            true if is_leaf(pte) => return Err("VA is already mapped by a larger page"),
+            true => pte_addr(pte),
+            false => {
+                let next = alloc_table().ok_or("page table pool exhausted")?;
+                write_pte(entry, (((next >> 12) as u64) << PTE_PPN_SHIFT) | PTE_V);
+                next
+            }
+        };
+    }
+    Ok(table + vpn(va, level) * 8)
+}
+
+// Recursively report the leaves of the table at `table` (covering VAs from
+// `base`).
+fn visit(
+    mode: Mode,
+    table: usize,
+    level: usize,
+    base: usize,
+    f: &mut impl FnMut(usize, usize, usize, u64),
+) {
+    for index in 0..ENTRIES {
+        let pte = read_pte(table + index * 8);
+        if pte & PTE_V == 0 {
+            continue;
+        }
+        let va = sign_extend(mode, base | (index << (12 + level * VPN_BITS)));
+        match (is_leaf(pte), level) {
+            (true, _) => f(va, pte_addr(pte), page_size(level), pte),
+            // A pointer at level 0 is malformed; there is nothing below it.
+            (false, 0) => {}
+            (false, _) => visit(mode, pte_addr(pte), level - 1, va, f),
+        }
+    }
+}
+
+// The VPN field of `va` for `level`.
+fn vpn(va: usize, level: usize) -> usize {
+    (va >> (12 + level * VPN_BITS)) & (ENTRIES - 1)
+}
+
+// Number of significant virtual address bits in `mode`.
+fn va_bits(mode: Mode) -> usize {
+    12 + mode.levels() * VPN_BITS
+}
+
+// Whether `va` is sign-extended from its top significant bit.
+fn is_canonical(mode: Mode, va: usize) -> bool {
+    sign_extend(mode, va) == va
+}
+
+// Sign-extend `va` from its top significant bit.
+fn sign_extend(mode: Mode, va: usize) -> usize {
+    let shift = usize::BITS as usize - va_bits(mode);
+    (((va << shift) as isize) >> shift) as usize
+}
+
+// Read the PTE at physical `addr`.
+fn read_pte(addr: usize) -> u64 {
+    unsafe { (addr as *const u64).read_volatile() }
+}
+
+// Write the PTE at physical `addr`.
+fn write_pte(addr: usize, pte: u64) {
+    unsafe { (addr as *mut u64).write_volatile(pte) }
+}
@@ -0,0 +1,548 @@
+use crate::fdt;
+
+// -----------------------------------------------------------------------------
+// PCI Express (ECAM)
+// -----------------------------------------------------------------------------
+
+// QEMU virt has a generic PCIe host bridge whose configuration space is
+// memory-mapped (ECAM): each function gets 4 KiB at
+// base + (bus << 20 | device << 15 | function << 12). Devices decode the
+// addresses their BARs are given within the bridge's I/O and memory windows,
+// which the device tree describes in the bridge's `ranges`.
+
+// QEMU virt's bridge, when there is no device tree.
+const DEFAULT_ECAM_BASE: usize = 0x3000_0000;
+const DEFAULT_IO: Window = Window {
+    pci: 0,
+    cpu: 0x0300_0000,
+    size: 0x1_0000,
+};
+const DEFAULT_MEM: Window = Window {
+    pci: 0x4000_0000,
+    cpu: 0x4000_0000,
+    size: 0x4000_0000,
+};
+
+/// Size of one function's configuration space.
+pub(crate) const CONFIG_SIZE: usize = 4096;
+
+// Configuration space registers (common to all header types).
+const VENDOR_ID: usize = 0x00;
+const DEVICE_ID: usize = 0x02;
+const COMMAND: usize = 0x04;
+const CLASS_REVISION: usize = 0x08;
+const HEADER_TYPE: usize = 0x0e;
+const BAR0: usize = 0x10;
+
+// Type 1 (bridge) header registers.
+const PRIMARY_BUS: usize = 0x18;
+const SECONDARY_BUS: usize = 0x19;
+const SUBORDINATE_BUS: usize = 0x1a;
+const IO_BASE: usize = 0x1c;
+const IO_LIMIT: usize = 0x1d;
+const MEMORY_BASE: usize = 0x20;
+const MEMORY_LIMIT: usize = 0x22;
+const PREF_MEMORY_BASE: usize = 0x24;
+const PREF_MEMORY_LIMIT: usize = 0x26;
+const PREF_BASE_UPPER: usize = 0x28;
+const PREF_LIMIT_UPPER: usize = 0x2c;
+const IO_BASE_UPPER: usize = 0x30;
+const IO_LIMIT_UPPER: usize = 0x32;
+
+// Command register bits.
+const CMD_IO: u32 = 1 << 0;
+const CMD_MEMORY: u32 = 1 << 1;
+const CMD_BUS_MASTER: u32 = 1 << 2;
+
+// Header type values (low seven bits) and the multi-function flag.
+const HEADER_NORMAL: u8 = 0;
+const HEADER_BRIDGE: u8 = 1;
+const HEADER_MULTI_FUNCTION: u8 = 0x80;
+
+// Read from absent functions.
+const NO_VENDOR: u16 = 0xffff;
+
+// Bridge window granularity.
+const BRIDGE_MEM_ALIGN: u64 = 1 << 20;
+const BRIDGE_IO_ALIGN: u64 = 1 << 12;
+
+// I/O addresses below this are left alone (legacy ISA ports).
+const IO_ALLOC_START: u64 = 0x1000;
+
+/// A function's address: bus, device (0..32) and function (0..8).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Bdf {
+    pub(crate) bus: u8,
+    pub(crate) dev: u8,
+    pub(crate) func: u8,
+}
+
+/// What a BAR decodes.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum BarKind {
+    Io,
+    Mem32,
+    Mem64,
+}
+
+/// A base address register, sized.
+#[derive(Copy, Clone)]
+pub(crate) struct Bar {
+    /// BAR number; a 64-bit BAR also takes the next one.
+    pub(crate) index: usize,
+    pub(crate) kind: BarKind,
+    pub(crate) prefetchable: bool,
+    /// Bus address programmed into the BAR (0 = unassigned).
+    pub(crate) addr: u64,
+    /// Bytes decoded.
+    pub(crate) size: u64,
+}
+
+/// A range of bus addresses the host bridge forwards, and where the CPU sees
+/// it.
+#[derive(Copy, Clone)]
+pub(crate) struct Window {
+    pub(crate) pci: u64,
+    pub(crate) cpu: u64,
+    pub(crate) size: u64,
+}
+
+/// A PCIe host bridge with an ECAM configuration window.
+#[derive(Copy, Clone)]
+pub(crate) struct Host {
+    /// ECAM base address (of `bus_start`).
+    pub(crate) base: usize,
+    /// Bus numbers the ECAM window covers.
+    pub(crate) bus_start: u8,
+    pub(crate) bus_end: u8,
+    /// I/O port window, if any.
+    pub(crate) io: Option<Window>,
+    /// 32-bit memory window, if any.
+    pub(crate) mem: Option<Window>,
+}
+
+impl Host {
+    /// Find the host bridge in the device tree, or at QEMU virt's address if
+    /// there is no device tree.
+    pub(crate) fn find() -> Option<Host> {
+        let Some(fdt) = fdt::get() else {
+            return Some(Host {
+                base: DEFAULT_ECAM_BASE,
+                bus_start: 0,
+                bus_end: 0xff,
+                io: Some(DEFAULT_IO),
+                mem: Some(DEFAULT_MEM),
+            });
+        };
+
+        let node = fdt.find_compatible("pci-host-ecam-generic")?;
+        let (base, size) = fdt.reg(node, 0)?;
+        let buses = (size >> 20).clamp(1, 256) as u32;
+        let (bus_start, bus_end) = match fdt.property(node, "bus-range") {
+            Some(p) if p.len() >= 8 => (be32(p, 0), be32(p, 4)),
+            _ => (0, buses - 1),
+        };
+        let bus_start = bus_start.min(0xff);
+        let bus_end = bus_end.min(bus_start + buses - 1).min(0xff);
+
+        // Each `ranges` entry: a 3-cell PCI address (space code in the first
+        // cell), a parent address and a size.
+        let mut host = Host {
+            base: base as usize,
+            bus_start: bus_start as u8,
+            bus_end: bus_end as u8,
+            io: None,
+            mem: None,
+        };
+        let parent_cells = node.parent_address_cells() as usize;
+        let size_cells = fdt.property_u32(node, "#size-cells").unwrap_or(2) as usize;
+        let entry = (3 + parent_cells + size_cells) * 4;
+        if let Some(ranges) = fdt.property(node, "ranges") {
+            for e in ranges.chunks_exact(entry) {
+                let window = Window {
+                    pci: cells(&e[4..12]),
+                    cpu: cells(&e[12..12 + parent_cells * 4]),
+                    size: cells(&e[12 + parent_cells * 4..]),
+                };
+                match (be32(e, 0) >> 24) & 3 {
+                    1 => host.io = host.io.or(Some(window)),
+                    2 => host.mem = host.mem.or(Some(window)),
+                    _ => {}
+                }
+            }
+        }
+        Some(host)
+    }
+
+    /// Read `width` (1, 2 or 4) bytes of a function's configuration space.
+    /// `off` must be aligned to `width` and below `CONFIG_SIZE`.
+    pub(crate) fn read(&self, bdf: Bdf, off: usize, width: usize) -> u32 {
+        let Some(addr) = self.config_addr(bdf, off) else {
+            return u32::MAX;
+        };
+        unsafe {
+            match width {
+                1 => (addr as *const u8).read_volatile() as u32,
+                2 => (addr as *const u16).read_volatile() as u32,
+                _ => (addr as *const u32).read_volatile(),
+            }
+        }
+    }
+
+    /// Write `width` (1, 2 or 4) bytes of a function's configuration space.
+    /// `off` must be aligned to `width` and below `CONFIG_SIZE`.
+    pub(crate) fn write(&self, bdf: Bdf, off: usize, width: usize, value: u32) {
+        let Some(addr) = self.config_addr(bdf, off) else {
+            return;
+        };
+        unsafe {
+            match width {
+                1 => (addr as *mut u8).write_volatile(value as u8),
+                2 => (addr as *mut u16).write_volatile(value as u16),
+                _ => (addr as *mut u32).write_volatile(value),
+            }
+        }
+    }
+
+    /// Whether a function responds.
+    pub(crate) fn exists(&self, bdf: Bdf) -> bool {
+        self.vendor(bdf) != NO_VENDOR
+    }
+
+    pub(crate) fn vendor(&self, bdf: Bdf) -> u16 {
+        self.read(bdf, VENDOR_ID, 2) as u16
+    }
+
+    pub(crate) fn device(&self, bdf: Bdf) -> u16 {
+        self.read(bdf, DEVICE_ID, 2) as u16
+    }
+
+    /// Class code: base class, subclass and programming interface.
+    pub(crate) fn class(&self, bdf: Bdf) -> u32 {
+        self.read(bdf, CLASS_REVISION, 4) >> 8
+    }
+
+    /// Visit every function on every bus in the ECAM window. Devices behind
+    /// bridges only show up once `assign` has numbered the buses.
+    pub(crate) fn for_each_function(&self, mut f: impl FnMut(Bdf)) {
+        for bus in self.bus_start..=self.bus_end {
+            let _ = self.scan_bus(bus, |bdf| {
+                f(bdf);
+                Ok(())
+            });
+        }
+    }
+
+    /// Size a function's BARs, passing each implemented one to `f`. Decoding
+    /// is turned off while the BARs hold the sizing pattern.
+    pub(crate) fn bars(&self, bdf: Bdf, mut f: impl FnMut(&Bar)) {
+        let count = match self.header_type(bdf) {
+            HEADER_NORMAL => 6,
+            HEADER_BRIDGE => 2,
+            _ => 0,
+        };
+        let command = self.read(bdf, COMMAND, 2);
+        self.write(bdf, COMMAND, 2, command & !(CMD_IO | CMD_MEMORY));
+
+        let mut index = 0;
+        while index < count {
+            let off = BAR0 + index * 4;
+            let (orig, mask) = self.probe_bar(bdf, off);
+            if mask == 0 {
+                index += 1;
+                continue;
+            }
+
+            let bar = if orig & 1 != 0 {
+                // I/O BARs may implement only the low 16 bits.
+                let mask = mask & !3;
+                let mask = if mask & 0xffff_0000 == 0 {
+                    mask | 0xffff_0000
+                } else {
+                    mask
+                };
+                Bar {
+                    index,
+                    kind: BarKind::Io,
+                    prefetchable: false,
+                    addr: (orig & !3) as u64,
+                    size: (!mask).wrapping_add(1) as u64,
+                }
+            } else {
+                let is_64 = (orig >> 1) & 3 == 2 && index + 1 < count;
+                let (high, high_mask) = match is_64 {
+                    true => self.probe_bar(bdf, off + 4),
+                    false => (0, u32::MAX),
+                };
+                let mask = ((high_mask as u64) << 32) | (mask & !0xf) as u64;
+                Bar {
+                    index,
+                    kind: if is_64 {
+                        BarKind::Mem64
+                    } else {
+                        BarKind::Mem32
+                    },
+                    prefetchable: orig & (1 << 3) != 0,
+                    addr: ((high as u64) << 32) | (orig & !0xf) as u64,
+                    size: (!mask).wrapping_add(1),
+                }
+            };
+            index += if bar.kind == BarKind::Mem64 { 2 } else { 1 };
+            f(&bar);
+        }
+
+        self.write(bdf, COMMAND, 2, command);
+    }
+
+    /// Where the CPU sees a BAR's bus address.
+    pub(crate) fn cpu_addr(&self, bar: &Bar) -> u64 {
+        let window = match bar.kind {
+            BarKind::Io => self.io,
+            _ => self.mem,
+        };
+        match window {
+            Some(w) if bar.addr >= w.pci && bar.addr - w.pci < w.size => w.cpu + (bar.addr - w.pci),
+            _ => bar.addr,
+        }
+    }
+
+    /// Give every BAR an address in the bridge's windows, number the buses
+    /// behind PCI bridges (and open their windows), and turn on decoding and
+    /// bus mastering. Everything is reassigned from scratch. Returns the
+    /// number of BARs assigned and the last bus number used.
+    pub(crate) fn assign(&self) -> Result<(usize, u8), &'static str> {
+        let mut state = Assign {
+            mem: Alloc::new(self.mem),
+            io: Alloc::new(self.io),
+            next_bus: self.bus_start as u16 + 1,
+            bars: 0,
+        };
+        self.assign_bus(self.bus_start, &mut state)?;
+        Ok((state.bars, (state.next_bus - 1) as u8))
+    }
+}
+
+/// A short name for a base class.
+pub(crate) fn class_name(class: u32) -> &'static str {
+    match class >> 16 {
+        0x00 => "unclassified",
+        0x01 => "storage",
+        0x02 => "network",
+        0x03 => "display",
+        0x04 => "multimedia",
+        0x05 => "memory",
+        0x06 => "bridge",
+        0x07 => "communication",
+        0x08 => "system",
+        0x09 => "input",
+        0x0c => "serial bus",
+        0x0d => "wireless",
+        0xff => "vendor-specific",
+        _ => "other",
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+impl Host {
+    // Address of a configuration register, if the bus is in the window.
+    fn config_addr(&self, bdf: Bdf, off: usize) -> Option<usize> {
+        if bdf.bus < self.bus_start || bdf.bus > self.bus_end || bdf.dev >= 32 || bdf.func >= 8 {
+            return None;
+        }
+        let bus = (bdf.bus - self.bus_start) as usize;
+        Some(
+            self.base
+                + ((bus << 20) | ((bdf.dev as usize) << 15) | ((bdf.func as usize) << 12))
+                + (off % CONFIG_SIZE),
+        )
+    }
+
+    // Header type without the multi-function flag.
+    fn header_type(&self, bdf: Bdf) -> u8 {
+        self.read(bdf, HEADER_TYPE, 1) as u8 & !HEADER_MULTI_FUNCTION
+    }
+
+    // Visit the functions on one bus, stopping at the first error.
+    fn scan_bus(
+        &self,
+        bus: u8,
+        mut f: impl FnMut(Bdf) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        for dev in 0..32 {
+            let bdf = Bdf { bus, dev, func: 0 };
+            if !self.exists(bdf) {
+                continue;
+            }
+            f(bdf)?;
+            if self.read(bdf, HEADER_TYPE, 1) as u8 & HEADER_MULTI_FUNCTION == 0 {
+                continue;
+            }
+            for func in 1..8 {
+                let bdf = Bdf { bus, dev, func };
+                if self.exists(bdf) {
+                    f(bdf)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // A BAR's value and the bits that stick when all ones are written to it.
+    fn probe_bar(&self, bdf: Bdf, off: usize) -> (u32, u32) {
+        let orig = self.read(bdf, off, 4);
+        self.write(bdf, off, 4, u32::MAX);
+        let mask = self.read(bdf, off, 4);
+        self.write(bdf, off, 4, orig);
+        (orig, mask)
+    }
+
+    // Assign the functions on `bus` and, depth first, the buses behind them.
+    fn assign_bus(&self, bus: u8, state: &mut Assign) -> Result<(), &'static str> {
+        self.scan_bus(bus, |bdf| {
+            self.assign_bars(bdf, state)?;
+            if self.header_type(bdf) == HEADER_BRIDGE {
+                self.assign_bridge(bdf, state)?;
+            }
+            let command = self.read(bdf, COMMAND, 2);
+            self.write(
+                bdf,
+                COMMAND,
+                2,
+                command | CMD_IO | CMD_MEMORY | CMD_BUS_MASTER,
+            );
+            Ok(())
+        })
+    }
+
+    fn assign_bars(&self, bdf: Bdf, state: &mut Assign) -> Result<(), &'static str> {
+        let mut result = Ok(());
+        self.bars(bdf, |bar| {
+            if result.is_err() {
+                return;
+            }
+            let addr = match bar.kind {
+                BarKind::Io => state.io.take(IO_ALLOC_START, bar.size),
+                _ => state.mem.take(0, bar.size),
+            };
+            let addr = match addr {
+                Some(a) if bar.kind != BarKind::Mem32 || a + bar.size <= 1 << 32 => a,
+                _ => {
+                    result = Err(match bar.kind {
+                        BarKind::Io => "out of PCI I/O space",
+                        _ => "out of PCI memory space",
+                    });
+                    return;
+                }
+            };
+            let off = BAR0 + bar.index * 4;
+            self.write(bdf, off, 4, addr as u32);
+            if bar.kind == BarKind::Mem64 {
+                self.write(bdf, off + 4, 4, (addr >> 32) as u32);
+            }
+            state.bars += 1;
+        });
+        result
+    }
+
+    // Number the bus behind a bridge, assign it, and open the bridge's
+    // windows over what its side was given. Everything goes through the
+    // (non-prefetchable) memory window, which is closed if unused.
+    fn assign_bridge(&self, bdf: Bdf, state: &mut Assign) -> Result<(), &'static str> {
+        let secondary = state.next_bus;
+        if secondary > self.bus_end as u16 {
+            return Err("out of PCI bus numbers");
+        }
+        state.next_bus += 1;
+        let secondary = secondary as u8;
+        self.write(bdf, PRIMARY_BUS, 1, bdf.bus as u32);
+        self.write(bdf, SECONDARY_BUS, 1, secondary as u32);
+        // Forward everything below while the far side is scanned.
+        self.write(bdf, SUBORDINATE_BUS, 1, self.bus_end as u32);
+
+        state.mem.align(BRIDGE_MEM_ALIGN);
+        state.io.align(BRIDGE_IO_ALIGN);
+        let (mem_start, io_start) = (state.mem.next, state.io.next);
+        self.assign_bus(secondary, state)?;
+        self.write(bdf, SUBORDINATE_BUS, 1, state.next_bus as u32 - 1);
+        state.mem.align(BRIDGE_MEM_ALIGN);
+        state.io.align(BRIDGE_IO_ALIGN);
+
+        let (mem_base, mem_limit) = match state.mem.next > mem_start {
+            true => (mem_start >> 16, (state.mem.next - 1) >> 16),
+            false => (0xfff0, 0),
+        };
+        self.write(bdf, MEMORY_BASE, 2, mem_base as u32 & 0xfff0);
+        self.write(bdf, MEMORY_LIMIT, 2, mem_limit as u32 & 0xfff0);
+
+        let (io_base, io_limit) = match state.io.next > io_start {
+            true => (io_start, state.io.next - 1),
+            false => (0xf000, 0),
+        };
+        self.write(bdf, IO_BASE, 1, (io_base >> 8) as u32 & 0xf0);
+        self.write(bdf, IO_LIMIT, 1, (io_limit >> 8) as u32 & 0xf0);
+        self.write(bdf, IO_BASE_UPPER, 2, (io_base >> 16) as u32);
+        self.write(bdf, IO_LIMIT_UPPER, 2, (io_limit >> 16) as u32);
+
+        self.write(bdf, PREF_MEMORY_BASE, 2, 0xfff0);
+        self.write(bdf, PREF_MEMORY_LIMIT, 2, 0);
+        self.write(bdf, PREF_BASE_UPPER, 4, 0);
+        self.write(bdf, PREF_LIMIT_UPPER, 4, 0);
+        Ok(())
+    }
+}
+
+// State of an `assign` pass.
+struct Assign {
+    mem: Alloc,
+    io: Alloc,
+    next_bus: u16,
+    bars: usize,
+}
+
+// Bump allocator over a window's bus addresses.
+struct Alloc {
+    next: u64,
+    end: u64,
+}
+
+impl Alloc {
+    fn new(window: Option<Window>) -> Alloc {
+        match window {
+            Some(w) => Alloc {
+                next: w.pci,
+                end: w.pci + w.size,
+            },
+            None => Alloc { next: 0, end: 0 },
+        }
+    }
+
+    // Take `size` bytes (a power of two), naturally aligned and at or above
+    // `min`.
+    fn take(&mut self, min: u64, size: u64) -> Option<u64> {
+        let addr = self.next.max(min).checked_next_multiple_of(size)?;
+        let end = addr.checked_add(size)?;
+        if end > self.end {
+            return None;
+        }
+        self.next = end;
+        Some(addr)
+    }
+
+    fn align(&mut self, align: u64) {
+        self.next = self.next.next_multiple_of(align).min(self.end);
+    }
+}
+
+// Read a big-endian u32 at `off`.
+fn be32(b: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes([b[off], b[off + 1], b[off + 2], b[off + 3]])
+}
+
+// A big-endian value of one or two cells.
+fn cells(b: &[u8]) -> u64 {
+    b.chunks_exact(4)
+        .fold(0, |v, c| (v << 32) | be32(c, 0) as u64)
+}
@@ -0,0 +1,236 @@
+use crate::{csr, fdt, system, timer};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// Platform-Level Interrupt Controller (PLIC)
+// -----------------------------------------------------------------------------
+
+// The PLIC routes device interrupt lines ("sources", numbered from 1) to
+// contexts: one per hart and privilege mode that can take external
+// interrupts. A source reaches a context when it is enabled there and its
+// priority is above the context's threshold; the context then claims it (the
+// claim register returns the source) and completes it when done.
+//
+// The monitor runs with interrupts disabled, so it never takes external
+// interrupts. Instead `poll` claims and completes whatever is pending for
+// the monitor's context while the REPL waits for input, counting each source.
+
+// QEMU virt's PLIC, when there is no device tree.
+const DEFAULT_BASE: usize = 0x0c00_0000;
+const DEFAULT_SOURCES: u32 = 95;
+
+/// Most sources a PLIC can have (source 0 means "none").
+pub(crate) const MAX_SOURCES: usize = 1024;
+
+/// Highest priority QEMU's PLIC implements.
+pub(crate) const MAX_PRIORITY: u32 = 7;
+
+// Register offsets.
+const PRIORITY: usize = 0x0000;
+const PENDING: usize = 0x1000;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const THRESHOLD: usize = 0x20_0000;
+const CLAIM: usize = 0x20_0004;
+const CONTEXT_STRIDE: usize = 0x1000;
+
+// How often `poll` looks at the claim register.
+const POLL_MS: u64 = 10;
+
+// The PLIC found at boot (base 0 = none).
+static BASE: AtomicUsize = AtomicUsize::new(0);
+static SOURCES: AtomicU32 = AtomicU32::new(0);
+static CONTEXT: AtomicUsize = AtomicUsize::new(0);
+
+// Claims per source since boot.
+static COUNTS: [AtomicU32; MAX_SOURCES] = [const { AtomicU32::new(0) }; MAX_SOURCES];
+
+// When `poll` next looks.
+static NEXT_POLL: AtomicU64 = AtomicU64::new(0);
+
+/// The monitor's view of the PLIC: its registers and the context of the boot
+/// hart in the monitor's privilege mode.
+#[derive(Copy, Clone)]
+pub(crate) struct Plic {
+    /// MMIO base address.
+    pub(crate) base: usize,
+    /// Number of sources (1..=sources are valid).
+    pub(crate) sources: u32,
+    /// The monitor's context.
+    pub(crate) context: usize,
+}
+
+/// Find the PLIC and the monitor's context in it. Called once at boot, after
+/// `fdt::init`.
+pub(crate) fn init() {
+    let hart = system::boot_hart();
+    let found = match fdt::get() {
+        Some(fdt) => find(&fdt, hart),
+        // QEMU virt gives each hart a machine context, then a supervisor one.
+        None => Some(Plic {
+            base: DEFAULT_BASE,
+            sources: DEFAULT_SOURCES,
+            context: hart * 2 + (csr::IRQ_EXTERNAL == 9) as usize,
+        }),
+    };
+    if let Some(plic) = found {
+        SOURCES.store(plic.sources, Ordering::Relaxed);
+        CONTEXT.store(plic.context, Ordering::Relaxed);
+        BASE.store(plic.base, Ordering::Relaxed);
+    }
+}
+
+/// The PLIC found at boot, if any.
+pub(crate) fn get() -> Option<Plic> {
+    match BASE.load(Ordering::Relaxed) {
+        0 => None,
+        base => Some(Plic {
+            base,
+            sources: SOURCES.load(Ordering::Relaxed),
+            context: CONTEXT.load(Ordering::Relaxed),
+        }),
+    }
+}
+
+/// How many times `source` has been claimed since boot.
+pub(crate) fn count(source: u32) -> u32 {
+    COUNTS
+        .get(source as usize)
+        .map_or(0, |c| c.load(Ordering::Relaxed))
+}
+
+/// Claim and complete everything pending for the monitor's context, counting
+/// each source. Rate-limited, so it can be called from a busy loop; a source
+/// whose device holds its line asserted counts once per poll.
+pub(crate) fn poll() {
+    let now = timer::now();
+    if now < NEXT_POLL.load(Ordering::Relaxed) {
+        return;
+    }
+    NEXT_POLL.store(now + timer::ms_to_ticks(POLL_MS), Ordering::Relaxed);
+
+    let Some(plic) = get() else {
+        return;
+    };
+    // A level-triggered source pends again as soon as it is completed, so
+    // take at most one claim per source.
+    for _ in 0..plic.sources {
+        let Some(source) = plic.claim() else {
+            break;
+        };
+        plic.complete(source);
+    }
+}
+
+impl Plic {
+    /// A source's priority (0 = never delivered).
+    pub(crate) fn priority(&self, source: u32) -> u32 {
+        self.read(PRIORITY + source as usize * 4)
+    }
+
+    pub(crate) fn set_priority(&self, source: u32, priority: u32) {
+        self.write(PRIORITY + source as usize * 4, priority);
+    }
+
+    /// Whether a source is waiting to be claimed (by any context).
+    pub(crate) fn is_pending(&self, source: u32) -> bool {
+        let word = self.read(PENDING + (source as usize / 32) * 4);
+        word & (1 << (source % 32)) != 0
+    }
+
+    /// Whether a source is enabled for the monitor's context.
+    pub(crate) fn is_enabled(&self, source: u32) -> bool {
+        self.read(self.enable_word(source)) & (1 << (source % 32)) != 0
+    }
+
+    /// Enable or disable a source for the monitor's context.
+    pub(crate) fn set_enabled(&self, source: u32, enabled: bool) {
+        let off = self.enable_word(source);
+        let bit = 1 << (source % 32);
+        let word = self.read(off);
+        self.write(off, if enabled { word | bit } else { word & !bit });
+    }
+
+    /// The monitor's context's threshold: only sources with a higher priority
+    /// are delivered.
+    pub(crate) fn threshold(&self) -> u32 {
+        self.read(THRESHOLD + self.context * CONTEXT_STRIDE)
+    }
+
+    pub(crate) fn set_threshold(&self, threshold: u32) {
+        self.write(THRESHOLD + self.context * CONTEXT_STRIDE, threshold);
+    }
+
+    /// Claim the highest-priority pending source for the monitor's context,
+    /// counting it.
+    pub(crate) fn claim(&self) -> Option<u32> {
+        let source = self.read(CLAIM + self.context * CONTEXT_STRIDE);
+        let counter = COUNTS.get(source as usize).filter(|_| source != 0)?;
+        counter.fetch_add(1, Ordering::Relaxed);
+        Some(source)
+    }
+
+    /// Tell the PLIC the monitor is done with a claimed source.
+    pub(crate) fn complete(&self, source: u32) {
+        self.write(CLAIM + self.context * CONTEXT_STRIDE, source);
+    }
+
+    // Offset of the enable word holding `source`'s bit for our context.
+    fn enable_word(&self, source: u32) -> usize {
+        ENABLE + self.context * ENABLE_STRIDE + (source as usize / 32) * 4
+    }
+
+    fn read(&self, off: usize) -> u32 {
+        unsafe { ((self.base + off) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, off: usize, value: u32) {
+        unsafe { ((self.base + off) as *mut u32).write_volatile(value) }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Find the PLIC in the device tree. Its `interrupts-extended` lists the
+// contexts in order as (hart interrupt controller, interrupt number) pairs;
+// ours is the boot hart's controller with our mode's external interrupt.
+fn find(fdt: &fdt::Fdt, hart: usize) -> Option<Plic> {
+    let node = fdt
+        .find_compatible("riscv,plic0")
+        .or_else(|| fdt.find_compatible("sifive,plic-1.0.0"))?;
+    let base = fdt.reg(node, 0)?.0 as usize;
+    let sources = fdt
+        .property_u32(node, "riscv,ndev")
+        .unwrap_or(DEFAULT_SOURCES)
+        .min(MAX_SOURCES as u32 - 1);
+
+    // "/cpus/cpu@<hart in hex>/interrupt-controller"
+    let mut path = [0u8; 64];
+    let mut len = 0;
+    let mut push = |s: &[u8]| {
+        path[len..len + s.len()].copy_from_slice(s);
+        len += s.len();
+    };
+    push(b"/cpus/cpu@");
+    let digits = (hart.max(1).ilog2() / 4 + 1) as usize;
+    for i in (0..digits).rev() {
+        push(&[crate::hex::hex_digit(((hart >> (i * 4)) & 0xf) as u8)]);
+    }
+    push(b"/interrupt-controller");
+    let path = core::str::from_utf8(&path[..len]).ok()?;
+    let intc = fdt.find_node(path)?;
+    let phandle = fdt.property_u32(intc, "phandle")?;
+
+    let contexts = fdt.property(node, "interrupts-extended")?;
+    let context = contexts.chunks_exact(8).position(|pair| {
+        let cell = |i: usize| u32::from_be_bytes([pair[i], pair[i + 1], pair[i + 2], pair[i + 3]]);
+        cell(0) == phandle && cell(4) as usize == csr::IRQ_EXTERNAL
+    })?;
+    Some(Plic {
+        base,
+        sources,
+        context,
+    })
+}
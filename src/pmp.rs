@@ -0,0 +1,199 @@
+use crate::csr;
+
+// -----------------------------------------------------------------------------
+// Physical Memory Protection (PMP)
+// -----------------------------------------------------------------------------
+
+/// Most entries the architecture allows (harts may implement fewer).
+pub(crate) const MAX_ENTRIES: usize = 64;
+
+/// Entries `boot` and `ujump` reprogram for every program they start (0 and
+/// 1, which take priority over the rest).
+pub(crate) const LAUNCH_ENTRIES: usize = 2;
+
+/// Entry permission bits, and the lock bit (which also binds machine mode and
+/// holds until reset).
+pub(crate) const R: u8 = 1 << 0;
+pub(crate) const W: u8 = 1 << 1;
+pub(crate) const X: u8 = 1 << 2;
+pub(crate) const RWX: u8 = R | W | X;
+pub(crate) const L: u8 = 1 << 7;
+
+/// Address-matching mode field (bits 3..4).
+pub(crate) const A_MASK: u8 = 3 << 3;
+pub(crate) const A_OFF: u8 = 0;
+pub(crate) const A_TOR: u8 = 1 << 3;
+pub(crate) const A_NA4: u8 = 2 << 3;
+pub(crate) const A_NAPOT: u8 = 3 << 3;
+
+// CSR numbers of pmpcfg0 (entries 0..7 on RV64) and pmpaddr0.
+const CSR_PMPCFG0: usize = 0x3a0;
+const CSR_PMPADDR0: usize = 0x3b0;
+
+// -----------------------------------------------------------------------------
+// Entry Programming
+// -----------------------------------------------------------------------------
+
+/// Program entry `index` to cover the naturally aligned range
+/// `base..base + size` with `perms`. Returns false if the range cannot be
+/// NAPOT-encoded (size not a power of two >= 8, or base not size-aligned).
+pub(crate) fn set_napot(index: usize, base: usize, size: usize, perms: u8) -> bool {
+    let Some(addr) = napot_addr(base, size) else {
+        return false;
+    };
+    write_addr(index, addr);
+    write_cfg(index, A_NAPOT | perms);
+    true
+}
+
+/// Program entry `index` to cover the whole address space with `perms`.
+pub(crate) fn set_all(index: usize, perms: u8) {
+    write_addr(index, usize::MAX);
+    write_cfg(index, A_NAPOT | perms);
+}
+
+/// Program entry `index` to cover `base..base + size` with `perms` (which may
+/// include [`L`]), as NA4 for a 4-byte range and NAPOT otherwise. Returns
+/// false if the range cannot be encoded that way.
+pub(crate) fn set_range(index: usize, base: usize, size: usize, perms: u8) -> bool {
+    let (addr, mode) = match size {
+        4 if base.is_multiple_of(4) => (base >> 2, A_NA4),
+        _ => match napot_addr(base, size) {
+            Some(addr) => (addr, A_NAPOT),
+            None => return false,
+        },
+    };
+    write_addr(index, addr);
+    write_cfg(index, mode | perms);
+    true
+}
+
+/// Program entry `index` as top-of-range: it covers from the previous entry's
+/// address (or 0 for entry 0) up to, not including, `top`.
+pub(crate) fn set_tor(index: usize, top: usize, perms: u8) {
+    write_addr(index, top >> 2);
+    write_cfg(index, A_TOR | perms);
+}
+
+/// Turn entry `index` off, so it no longer matches any address.
+pub(crate) fn clear(index: usize) {
+    write_cfg(index, 0);
+}
+
+/// Decode entry `index` (given its configuration and address registers, and
+/// the previous entry's address register for TOR) into the inclusive address
+/// range it matches. Returns None for an entry that is off.
+pub(crate) fn decode_range(cfg: u8, addr: usize, prev_addr: usize) -> Option<(usize, usize)> {
+    match cfg & A_MASK {
+        A_TOR => {
+            let (start, top) = (prev_addr << 2, addr << 2);
+            // An empty range (top <= start) matches nothing.
+            (top > start).then(|| (start, top - 1))
+        }
+        A_NA4 => Some((addr << 2, (addr << 2) + 3)),
+        A_NAPOT => {
+            // Trailing ones select the size: 2^(ones + 3) bytes.
+            let ones = addr.trailing_ones() as usize;
+            if ones + 3 >= usize::BITS as usize - 2 {
+                return Some((0, usize::MAX));
+            }
+            let size = 1usize << (ones + 3);
+            let base = (addr & !((1 << ones) - 1)) << 2;
+            Some((base, base + (size - 1)))
+        }
+        _ => None,
+    }
+}
+
+/// Encode a NAPOT range as a pmpaddr value.
+pub(crate) fn napot_addr(base: usize, size: usize) -> Option<usize> {
+    if size < 8 || !size.is_power_of_two() || !base.is_multiple_of(size) {
+        return None;
+    }
+    Some((base >> 2) | ((size >> 3) - 1))
+}
+
+// -----------------------------------------------------------------------------
+// CSR Access
+// -----------------------------------------------------------------------------
+
+// Entries on harts that do not implement them raise an illegal-instruction
+// exception when accessed; callers that need to cope with that go through
+// `trap::probe`.
+
+/// Read the configuration byte of entry `index`.
+pub(crate) fn read_cfg(index: usize) -> u8 {
+    (read_cfg_reg(index / 8) >> ((index % 8) * 8)) as u8
+}
+
+/// Write the configuration byte of entry `index`.
+pub(crate) fn write_cfg(index: usize, cfg: u8) {
+    let shift = (index % 8) * 8;
+    let reg = read_cfg_reg(index / 8) & !(0xff << shift);
+    write_cfg_reg(index / 8, reg | ((cfg as usize) << shift));
+}
+
+/// Read the address register of entry `index`.
+pub(crate) fn read_addr(index: usize) -> usize {
+    // CSR numbers must be immediates, so each register is spelled out.
+    macro_rules! read_pmpaddr {
+        ($($i:literal),*) => {
+            match index {
+                $($i => csr::read!(CSR_PMPADDR0 + $i),)*
+                _ => 0,
+            }
+        };
+    }
+    read_pmpaddr!(
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+        48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63
+    )
+}
+
+/// Write the address register of entry `index`.
+pub(crate) fn write_addr(index: usize, v: usize) {
+    macro_rules! write_pmpaddr {
+        ($($i:literal),*) => {
+            match index {
+                $($i => csr::write!(CSR_PMPADDR0 + $i, v),)*
+                _ => {}
+            }
+        };
+    }
+    write_pmpaddr!(
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24,
+        25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47,
+        48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63
+    )
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Read pmpcfg{2 * reg} (RV64 only has the even-numbered pmpcfg registers).
+fn read_cfg_reg(reg: usize) -> usize {
+    macro_rules! read_pmpcfg {
+        ($($i:literal),*) => {
+            match reg {
+                $($i => csr::read!(CSR_PMPCFG0 + 2 * $i),)*
+                _ => 0,
+            }
+        };
+    }
+    read_pmpcfg!(0, 1, 2, 3, 4, 5, 6, 7)
+}
+
+// Write pmpcfg{2 * reg}.
+fn write_cfg_reg(reg: usize, v: usize) {
+    macro_rules! write_pmpcfg {
+        ($($i:literal),*) => {
+            match reg {
+                $($i => csr::write!(CSR_PMPCFG0 + 2 * $i, v),)*
+                _ => {}
+            }
+        };
+    }
+    write_pmpcfg!(0, 1, 2, 3, 4, 5, 6, 7)
+}
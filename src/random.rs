@@ -0,0 +1,74 @@
+use crate::{timer, virtio::rng};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// -----------------------------------------------------------------------------
+// Random Memory Fills
+// -----------------------------------------------------------------------------
+
+// Fills come from the virtio entropy device when there is one, and otherwise
+// from a xorshift64* generator seeded from `mtime`. Seeding the generator
+// explicitly makes fills use it even with a device, so they can be repeated.
+
+// Generator state; never 0 once in use.
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+// Whether the user seeded the generator.
+static SEEDED: AtomicBool = AtomicBool::new(false);
+
+// Stands in for a seed of 0, which xorshift cannot leave.
+const ZERO_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Where a fill's bytes came from.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Source {
+    Device,
+    Prng,
+}
+
+/// Seed the generator and use it for fills from now on, or with `None` go
+/// back to the entropy device.
+pub(crate) fn seed(seed: Option<u64>) {
+    if let Some(seed) = seed {
+        STATE.store(nonzero(seed), Ordering::Relaxed);
+    }
+    SEEDED.store(seed.is_some(), Ordering::Relaxed);
+}
+
+/// Whether fills use the generator because it was seeded.
+pub(crate) fn is_seeded() -> bool {
+    SEEDED.load(Ordering::Relaxed)
+}
+
+/// Fill `len` bytes of memory at `addr` with random bytes.
+pub(crate) fn fill(addr: usize, len: usize) -> Result<Source, &'static str> {
+    if !is_seeded() && rng::available() {
+        rng::fill(addr, len)?;
+        return Ok(Source::Device);
+    }
+
+    if STATE.load(Ordering::Relaxed) == 0 {
+        STATE.store(nonzero(timer::now()), Ordering::Relaxed);
+    }
+    let dst = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+    let mut x = STATE.load(Ordering::Relaxed);
+    for chunk in dst.chunks_mut(8) {
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        let v = x.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        chunk.copy_from_slice(&v.to_le_bytes()[..chunk.len()]);
+    }
+    STATE.store(x, Ordering::Relaxed);
+    Ok(Source::Prng)
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+fn nonzero(seed: u64) -> u64 {
+    match seed {
+        0 => ZERO_SEED,
+        s => s,
+    }
+}
@@ -1,14 +1,20 @@
+#[cfg(not(feature = "supervisor"))]
+use crate::pmp;
 use crate::{
-    INFO_BANNER, STACK_BOTTOM, STACK_TOP, hex, memory,
+    INFO_BANNER, STACK_BOTTOM, STACK_TOP, checksum, console, csr, elf, fdt, fwcfg, hex, memory,
+    memtest, net, paging, pci, plic, random,
     repl::{
+        expr, history,
         meminfo::{
             print_memory_dump, print_memory_dump_as_ascii, print_stack_range,
             print_valid_address_ranges,
         },
         runner::{FINDME, get_current_addr, set_current_addr},
+        symbols, vars,
     },
-    system,
-    uart::{clear_screen, print, print_hex_u32, println},
+    rtc, semihost, system, trap, uart,
+    uart::{clear_screen, print, print_dec_u64, print_hex_u32, print_hex_u64, println},
+    virtio,
 };
 
 // -----------------------------------------------------------------------------
@@ -22,17 +28,55 @@ pub(crate) fn handle_command(line: &[u8]) {
         return;
     };
 
+    // After `sym paste`, lines are symbols until an empty one.
+    if symbols::pasting() {
+        sym_paste_line(s.trim());
+        return;
+    }
+
     match parse_command(s.trim()) {
         None => {}
         Some(Command::Help) => cmd_help(),
         Some(Command::Info) => cmd_info(),
         Some(Command::Clear) => cmd_clear(),
         Some(Command::Poweroff) => cmd_poweroff(),
+        Some(Command::Reboot) => cmd_reboot(),
+        Some(Command::Exit { code }) => cmd_exit(code),
         Some(Command::AddrGet) => cmd_addr_get(),
         Some(Command::AddrSet { addr }) => cmd_addr_set(addr),
         Some(Command::Write { start, bytes, len }) => cmd_write(start, &bytes[..len]),
         Some(Command::Dump { start, end, ascii }) => cmd_dump(start, end, ascii),
-        Some(Command::Jump { addr }) => cmd_jump(addr),
+        Some(Command::Jump { addr, timeout_ms }) => cmd_jump(addr, timeout_ms),
+        Some(Command::Boot { addr, dtb }) => cmd_boot(addr, dtb),
+        Some(Command::Ujump { addr, window }) => cmd_ujump(addr, window),
+        Some(Command::Pt(pt)) => cmd_pt(pt),
+        Some(Command::Pmp(pmp)) => cmd_pmp(pmp),
+        Some(Command::Blk(blk)) => cmd_blk(blk),
+        Some(Command::Tftp { addr, filename }) => cmd_tftp(addr, filename),
+        Some(Command::Fwcfg(fwcfg)) => cmd_fwcfg(fwcfg),
+        Some(Command::Semihost(sh)) => cmd_sh(sh),
+        Some(Command::Pci(pci)) => cmd_pci(pci),
+        Some(Command::Date { set }) => cmd_date(set),
+        Some(Command::Rand(rand)) => cmd_rand(rand),
+        Some(Command::Irq(irq)) => cmd_irq(irq),
+        Some(Command::Memtest {
+            start,
+            end,
+            pattern,
+            test,
+            passes,
+        }) => cmd_memtest(start, end, pattern, test, passes),
+        Some(Command::Checksum { kind, start, end }) => cmd_checksum(kind, start, end),
+        Some(Command::Calc { value }) => cmd_calc(value),
+        Some(Command::Set { name, value }) => cmd_set(name, value),
+        Some(Command::Unset { name }) => cmd_unset(name),
+        Some(Command::Vars) => cmd_vars(),
+        Some(Command::Sym(sym)) => cmd_sym(sym),
+        Some(Command::History) => cmd_history(),
+        Some(Command::Continue) => cmd_continue(),
+        Some(Command::Regs) => cmd_regs(),
+        Some(Command::Baud { rate }) => cmd_baud(rate),
+        Some(Command::Harts) => cmd_harts(),
         Some(Command::Noop) => {}
         Some(Command::Unknown) => println("unknown command (try 'help')"),
     }
@@ -43,11 +87,15 @@ pub(crate) fn handle_command(line: &[u8]) {
 // -----------------------------------------------------------------------------
 
 #[derive(Copy, Clone)]
-enum Command {
+enum Command<'a> {
     Help,
     Info,
     Clear,
     Poweroff,
+    Reboot,
+    Exit {
+        code: u16,
+    },
     AddrGet,
     AddrSet {
         addr: usize,
@@ -64,12 +112,183 @@ enum Command {
     },
     Jump {
         addr: usize,
+        timeout_ms: Option<u64>,
+    },
+    Boot {
+        addr: usize,
+        dtb: Option<usize>,
+    },
+    Ujump {
+        addr: usize,
+        window: Option<(usize, usize)>,
+    },
+    Pt(PtCommand),
+    Pmp(PmpCommand),
+    Blk(BlkCommand),
+    Tftp {
+        addr: usize,
+        filename: &'a str,
+    },
+    Fwcfg(FwcfgCommand<'a>),
+    Semihost(ShCommand<'a>),
+    Pci(PciCommand),
+    Date {
+        set: Option<u64>,
+    },
+    Rand(RandCommand),
+    Irq(IrqCommand),
+    Memtest {
+        start: usize,
+        end: usize,
+        // Data for the fill and address-line tests.
+        pattern: u64,
+        // None = all tests.
+        test: Option<memtest::Test>,
+        // 0 = until Ctrl+C.
+        passes: u32,
+    },
+    Checksum {
+        kind: checksum::Kind,
+        start: usize,
+        end: usize,
+    },
+    Calc {
+        value: u64,
+    },
+    Set {
+        name: &'a str,
+        value: u64,
+    },
+    Unset {
+        name: &'a str,
     },
+    Vars,
+    Sym(SymCommand<'a>),
+    History,
+    Continue,
+    Regs,
+    Baud {
+        rate: Option<u32>,
+    },
+    Harts,
     Noop,
     Unknown,
 }
 
-fn parse_command(cmd: &str) -> Option<Command> {
+#[derive(Copy, Clone)]
+enum PtCommand {
+    Info,
+    New {
+        mode: paging::Mode,
+        pool: usize,
+    },
+    Map {
+        va: usize,
+        pa: usize,
+        size: usize,
+        flags: u64,
+    },
+    Walk {
+        va: usize,
+    },
+    Dump,
+    Satp {
+        on: Option<bool>,
+    },
+}
+
+#[derive(Copy, Clone)]
+#[cfg_attr(feature = "supervisor", allow(dead_code))]
+enum PmpCommand {
+    Show,
+    Off {
+        index: usize,
+    },
+    Range {
+        index: usize,
+        base: usize,
+        size: usize,
+        perms: u8,
+    },
+    Tor {
+        index: usize,
+        top: usize,
+        perms: u8,
+    },
+}
+
+#[derive(Copy, Clone)]
+enum BlkCommand {
+    Info,
+    Read { lba: u64, count: u64, addr: usize },
+    Write { lba: u64, count: u64, addr: usize },
+}
+
+#[derive(Copy, Clone)]
+enum FwcfgCommand<'a> {
+    List,
+    Load { name: &'a str, addr: usize },
+}
+
+#[derive(Copy, Clone)]
+enum ShCommand<'a> {
+    Load {
+        path: &'a str,
+        addr: usize,
+    },
+    Save {
+        path: &'a str,
+        addr: usize,
+        len: usize,
+    },
+    Exit {
+        code: u8,
+    },
+}
+
+#[derive(Copy, Clone)]
+enum PciCommand {
+    List,
+    Read {
+        bdf: pci::Bdf,
+        off: usize,
+        width: usize,
+    },
+    Write {
+        bdf: pci::Bdf,
+        off: usize,
+        width: usize,
+        value: u32,
+    },
+    Assign,
+}
+
+#[derive(Copy, Clone)]
+enum RandCommand {
+    Fill { start: usize, end: usize },
+    Seed { seed: Option<u64> },
+}
+
+#[derive(Copy, Clone)]
+enum IrqCommand {
+    List,
+    Enable { source: u32, priority: u32 },
+    Disable { source: u32 },
+    Threshold { value: u32 },
+}
+
+#[derive(Copy, Clone)]
+enum SymCommand<'a> {
+    Summary,
+    List,
+    Clear,
+    Paste,
+    Load { addr: usize },
+    // A symbol name, or an expression for an address.
+    Lookup(&'a str),
+}
+
+fn parse_command(cmd: &str) -> Option<Command<'_>> {
     let cmd = cmd.trim();
     if cmd.is_empty() {
         return None;
@@ -80,10 +299,44 @@ fn parse_command(cmd: &str) -> Option<Command> {
         "info" => Some(Command::Info),
         "clear" | "reset" => Some(Command::Clear),
         "poweroff" | "q" => Some(Command::Poweroff),
+        "reboot" => Some(Command::Reboot),
+        "continue" | "c" => Some(Command::Continue),
+        "regs" => Some(Command::Regs),
+        "harts" => Some(Command::Harts),
+        "pmp" => Some(Command::Pmp(PmpCommand::Show)),
+        "lspci" => Some(Command::Pci(PciCommand::List)),
+        "date" => Some(Command::Date { set: None }),
+        "irq" => Some(Command::Irq(IrqCommand::List)),
+        "vars" => Some(Command::Vars),
+        "sym" => Some(Command::Sym(SymCommand::Summary)),
+        "history" => Some(Command::History),
         _ => {
             let first_word = cmd.split_whitespace().next().unwrap_or("");
             match first_word {
                 "jump" => parse_jump_cmd(cmd),
+                "boot" => parse_boot_cmd(cmd),
+                "ujump" => parse_ujump_cmd(cmd),
+                "pt" => parse_pt_cmd(cmd),
+                "pmp" => parse_pmp_cmd(cmd),
+                "blk" => parse_blk_cmd(cmd),
+                "tftp" => parse_tftp_cmd(cmd),
+                "fwcfg" => parse_fwcfg_cmd(cmd),
+                "sh" => parse_sh_cmd(cmd),
+                "pci" => parse_pci_cmd(cmd),
+                "date" => parse_date_cmd(cmd),
+                "rand" => parse_rand_cmd(cmd),
+                "irq" => parse_irq_cmd(cmd),
+                "memtest" => parse_memtest_cmd(cmd),
+                "crc32" => parse_checksum_cmd(cmd, checksum::Kind::Crc32),
+                "sum" => parse_checksum_cmd(cmd, checksum::Kind::Sum),
+                "adler32" => parse_checksum_cmd(cmd, checksum::Kind::Adler32),
+                "sha256" => parse_checksum_cmd(cmd, checksum::Kind::Sha256),
+                "calc" => parse_calc_cmd(cmd),
+                "set" => parse_set_cmd(cmd),
+                "unset" => parse_unset_cmd(cmd),
+                "sym" => parse_sym_cmd(cmd),
+                "baud" => parse_baud_cmd(cmd),
+                "exit" => parse_exit_cmd(cmd),
                 _ => parse_address_cmd(cmd)
                     .or_else(|| parse_write_cmd(cmd))
                     .or_else(|| parse_dump_cmd(cmd))
@@ -93,14 +346,14 @@ fn parse_command(cmd: &str) -> Option<Command> {
     }
 }
 
-fn parse_address_cmd(cmd: &str) -> Option<Command> {
+fn parse_address_cmd(cmd: &str) -> Option<Command<'_>> {
     match cmd.strip_prefix('@') {
         None => None,
         Some(rest) => {
             let rest = rest.trim();
             match rest {
                 "" => Some(Command::AddrGet),
-                _ => match hex::parse_hex_usize(rest) {
+                _ => match expr::parse_usize(rest) {
                     Some(addr) => Some(Command::AddrSet { addr }),
                     None => {
                         println("error: invalid address");
@@ -112,7 +365,7 @@ fn parse_address_cmd(cmd: &str) -> Option<Command> {
     }
 }
 
-fn parse_write_cmd(cmd: &str) -> Option<Command> {
+fn parse_write_cmd(cmd: &str) -> Option<Command<'_>> {
     let (addr_s, data_s) = cmd.split_once(':')?;
     let addr_s = addr_s.trim();
     let data_s = data_s.trim();
@@ -127,7 +380,7 @@ fn parse_write_cmd(cmd: &str) -> Option<Command> {
         return Some(Command::Noop);
     }
 
-    let Some(start) = hex::parse_hex_usize(addr_s) else {
+    let Some(start) = expr::parse_usize(addr_s) else {
         println("error: invalid address");
         return Some(Command::Noop);
     };
@@ -139,246 +392,2352 @@ fn parse_write_cmd(cmd: &str) -> Option<Command> {
     Some(Command::Write { start, bytes, len })
 }
 
-fn parse_dump_cmd(cmd: &str) -> Option<Command> {
+fn parse_dump_cmd(cmd: &str) -> Option<Command<'_>> {
     let (ascii, core) = match cmd.strip_suffix(".as_str") {
         Some(prefix) => (true, prefix),
         None => (false, cmd),
     };
 
-    match core.split_once('+') {
-        Some((start_s, off_s)) => {
-            let start = match hex::parse_hex_usize(start_s) {
-                Some(v) => v,
-                None => {
-                    println("error: invalid start address");
-                    return Some(Command::Noop);
-                }
-            };
-            let off = match hex::parse_hex_usize(off_s) {
-                Some(v) => v,
-                None => {
-                    println("error: invalid offset");
-                    return Some(Command::Noop);
-                }
-            };
-            let (start, end) = match range_from_len(start, off) {
-                Ok(r) => r,
-                Err(()) => return Some(Command::Noop),
-            };
-            Some(Command::Dump { start, end, ascii })
-        }
-        None => match core.split_once('.') {
-            Some((start_s, end_s)) => {
-                let start = match hex::parse_hex_usize(start_s) {
-                    Some(v) => v,
-                    None => {
-                        println("error: invalid start address");
-                        return Some(Command::Noop);
-                    }
-                };
-                let end = match hex::parse_hex_usize(end_s) {
-                    Some(v) => v,
-                    None => {
-                        println("error: invalid end address");
-                        return Some(Command::Noop);
-                    }
-                };
+    let (start_s, sep, rest_s) = expr::split_pair(core, b"+.")?;
+    let Some(start) = expr::parse_usize(start_s) else {
+        println("error: invalid start address");
+        return Some(Command::Noop);
+    };
 
-                match validate_range(start, end) {
-                    Ok(()) => Some(Command::Dump { start, end, ascii }),
-                    Err(()) => Some(Command::Noop),
-                }
-            }
-            None => None,
-        },
+    if sep == b'+' {
+        let Some(off) = expr::parse_usize(rest_s) else {
+            println("error: invalid offset");
+            return Some(Command::Noop);
+        };
+        return match range_from_len(start, off) {
+            Ok((start, end)) => Some(Command::Dump { start, end, ascii }),
+            Err(()) => Some(Command::Noop),
+        };
+    }
+
+    let Some(end) = expr::parse_usize(rest_s) else {
+        println("error: invalid end address");
+        return Some(Command::Noop);
+    };
+    match validate_range(start, end) {
+        Ok(()) => Some(Command::Dump { start, end, ascii }),
+        Err(()) => Some(Command::Noop),
     }
 }
 
-fn parse_jump_cmd(cmd: &str) -> Option<Command> {
-    let rest = cmd.strip_prefix("jump")?;
-    let rest = rest.trim();
+fn parse_jump_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("jump")?.split_whitespace();
 
-    if rest.is_empty() {
-        println("error: no address (usage: jump ADDR)");
+    let Some(addr_s) = args.next() else {
+        println("error: no address (usage: jump ADDR [timeout MS])");
         return Some(Command::Noop);
-    }
+    };
+    let Some(addr) = expr::parse_usize(addr_s) else {
+        println("error: invalid address");
+        return Some(Command::Noop);
+    };
 
-    match hex::parse_hex_usize(rest) {
-        Some(addr) => Some(Command::Jump { addr }),
-        None => {
-            println("error: invalid address");
-            Some(Command::Noop)
+    let timeout_ms = match (args.next(), args.next(), args.next()) {
+        (None, _, _) => None,
+        (Some("timeout"), Some(ms_s), None) => match ms_s.parse::<u64>() {
+            Ok(ms) if ms > 0 => Some(ms),
+            _ => {
+                println("error: invalid timeout (decimal milliseconds, e.g. timeout 500)");
+                return Some(Command::Noop);
+            }
+        },
+        _ => {
+            println("error: usage: jump ADDR [timeout MS]");
+            return Some(Command::Noop);
         }
-    }
-}
-
-// -----------------------------------------------------------------------------
-// Commands
-// -----------------------------------------------------------------------------
+    };
 
-fn cmd_help() {
-    println("shell commands:");
-    println("  help          - show help information");
-    println("  info          - show monitor info");
-    println("  clear (reset) - clear the terminal");
-    println("  poweroff (q)  - power off the system");
-    println("");
-    println("memory management commands:");
-    println("  @         - get current address");
-    println("  @ADDR     - set current address (e.g. @80001000)");
-    println("  ADDR.ADDR - dump memory range (e.g. 80002004.80002007)");
-    println("  ADDR+OFF  - dump OFF bytes from ADDR (e.g. 80002004+4)");
-    println("  ADDR.ADDR.as_str - dump range as ASCII (e.g. 80001000.8000103f.as_str)");
-    println("  ADDR+OFF.as_str  - dump as ASCII (e.g. 80001000+40.as_str)");
-    println("  ADDR: XX YY .. - write up to 32 bytes (e.g. 80001000: 48 69 21)");
-    println("  jump ADDR     - jump to ADDR and execute (e.g. jump 80001000)");
+    Some(Command::Jump { addr, timeout_ms })
 }
 
-fn cmd_info() {
-    println(INFO_BANNER);
+fn parse_boot_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("boot")?.split_whitespace();
 
-    print("stack: ");
-    print_hex_u32(STACK_BOTTOM as u32);
-    print(".. ");
-    print_hex_u32(STACK_TOP as u32);
-    println("");
+    let Some(addr_s) = args.next() else {
+        println("error: no address (usage: boot ADDR [DTB])");
+        return Some(Command::Noop);
+    };
+    let Some(addr) = expr::parse_usize(addr_s) else {
+        println("error: invalid address");
+        return Some(Command::Noop);
+    };
 
-    print("current: ");
-    print_hex_u32(get_current_addr() as u32);
-    println("");
+    let dtb = match args.next() {
+        None => None,
+        Some(dtb_s) => match expr::parse_usize(dtb_s) {
+            Some(dtb) => Some(dtb),
+            None => {
+                println("error: invalid device tree address");
+                return Some(Command::Noop);
+            }
+        },
+    };
 
-    print("FINDME @ ");
-    let addr = (&FINDME as *const [u8; 8]) as usize;
-    print_hex_u32(addr as u32);
-    println("");
-}
+    if args.next().is_some() {
+        println("error: too many arguments (usage: boot ADDR [DTB])");
+        return Some(Command::Noop);
+    }
 
-fn cmd_clear() {
-    clear_screen();
+    Some(Command::Boot { addr, dtb })
 }
 
-fn cmd_poweroff() -> ! {
-    system::poweroff()
-}
+fn parse_ujump_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("ujump")?.split_whitespace();
 
-fn cmd_addr_get() {
-    print_hex_u32(get_current_addr() as u32);
-    println("");
-}
+    let Some(addr_s) = args.next() else {
+        println("error: no address (usage: ujump ADDR [BASE+SIZE])");
+        return Some(Command::Noop);
+    };
+    let Some(addr) = expr::parse_usize(addr_s) else {
+        println("error: invalid address");
+        return Some(Command::Noop);
+    };
 
-fn cmd_addr_set(addr: usize) {
-    if !memory::is_in_ram(addr) || memory::is_in_stack(addr) {
-        println("error: invalid memory address");
-        print_valid_address_ranges();
-        return;
+    let window = match args.next() {
+        None => None,
+        Some(window_s) => {
+            let parsed = expr::split_pair(window_s, b"+").and_then(|(base_s, _, size_s)| {
+                Some((expr::parse_usize(base_s)?, expr::parse_usize(size_s)?))
+            });
+            match parsed {
+                Some(window) => Some(window),
+                None => {
+                    println("error: invalid window (use BASE+SIZE, e.g. 80200000+10000)");
+                    return Some(Command::Noop);
+                }
+            }
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments (usage: ujump ADDR [BASE+SIZE])");
+        return Some(Command::Noop);
     }
-    set_current_addr(addr);
-    cmd_addr_get();
+
+    Some(Command::Ujump { addr, window })
 }
 
-fn cmd_write(start: usize, bytes: &[u8]) {
-    let len = bytes.len();
-    if len == 0 {
-        println("error: no data");
-        return;
-    }
+fn parse_pt_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("pt")?.split_whitespace();
 
-    let Some(end) = start.checked_add(len - 1) else {
-        println("error: address overflow");
-        return;
+    let pt = match args.next() {
+        None => PtCommand::Info,
+        Some("new") => {
+            let mut mode = paging::Mode::Sv39;
+            let mut pool = paging::DEFAULT_POOL;
+            for arg in args.by_ref() {
+                match (paging::Mode::from_name(arg), expr::parse_usize(arg)) {
+                    (Some(m), _) => mode = m,
+                    (None, Some(p)) => pool = p,
+                    (None, None) => {
+                        println("error: usage: pt new [sv39|sv48] [POOL]");
+                        return Some(Command::Noop);
+                    }
+                }
+            }
+            PtCommand::New { mode, pool }
+        }
+        Some("map") => {
+            let (Some(va), Some(pa), Some(size), Some(flags_s)) =
+                (args.next(), args.next(), args.next(), args.next())
+            else {
+                println(
+                    "error: usage: pt map VA PA SIZE FLAGS (e.g. pt map 80200000 80200000 1000 rwxad)",
+                );
+                return Some(Command::Noop);
+            };
+            let (Some(va), Some(pa), Some(size)) = (
+                expr::parse_usize(va),
+                expr::parse_usize(pa),
+                expr::parse_usize(size),
+            ) else {
+                println("error: invalid address or size");
+                return Some(Command::Noop);
+            };
+            let Some(flags) = paging::parse_flags(flags_s) else {
+                println("error: invalid flags (use letters from rwxugad)");
+                return Some(Command::Noop);
+            };
+            PtCommand::Map {
+                va,
+                pa,
+                size,
+                flags,
+            }
+        }
+        Some("walk") => match args.next().and_then(expr::parse_usize) {
+            Some(va) => PtCommand::Walk { va },
+            None => {
+                println("error: usage: pt walk VA");
+                return Some(Command::Noop);
+            }
+        },
+        Some("dump") => PtCommand::Dump,
+        Some("satp") => match args.next() {
+            None => PtCommand::Satp { on: None },
+            Some("on") => PtCommand::Satp { on: Some(true) },
+            Some("off") => PtCommand::Satp { on: Some(false) },
+            Some(_) => {
+                println("error: usage: pt satp [on|off]");
+                return Some(Command::Noop);
+            }
+        },
+        Some(_) => {
+            println("error: unknown pt command (new, map, walk, dump, satp)");
+            return Some(Command::Noop);
+        }
     };
 
-    if !memory::is_in_ram(start) || !memory::is_in_ram(end) {
-        println("error: address out of range");
-        print_valid_address_ranges();
-        return;
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
     }
 
-    if memory::ranges_overlap(start, end, STACK_BOTTOM, STACK_TOP) {
-        println("error: write into riscmon stack not allowed");
-        print_stack_range();
-        return;
-    }
+    Some(Command::Pt(pt))
+}
 
-    for (i, &b) in bytes.iter().enumerate() {
-        unsafe {
-            ((start + i) as *mut u8).write_volatile(b);
+#[cfg(not(feature = "supervisor"))]
+fn parse_pmp_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str =
+        "error: usage: pmp set N off | pmp set N BASE+SIZE PERMS | pmp set N tor TOP PERMS";
+    let mut args = cmd.strip_prefix("pmp")?.split_whitespace();
+
+    let (Some("set"), Some(index_s), Some(what)) = (args.next(), args.next(), args.next()) else {
+        println(USAGE);
+        return Some(Command::Noop);
+    };
+    let index = match index_s.parse::<usize>() {
+        Ok(index) if index < pmp::MAX_ENTRIES => index,
+        _ => {
+            println("error: invalid entry (decimal, 0..63)");
+            return Some(Command::Noop);
         }
-    }
+    };
 
-    if end < memory::RAM_END_INCLUSIVE {
-        set_current_addr(end + 1);
+    let pmp = match what {
+        "off" => PmpCommand::Off { index },
+        "tor" => {
+            let (Some(top), Some(perms)) = (
+                args.next().and_then(expr::parse_usize),
+                args.next().and_then(parse_pmp_perms),
+            ) else {
+                println(USAGE);
+                return Some(Command::Noop);
+            };
+            PmpCommand::Tor { index, top, perms }
+        }
+        range => {
+            let range = expr::split_pair(range, b"+").and_then(|(base_s, _, size_s)| {
+                Some((expr::parse_usize(base_s)?, expr::parse_usize(size_s)?))
+            });
+            let (Some((base, size)), Some(perms)) = (range, args.next().and_then(parse_pmp_perms))
+            else {
+                println(USAGE);
+                return Some(Command::Noop);
+            };
+            PmpCommand::Range {
+                index,
+                base,
+                size,
+                perms,
+            }
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
     }
 
-    cmd_addr_get();
+    Some(Command::Pmp(pmp))
 }
 
-fn cmd_dump(start: usize, end: usize, ascii: bool) {
-    if let Err(()) = validate_range(start, end) {
-        return;
-    }
-
-    match ascii {
-        true => print_memory_dump_as_ascii(start, end),
-        false => print_memory_dump(start, end),
-    }
+// Without PMP access every form reports the same error.
+#[cfg(feature = "supervisor")]
+fn parse_pmp_cmd(_cmd: &str) -> Option<Command<'_>> {
+    Some(Command::Pmp(PmpCommand::Show))
 }
 
-fn cmd_jump(addr: usize) {
-    if !memory::is_in_ram(addr) {
-        println("error: address out of range");
-        print_valid_address_ranges();
-        return;
-    }
+fn parse_blk_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("blk")?.split_whitespace();
 
-    if memory::is_in_stack(addr) {
+    let blk = match args.next() {
+        None | Some("info") => BlkCommand::Info,
+        Some(op @ ("read" | "write")) => {
+            let (Some(lba), Some(count), Some(addr)) = (
+                args.next().and_then(expr::parse_usize),
+                args.next().and_then(expr::parse_usize),
+                args.next().and_then(expr::parse_usize),
+            ) else {
+                print("error: usage: blk ");
+                print(op);
+                println(" LBA COUNT ADDR (all hex)");
+                return Some(Command::Noop);
+            };
+            let (lba, count) = (lba as u64, count as u64);
+            match op {
+                "read" => BlkCommand::Read { lba, count, addr },
+                _ => BlkCommand::Write { lba, count, addr },
+            }
+        }
+        Some(_) => {
+            println("error: unknown blk command (info, read, write)");
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Blk(blk))
+}
+
+fn parse_tftp_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("tftp")?.split_whitespace();
+
+    let (Some(addr), Some(filename), None) = (
+        args.next().and_then(expr::parse_usize),
+        args.next(),
+        args.next(),
+    ) else {
+        println("error: usage: tftp ADDR FILENAME");
+        return Some(Command::Noop);
+    };
+
+    Some(Command::Tftp { addr, filename })
+}
+
+fn parse_fwcfg_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("fwcfg")?.split_whitespace();
+
+    let fwcfg = match args.next() {
+        None | Some("list") => FwcfgCommand::List,
+        Some("load") => {
+            let (Some(name), Some(addr)) = (args.next(), args.next().and_then(expr::parse_usize))
+            else {
+                println("error: usage: fwcfg load NAME ADDR");
+                return Some(Command::Noop);
+            };
+            FwcfgCommand::Load { name, addr }
+        }
+        Some(_) => {
+            println("error: unknown fwcfg command (list, load)");
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Fwcfg(fwcfg))
+}
+
+fn parse_sh_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str =
+        "error: usage: sh load HOSTPATH ADDR | sh save HOSTPATH ADDR LEN | sh exit CODE";
+    let mut args = cmd.strip_prefix("sh")?.split_whitespace();
+
+    let sh = match (args.next(), args.next()) {
+        (Some("load"), Some(path)) => match args.next().and_then(expr::parse_usize) {
+            Some(addr) => ShCommand::Load { path, addr },
+            None => {
+                println(USAGE);
+                return Some(Command::Noop);
+            }
+        },
+        (Some("save"), Some(path)) => match (
+            args.next().and_then(expr::parse_usize),
+            args.next().and_then(expr::parse_usize),
+        ) {
+            (Some(addr), Some(len)) => ShCommand::Save { path, addr, len },
+            _ => {
+                println(USAGE);
+                return Some(Command::Noop);
+            }
+        },
+        (Some("exit"), Some(code)) => match code.parse::<u8>() {
+            Ok(code) => ShCommand::Exit { code },
+            Err(_) => {
+                println("error: invalid exit code (decimal 0..255)");
+                return Some(Command::Noop);
+            }
+        },
+        _ => {
+            println(USAGE);
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Semihost(sh))
+}
+
+fn parse_pci_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str =
+        "error: usage: pci read B:D.F OFF [b|h|w] | pci write B:D.F OFF VALUE [b|h|w]";
+    let mut args = cmd.strip_prefix("pci")?.split_whitespace();
+
+    let op = args.next();
+    let pci = match op {
+        None | Some("list") => PciCommand::List,
+        Some("assign") => PciCommand::Assign,
+        Some("read" | "write") => {
+            let bdf = args.next().and_then(parse_bdf);
+            let off = args.next().and_then(expr::parse_usize);
+            let value = match op {
+                Some("write") => args.next().and_then(expr::parse_usize),
+                _ => Some(0),
+            };
+            let width = match args.next() {
+                None | Some("w") => Some(4),
+                Some("h") => Some(2),
+                Some("b") => Some(1),
+                Some(_) => None,
+            };
+            let (Some(bdf), Some(off), Some(value), Some(width)) = (bdf, off, value, width) else {
+                println(USAGE);
+                return Some(Command::Noop);
+            };
+            if off >= pci::CONFIG_SIZE || off % width != 0 {
+                println("error: offset must be below 1000 (hex) and aligned to the width");
+                return Some(Command::Noop);
+            }
+            let Ok(value) = u32::try_from(value) else {
+                println("error: value too large");
+                return Some(Command::Noop);
+            };
+            if width < 4 && value >> (width * 8) != 0 {
+                println("error: value too large for the width");
+                return Some(Command::Noop);
+            }
+            match op {
+                Some("read") => PciCommand::Read { bdf, off, width },
+                _ => PciCommand::Write {
+                    bdf,
+                    off,
+                    width,
+                    value,
+                },
+            }
+        }
+        Some(_) => {
+            println("error: unknown pci command (list, read, write, assign)");
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Pci(pci))
+}
+
+fn parse_date_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("date")?.split_whitespace();
+
+    let (Some("set"), Some(date), None) = (args.next(), args.next(), args.next()) else {
+        println("error: usage: date [set YYYY-MM-DDTHH:MM:SSZ]");
+        return Some(Command::Noop);
+    };
+    match rtc::DateTime::parse(date).and_then(|dt| dt.to_unix_ns()) {
+        Some(ns) => Some(Command::Date { set: Some(ns) }),
+        None => {
+            println("error: invalid date (YYYY-MM-DDTHH:MM:SSZ, UTC, 1970..2500)");
+            Some(Command::Noop)
+        }
+    }
+}
+
+fn parse_rand_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str = "error: usage: rand START END | rand seed N|off";
+    let mut args = cmd.strip_prefix("rand")?.split_whitespace();
+
+    let rand = match (args.next(), args.next()) {
+        (Some("seed"), Some("off")) => RandCommand::Seed { seed: None },
+        (Some("seed"), Some(seed)) => match expr::parse_usize(seed) {
+            Some(seed) => RandCommand::Seed {
+                seed: Some(seed as u64),
+            },
+            None => {
+                println("error: invalid seed (hex, or 'off')");
+                return Some(Command::Noop);
+            }
+        },
+        (Some(start), Some(end)) => match (expr::parse_usize(start), expr::parse_usize(end)) {
+            (Some(start), Some(end)) => RandCommand::Fill { start, end },
+            _ => {
+                println(USAGE);
+                return Some(Command::Noop);
+            }
+        },
+        _ => {
+            println(USAGE);
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Rand(rand))
+}
+
+fn parse_irq_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str =
+        "error: usage: irq | irq enable N [PRIO] | irq disable N | irq threshold T (decimal)";
+    let mut args = cmd.strip_prefix("irq")?.split_whitespace();
+    let op = args.next();
+    let mut number = || args.next().map(|a| a.parse::<u32>().ok());
+
+    let irq = match (op, number(), number()) {
+        (None, ..) => IrqCommand::List,
+        (Some("enable"), Some(Some(source)), None) => IrqCommand::Enable {
+            source,
+            priority: 1,
+        },
+        (Some("enable"), Some(Some(source)), Some(Some(priority))) => {
+            IrqCommand::Enable { source, priority }
+        }
+        (Some("disable"), Some(Some(source)), None) => IrqCommand::Disable { source },
+        (Some("threshold"), Some(Some(value)), None) => IrqCommand::Threshold { value },
+        _ => {
+            println(USAGE);
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Irq(irq))
+}
+
+fn parse_memtest_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("memtest")?.split_whitespace().peekable();
+
+    let (Some(start), Some(end)) = (
+        args.next().and_then(expr::parse_usize),
+        args.next().and_then(expr::parse_usize),
+    ) else {
+        println("error: usage: memtest START END [PATTERN] [walk|addr|march|fill|all] [PASSES]");
+        return Some(Command::Noop);
+    };
+
+    let find_test = |arg: &str| memtest::Test::ALL.into_iter().find(|t| t.name() == arg);
+
+    let pattern = match args.next_if(|&arg| arg != "all" && find_test(arg).is_none()) {
+        None => memtest::DEFAULT_PATTERN,
+        Some(arg) => match expr::parse_usize(arg) {
+            Some(pattern) => pattern as u64,
+            None => {
+                println("error: invalid pattern (hex word)");
+                return Some(Command::Noop);
+            }
+        },
+    };
+
+    let test = match args.next() {
+        Some("all") | None => None,
+        Some(name) => match find_test(name) {
+            Some(test) => Some(test),
+            None => {
+                println("error: unknown test (walk, addr, march, fill, all)");
+                return Some(Command::Noop);
+            }
+        },
+    };
+
+    let passes = match args.next().map(|p| p.parse::<u32>()) {
+        None => 1,
+        Some(Ok(passes)) => passes,
+        Some(Err(_)) => {
+            println("error: invalid pass count (decimal)");
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Memtest {
+        start,
+        end,
+        pattern,
+        test,
+        passes,
+    })
+}
+
+fn parse_checksum_cmd(cmd: &str, kind: checksum::Kind) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix(kind.name())?.split_whitespace();
+
+    let (Some(start), Some(end), None) = (
+        args.next().and_then(expr::parse_usize),
+        args.next().and_then(expr::parse_usize),
+        args.next(),
+    ) else {
+        print("error: usage: ");
+        print(kind.name());
+        println(" START END (hex, inclusive)");
+        return Some(Command::Noop);
+    };
+
+    Some(Command::Checksum { kind, start, end })
+}
+
+fn parse_calc_cmd(cmd: &str) -> Option<Command<'_>> {
+    let rest = cmd.strip_prefix("calc")?.trim();
+    if rest.is_empty() {
+        println("error: usage: calc EXPR");
+        return Some(Command::Noop);
+    }
+
+    match expr::eval(rest) {
+        Ok(value) => Some(Command::Calc { value }),
+        Err(e) => {
+            print("error: ");
+            println(e);
+            Some(Command::Noop)
+        }
+    }
+}
+
+fn parse_set_cmd(cmd: &str) -> Option<Command<'_>> {
+    let rest = cmd.strip_prefix("set")?;
+    let Some((name, value)) = rest.split_once('=') else {
+        println("error: usage: set NAME = EXPR");
+        return Some(Command::Noop);
+    };
+
+    match expr::eval(value) {
+        Ok(value) => Some(Command::Set {
+            name: name.trim(),
+            value,
+        }),
+        Err(e) => {
+            print("error: ");
+            println(e);
+            Some(Command::Noop)
+        }
+    }
+}
+
+fn parse_unset_cmd(cmd: &str) -> Option<Command<'_>> {
+    let mut args = cmd.strip_prefix("unset")?.split_whitespace();
+
+    match (args.next(), args.next()) {
+        (Some(name), None) => Some(Command::Unset { name }),
+        _ => {
+            println("error: usage: unset NAME");
+            Some(Command::Noop)
+        }
+    }
+}
+
+fn parse_sym_cmd(cmd: &str) -> Option<Command<'_>> {
+    const USAGE: &str = "error: usage: sym [list|clear|paste|load ADDR|NAME|ADDR]";
+    let mut args = cmd.strip_prefix("sym")?.split_whitespace();
+
+    let sym = match (args.next(), args.next()) {
+        (Some("list"), None) => SymCommand::List,
+        (Some("clear"), None) => SymCommand::Clear,
+        (Some("paste"), None) => SymCommand::Paste,
+        (Some("load"), Some(addr)) => match expr::parse_usize(addr) {
+            Some(addr) => SymCommand::Load { addr },
+            None => {
+                println(USAGE);
+                return Some(Command::Noop);
+            }
+        },
+        (Some(arg), None) => SymCommand::Lookup(arg),
+        _ => {
+            println(USAGE);
+            return Some(Command::Noop);
+        }
+    };
+
+    if args.next().is_some() {
+        println("error: too many arguments");
+        return Some(Command::Noop);
+    }
+
+    Some(Command::Sym(sym))
+}
+
+fn parse_exit_cmd(cmd: &str) -> Option<Command<'_>> {
+    let rest = cmd.strip_prefix("exit")?;
+
+    // The host only sees the low 8 bits of an exit status.
+    match rest.trim().parse::<u8>() {
+        Ok(code) => Some(Command::Exit { code: code.into() }),
+        Err(_) => {
+            println("error: invalid exit code (usage: exit CODE, decimal 0..255)");
+            Some(Command::Noop)
+        }
+    }
+}
+
+fn parse_baud_cmd(cmd: &str) -> Option<Command<'_>> {
+    let rest = cmd.strip_prefix("baud")?;
+    let rest = rest.trim();
+
+    if rest.is_empty() {
+        return Some(Command::Baud { rate: None });
+    }
+
+    match rest.parse::<u32>() {
+        Ok(rate) => Some(Command::Baud { rate: Some(rate) }),
+        Err(_) => {
+            println("error: invalid baud rate (decimal, e.g. baud 115200)");
+            Some(Command::Noop)
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Commands
+// -----------------------------------------------------------------------------
+
+fn cmd_help() {
+    println("shell commands:");
+    println("  help          - show help information");
+    println("  info          - show monitor info");
+    println("  clear (reset) - clear the terminal");
+    println("  poweroff (q)  - power off the system");
+    println("  reboot        - reset the system");
+    println("  exit CODE     - end emulation with exit status CODE (0..255, 0 = pass)");
+    println("  baud [RATE]   - show or set the UART baud rate (decimal)");
+    println("  harts         - show hart states (under SBI firmware)");
+    println("  history       - list recent commands (!N re-runs one, Up/Down recall them)");
+    println("  date          - show the RTC's date and time (UTC, ISO 8601)");
+    println("  date set YYYY-MM-DDTHH:MM:SSZ - set the RTC");
+    println("  calc EXPR     - evaluate EXPR: + - * / % & | ^ << >> ~ ( ), . and registers");
+    println("                  (hex; #10 or 0d10 decimal, 0b1010 binary; command arguments");
+    println("                  take expressions without spaces, e.g. jump .+100, .+#16)");
+    println("  set NAME = EXPR - set a variable, usable in any expression");
+    println("  unset NAME    - remove a variable");
+    println("  sym [NAME|ADDR] - count symbols, or look one up (sym list, sym clear)");
+    println("  sym load ADDR - take symbols from the ELF file at ADDR (tftp/fwcfg/sh do it)");
+    println("  sym paste     - read ADDR NAME (or nm) lines until an empty line");
+    println("  vars          - list variables (and built-ins ram_base, findme, entry, ...)");
+    println("");
+    println("memory management commands:");
+    println("  @         - get current address");
+    println("  @ADDR     - set current address (e.g. @80001000)");
+    println("  ADDR.ADDR - dump memory range (e.g. 80002004.80002007)");
+    println("  ADDR+OFF  - dump OFF bytes from ADDR (e.g. 80002004+4)");
+    println("  ADDR.ADDR.as_str - dump range as ASCII (e.g. 80001000.8000103f.as_str)");
+    println("  ADDR+OFF.as_str  - dump as ASCII (e.g. 80001000+40.as_str)");
+    println("  ADDR: XX YY .. - write up to 32 bytes (e.g. 80001000: 48 69 21)");
+    println("  jump ADDR     - jump to ADDR and execute (e.g. jump 80001000)");
+    println("  jump ADDR timeout MS - abort if not returned after MS (decimal) milliseconds");
+    println("  boot ADDR [DTB] - boot a supervisor-mode payload (a0=hart, a1=DTB)");
+    println("  ujump ADDR [BASE+SIZE] - run ADDR in user mode, sandboxed to a RAM window");
+    println("  rand START END - fill memory (inclusive) with random bytes");
+    println("  rand seed N|off - fill from a PRNG seeded with N (hex), or from virtio-rng");
+    println("  memtest START END [PATTERN] [walk|addr|march|fill|all] [PASSES]");
+    println("                  (test RAM; PATTERN is the fill/addr data word, 0 passes = forever)");
+    println("  crc32|sum|adler32|sha256 START END - checksum a range (inclusive, any size)");
+    println("");
+    println("page table commands:");
+    println("  pt            - show the current page table");
+    println("  pt new [sv39|sv48] [POOL] - start a new page table, tables allocated at POOL");
+    println("  pt map VA PA SIZE FLAGS - map SIZE bytes (FLAGS from rwxugad, e.g. rwxad)");
+    println("  pt walk VA    - walk VA, printing each level's PTE");
+    println("  pt dump       - list all mappings");
+    println("  pt satp [on|off] - show or set translation for boot/ujump programs");
+    println("");
+    println("physical memory protection commands:");
+    println("  pmp           - show the PMP entries implemented on this hart");
+    println("  pmp set N BASE+SIZE PERMS - protect a NAPOT range (PERMS from rwxl, or -)");
+    println("  pmp set N tor TOP PERMS - protect from entry N-1's address up to TOP");
+    println("  pmp set N off - turn entry N off");
+    println("                  (boot/ujump reprogram entries 0-1, so those cannot be locked)");
+    println("");
+    println("disk commands (virtio-blk):");
+    println("  blk info      - show the attached disk");
+    println("  blk read LBA COUNT ADDR - read COUNT sectors at LBA into memory at ADDR");
+    println("  blk write LBA COUNT ADDR - write COUNT sectors from ADDR to the disk at LBA");
+    println("");
+    println("network commands (virtio-net, QEMU user-mode networking):");
+    println("  tftp ADDR FILENAME - download FILENAME from 10.0.2.2 into memory at ADDR");
+    println("");
+    println("host file commands (QEMU fw_cfg):");
+    println("  fwcfg list    - list the files QEMU passes in (-kernel, -initrd, -fw_cfg)");
+    println("  fwcfg load NAME ADDR - copy file NAME into memory at ADDR");
+    println("");
+    println("semihosting commands (-semihosting-config enable=on):");
+    println("  sh load HOSTPATH ADDR - copy a host file into memory at ADDR");
+    println("  sh save HOSTPATH ADDR LEN - write LEN bytes at ADDR to a host file");
+    println("  sh exit CODE  - end the QEMU run with exit status CODE (decimal)");
+    println("");
+    println("PCI commands:");
+    println("  lspci         - list PCI functions with their class and BARs");
+    println("  pci read B:D.F OFF [b|h|w] - read config space (byte, half, word; default w)");
+    println("  pci write B:D.F OFF VALUE [b|h|w] - write config space");
+    println("  pci assign    - assign BARs and bus numbers, enable decoding");
+    println("");
+    println("interrupt commands (PLIC):");
+    println("  irq           - list active sources: priority, enabled, pending, claims");
+    println("  irq enable N [PRIO] - enable source N for riscmon's context (priority 1..7)");
+    println("  irq disable N - disable source N");
+    println("  irq threshold T - set riscmon's context's priority threshold");
+    println("");
+    println("program control commands:");
+    println("  Ctrl+C        - break into the monitor while a jumped-to program runs");
+    println("  continue (c)  - resume the program stopped by Ctrl+C");
+    println("  regs          - show the registers saved when the program stopped");
+}
+
+fn cmd_info() {
+    println(INFO_BANNER);
+
+    print("stack: ");
+    print_hex_u32(STACK_BOTTOM as u32);
+    print(".. ");
+    print_hex_u32(STACK_TOP as u32);
+    println("");
+
+    print("mode: ");
+    print(csr::NAME);
+    print(", hart ");
+    print_dec_u64(system::boot_hart() as u64);
+    println("");
+
+    let console = console::active();
+    print("console: ");
+    print(console.name());
+    if let Some(base) = console.base() {
+        print(" @ ");
+        print_hex_u32(base as u32);
+    }
+    println("");
+
+    if let Some(fdt) = fdt::get() {
+        print("device tree: ");
+        print_hex_u32(fdt.addr() as u32);
+        print("+");
+        print_hex_u32(fdt.size() as u32);
+        println("");
+    }
+
+    print("current: ");
+    print_hex_u32(get_current_addr() as u32);
+    println("");
+
+    print("FINDME @ ");
+    let addr = (&FINDME as *const [u8; 8]) as usize;
+    print_hex_u32(addr as u32);
+    println("");
+}
+
+fn cmd_clear() {
+    clear_screen();
+}
+
+fn cmd_poweroff() -> ! {
+    system::poweroff()
+}
+
+fn cmd_reboot() -> ! {
+    system::reboot()
+}
+
+fn cmd_exit(code: u16) -> ! {
+    system::exit(code)
+}
+
+fn cmd_baud(rate: Option<u32>) {
+    if let Some(rate) = rate
+        && !uart::set_baud(rate)
+    {
+        println("error: unsupported baud rate for this console");
+        return;
+    }
+
+    match uart::baud() {
+        Some(baud) => {
+            print("baud: ");
+            print_dec_u64(baud as u64);
+            println("");
+        }
+        None => println("baud: not configurable on this console"),
+    }
+}
+
+fn cmd_harts() {
+    let mut hart = 0;
+    while let Some(status) = system::hart_status(hart) {
+        print("hart ");
+        print_dec_u64(hart as u64);
+        print(": ");
+        println(status);
+        hart += 1;
+    }
+
+    if hart == 0 {
+        println("hart states need SBI firmware (build with the 'supervisor' feature)");
+    }
+}
+
+fn cmd_addr_get() {
+    print_hex_u32(get_current_addr() as u32);
+    println("");
+}
+
+fn cmd_addr_set(addr: usize) {
+    if !memory::is_in_ram(addr) || memory::is_in_stack(addr) {
+        println("error: invalid memory address");
+        print_valid_address_ranges();
+        return;
+    }
+    set_current_addr(addr);
+    cmd_addr_get();
+}
+
+fn cmd_write(start: usize, bytes: &[u8]) {
+    let len = bytes.len();
+    if len == 0 {
+        println("error: no data");
+        return;
+    }
+
+    let Some(end) = start.checked_add(len - 1) else {
+        println("error: address overflow");
+        return;
+    };
+
+    if !memory::is_in_ram(start) || !memory::is_in_ram(end) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+
+    if memory::ranges_overlap(start, end, STACK_BOTTOM, STACK_TOP) {
+        println("error: write into riscmon stack not allowed");
+        print_stack_range();
+        return;
+    }
+
+    for (i, &b) in bytes.iter().enumerate() {
+        unsafe {
+            ((start + i) as *mut u8).write_volatile(b);
+        }
+    }
+
+    if end < memory::RAM_END_INCLUSIVE {
+        set_current_addr(end + 1);
+    }
+
+    cmd_addr_get();
+}
+
+fn cmd_dump(start: usize, end: usize, ascii: bool) {
+    if let Err(()) = validate_range(start, end) {
+        return;
+    }
+
+    match ascii {
+        true => print_memory_dump_as_ascii(start, end),
+        false => print_memory_dump(start, end),
+    }
+}
+
+fn cmd_jump(addr: usize, timeout_ms: Option<u64>) {
+    if !memory::is_in_ram(addr) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+
+    if memory::is_in_stack(addr) {
         println("error: cannot jump into riscmon stack");
         print_stack_range();
         return;
     }
 
-    print("jumping to ");
+    print("jumping to ");
+    print_hex_u32(addr as u32);
+    symbols::print_annotation(addr);
+    println(" ...");
+    if timeout_ms.is_some() {
+        print_run_time("started ");
+    }
+
+    // The program runs until it returns through ra, faults, or the user
+    // breaks in with Ctrl+C (or the optional watchdog fires). In every case
+    // trap::enter restores the monitor's own sp and callee-saved registers, so
+    // the REPL state survives even if the callee does not follow the calling
+    // convention.
+    let exit = trap::enter(addr, timeout_ms);
+    report_exit(exit);
+    if timeout_ms.is_some() {
+        print_run_time("ended ");
+    }
+}
+
+#[cfg(not(feature = "supervisor"))]
+fn cmd_boot(addr: usize, dtb: Option<usize>) {
+    let payload_start = memory::RAM_BASE + memory::MONITOR_SIZE;
+    if !memory::is_in_ram(addr) || addr < payload_start {
+        print("error: payload must be in RAM above riscmon (");
+        print_hex_u32(payload_start as u32);
+        print(".. ");
+        print_hex_u32(memory::RAM_END_INCLUSIVE as u32);
+        println(")");
+        return;
+    }
+
+    // Without an explicit DTB, hand over the one riscmon was booted with.
+    let dtb = match dtb {
+        Some(dtb) if fdt::Fdt::from_addr(dtb).is_none() => {
+            println("error: no device tree at that address");
+            return;
+        }
+        Some(dtb) => dtb,
+        None => fdt::get().map_or(0, |fdt| fdt.addr()),
+    };
+
+    print("booting ");
+    print_hex_u32(addr as u32);
+    symbols::print_annotation(addr);
+    print(" in supervisor mode (dtb ");
+    print_hex_u32(dtb as u32);
+    println(") ...");
+
+    // riscmon stays resident as the payload's SBI firmware: its ecalls trap
+    // back here and are serviced without leaving the payload, so control only
+    // returns to the REPL on a fault, a reboot request or Ctrl+C.
+    report_exit(trap::enter_supervisor(addr, system::boot_hart(), dtb));
+}
+
+#[cfg(feature = "supervisor")]
+fn cmd_boot(_addr: usize, _dtb: Option<usize>) {
+    println("error: boot needs riscmon in machine mode (it is running under SBI firmware)");
+}
+
+#[cfg(not(feature = "supervisor"))]
+fn cmd_ujump(addr: usize, window: Option<(usize, usize)>) {
+    // By default the program gets the naturally aligned window around ADDR.
+    let (base, size) = window.unwrap_or((addr & !(UJUMP_WINDOW_SIZE - 1), UJUMP_WINDOW_SIZE));
+
+    if pmp::napot_addr(base, size).is_none() {
+        println("error: window size must be a power of two (>= 8) and BASE aligned to it");
+        return;
+    }
+
+    let end = base + (size - 1);
+    if !memory::is_in_ram(base) || !memory::is_in_ram(end) {
+        println("error: window out of range");
+        print_valid_address_ranges();
+        return;
+    }
+
+    let monitor_end = memory::RAM_BASE + memory::MONITOR_SIZE - 1;
+    if memory::ranges_overlap(base, end, memory::RAM_BASE, monitor_end) {
+        print("error: window overlaps riscmon (");
+        print_hex_u32(memory::RAM_BASE as u32);
+        print(".. ");
+        print_hex_u32(monitor_end as u32);
+        println(")");
+        return;
+    }
+
+    // With translation on, ADDR is virtual and may lie anywhere.
+    if paging::satp() == 0 && !(base..=end).contains(&addr) {
+        println("error: address is outside the window");
+        return;
+    }
+
+    print("jumping to ");
+    print_hex_u32(addr as u32);
+    symbols::print_annotation(addr);
+    print(" in user mode (window ");
+    print_hex_u32(base as u32);
+    print(".. ");
+    print_hex_u32(end as u32);
+    println(") ...");
+
+    // PMP denies the program everything outside the window, including the
+    // UART and riscmon itself, so bad stores fault instead of corrupting the
+    // monitor.
+    report_exit(trap::enter_user(addr, base, size));
+}
+
+#[cfg(feature = "supervisor")]
+fn cmd_ujump(_addr: usize, _window: Option<(usize, usize)>) {
+    println("error: ujump needs riscmon in machine mode (it uses PMP)");
+}
+
+fn cmd_pt(pt: PtCommand) {
+    match pt {
+        PtCommand::Info => cmd_pt_info(),
+        PtCommand::New { mode, pool } => cmd_pt_new(mode, pool),
+        PtCommand::Map {
+            va,
+            pa,
+            size,
+            flags,
+        } => cmd_pt_map(va, pa, size, flags),
+        PtCommand::Walk { va } => cmd_pt_walk(va),
+        PtCommand::Dump => cmd_pt_dump(),
+        PtCommand::Satp { on } => cmd_pt_satp(on),
+    }
+}
+
+fn cmd_pt_info() {
+    let Some((mode, root)) = paging::current() else {
+        println("no page table (use 'pt new')");
+        return;
+    };
+
+    let (used, total) = paging::pool_usage();
+    print("page table: ");
+    print(mode.name());
+    print(", root ");
+    print_hex_u64(root as u64);
+    print(", ");
+    print_dec_u64(used as u64);
+    print("/");
+    print_dec_u64(total as u64);
+    println(" tables used");
+    cmd_pt_satp(None);
+}
+
+fn cmd_pt_new(mode: paging::Mode, pool: usize) {
+    match paging::new(mode, pool) {
+        Ok(_) => cmd_pt_info(),
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_pt_map(va: usize, pa: usize, size: usize, flags: u64) {
+    match paging::map(va, pa, size, flags) {
+        Ok(pages) => {
+            print("mapped ");
+            print_dec_u64(pages as u64);
+            println(" page(s)");
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_pt_walk(va: usize) {
+    let Some(walk) = paging::walk(va) else {
+        println("no page table (use 'pt new')");
+        return;
+    };
+
+    for step in walk.steps[..walk.len].iter() {
+        print("L");
+        print_dec_u64(step.level as u64);
+        print(" [");
+        print_dec_u64(step.index as u64);
+        print("] @ ");
+        print_hex_u64(step.addr as u64);
+        print(": ");
+        print_hex_u64(step.pte);
+        if step.pte & paging::PTE_V == 0 {
+            println(" invalid");
+        } else if paging::is_leaf(step.pte) {
+            print(" leaf ");
+            print_flags(step.pte);
+            print(" -> ");
+            print_hex_u64(paging::pte_addr(step.pte) as u64);
+            println("");
+        } else {
+            print(" table ");
+            print_hex_u64(paging::pte_addr(step.pte) as u64);
+            println("");
+        }
+    }
+
+    print("va ");
+    print_hex_u64(va as u64);
+    match (walk.len, walk.pa) {
+        (0, _) => println(": not canonical"),
+        (_, None) => println(": not mapped"),
+        (_, Some(pa)) => {
+            print(" -> pa ");
+            print_hex_u64(pa as u64);
+            println("");
+        }
+    }
+}
+
+fn cmd_pt_dump() {
+    if paging::current().is_none() {
+        println("no page table (use 'pt new')");
+        return;
+    }
+
+    let mut leaves = 0;
+    paging::for_each_leaf(|va, pa, size, pte| {
+        print("va ");
+        print_hex_u64(va as u64);
+        print(" -> pa ");
+        print_hex_u64(pa as u64);
+        print(match size {
+            0x1000 => "  4K ",
+            0x20_0000 => "  2M ",
+            0x4000_0000 => "  1G ",
+            _ => "  512G ",
+        });
+        print_flags(pte);
+        println("");
+        leaves += 1;
+    });
+    if leaves == 0 {
+        println("no mappings");
+    }
+}
+
+fn cmd_pt_satp(on: Option<bool>) {
+    #[cfg(feature = "supervisor")]
+    if on == Some(true) {
+        println("error: translation needs riscmon in machine mode (for boot/ujump)");
+        return;
+    }
+
+    if let Some(on) = on
+        && !paging::set_enabled(on)
+    {
+        println("error: no page table (use 'pt new')");
+        return;
+    }
+
+    let satp = paging::satp();
+    print("satp: ");
+    print_hex_u64(satp as u64);
+    match satp {
+        0 => println(" (translation off)"),
+        _ => println(" (translation on for boot/ujump programs)"),
+    }
+}
+
+#[cfg(not(feature = "supervisor"))]
+fn cmd_pmp(cmd: PmpCommand) {
+    let index = match cmd {
+        PmpCommand::Show => return cmd_pmp_show(),
+        PmpCommand::Off { index } => index,
+        PmpCommand::Range { index, .. } => index,
+        PmpCommand::Tor { index, .. } => index,
+    };
+
+    // Locked entries ignore writes until reset.
+    match trap::probe(|| pmp::read_cfg(index)) {
+        None => return println("error: entry not implemented on this hart"),
+        Some(cfg) if cfg & pmp::L != 0 => return println("error: entry is locked until reset"),
+        Some(_) => {}
+    }
+
+    let prev = match index {
+        0 => 0,
+        _ => trap::probe(|| pmp::read_addr(index - 1)).unwrap_or(0),
+    };
+    let (range, perms) = match cmd {
+        PmpCommand::Range {
+            base, size, perms, ..
+        } => match base.checked_add(size.wrapping_sub(1)) {
+            Some(end) if size > 0 => (Some((base, end)), perms),
+            _ => return println("error: invalid range"),
+        },
+        PmpCommand::Tor { top, perms, .. } => match top.checked_sub(1) {
+            Some(end) if top > prev << 2 => (Some((prev << 2, end)), perms),
+            _ => return println("error: TOP must be above the previous entry's address"),
+        },
+        _ => (None, 0),
+    };
+
+    // A lock would make `boot` and `ujump` silently run programs with the
+    // user's entry instead of their own.
+    if index < pmp::LAUNCH_ENTRIES && perms & pmp::L != 0 {
+        return println(
+            "error: entries 0 and 1 are reprogrammed by boot/ujump and cannot be locked",
+        );
+    }
+
+    // A locked entry binds machine mode too: refuse to lock riscmon out of
+    // its own memory.
+    if let Some((start, end)) = range
+        && perms & pmp::L != 0
+        && perms & pmp::RWX != pmp::RWX
+        && memory::ranges_overlap(start, end, memory::RAM_BASE, STACK_TOP - 1)
+    {
+        return println("error: refusing to lock a range over riscmon without rwx");
+    }
+
+    let done = trap::probe(|| match cmd {
+        PmpCommand::Range {
+            base, size, perms, ..
+        } => pmp::set_range(index, base, size, perms),
+        PmpCommand::Tor { top, perms, .. } => {
+            pmp::set_tor(index, top, perms);
+            true
+        }
+        _ => {
+            pmp::clear(index);
+            true
+        }
+    });
+
+    match done {
+        None => println("error: entry not implemented on this hart"),
+        Some(false) => {
+            println("error: range must be 4 bytes, or a power of two aligned to its size");
+            println("       (use 'pmp set N tor TOP PERMS' for other ranges)");
+        }
+        Some(true) => {
+            let (cfg, addr) =
+                trap::probe(|| (pmp::read_cfg(index), pmp::read_addr(index))).unwrap_or((0, 0));
+            print_pmp_entry(index, cfg, addr, prev);
+        }
+    }
+}
+
+#[cfg(not(feature = "supervisor"))]
+fn cmd_pmp_show() {
+    let mut prev = 0;
+    let mut entries = 0;
+    for index in 0..pmp::MAX_ENTRIES {
+        let Some((cfg, addr)) = trap::probe(|| (pmp::read_cfg(index), pmp::read_addr(index)))
+        else {
+            break;
+        };
+        print_pmp_entry(index, cfg, addr, prev);
+        prev = addr;
+        entries += 1;
+    }
+
+    print_dec_u64(entries as u64);
+    println(" entries implemented");
+}
+
+#[cfg(feature = "supervisor")]
+fn cmd_pmp(_cmd: PmpCommand) {
+    println("error: pmp needs riscmon in machine mode");
+}
+
+fn cmd_blk(blk: BlkCommand) {
+    match blk {
+        BlkCommand::Info => cmd_blk_info(),
+        BlkCommand::Read { lba, count, addr } => cmd_blk_transfer(lba, count, addr, false),
+        BlkCommand::Write { lba, count, addr } => cmd_blk_transfer(lba, count, addr, true),
+    }
+}
+
+fn cmd_blk_info() {
+    let info = match virtio::blk::info() {
+        Ok(info) => info,
+        Err(e) => {
+            print("error: ");
+            println(e);
+            return;
+        }
+    };
+
+    print("virtio-blk @ ");
+    print_hex_u32(info.dev.base as u32);
+    print(" (virtio-mmio v");
+    print_dec_u64(info.dev.version as u64);
+    println(")");
+    print("capacity: ");
+    print_dec_u64(info.sectors);
+    print(" sectors (");
+    print_dec_u64(info.sectors * virtio::blk::SECTOR_SIZE as u64 / 1024);
+    print(" KiB)");
+    println(if info.read_only { ", read-only" } else { "" });
+}
+
+fn cmd_blk_transfer(lba: u64, count: u64, addr: usize, write: bool) {
+    if count == 0 {
+        println("error: no sectors");
+        return;
+    }
+
+    let end = (count as usize)
+        .checked_mul(virtio::blk::SECTOR_SIZE)
+        .and_then(|len| addr.checked_add(len - 1));
+    let Some(end) = end.filter(|&end| memory::is_in_ram(addr) && memory::is_in_ram(end)) else {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    };
+
+    // The device writes memory directly, so nothing of riscmon's may be in
+    // the way.
+    if !write && memory::ranges_overlap(addr, end, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: read into riscmon's memory not allowed");
+        return;
+    }
+
+    let result = match write {
+        true => virtio::blk::write(lba, count, addr),
+        false => virtio::blk::read(lba, count, addr),
+    };
+    if let Err(e) = result {
+        print("error: ");
+        println(e);
+        return;
+    }
+
+    print(if write { "wrote " } else { "read " });
+    print_dec_u64(count);
+    print(" sector(s) ");
+    print(if write { "from " } else { "to " });
+    print_hex_u32(addr as u32);
+    print("..");
+    print_hex_u32(end as u32);
+    println("");
+}
+
+fn cmd_tftp(addr: usize, filename: &str) {
+    if !memory::is_in_ram(addr) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+
+    // The file may run up to the end of RAM, but not into riscmon.
+    if memory::ranges_overlap(addr, addr, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: download into riscmon's memory not allowed");
+        return;
+    }
+
+    match net::tftp::get(net::SERVER_IP, filename, addr, memory::RAM_END_INCLUSIVE) {
+        Ok(0) => println("loaded 0 bytes"),
+        Ok(size) => {
+            vars::set_loaded(addr, size);
+            print("loaded ");
+            print_dec_u64(size as u64);
+            print(" bytes to ");
+            print_hex_u32(addr as u32);
+            print("..");
+            print_hex_u32((addr + size - 1) as u32);
+            println("");
+            load_symbols_if_elf(addr, size);
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_fwcfg(cmd: FwcfgCommand) {
+    let Some(fw) = fwcfg::FwCfg::find() else {
+        println("error: no fw_cfg device");
+        return;
+    };
+
+    match cmd {
+        FwcfgCommand::List => cmd_fwcfg_list(&fw),
+        FwcfgCommand::Load { name, addr } => cmd_fwcfg_load(&fw, name, addr),
+    }
+}
+
+fn cmd_fwcfg_list(fw: &fwcfg::FwCfg) {
+    print("fw_cfg @ ");
+    print_hex_u32(fw.base as u32);
+    println(if fw.dma { " (dma)" } else { "" });
+
+    let mut files = 0;
+    fw.for_each_file(|file| {
+        print("  ");
+        print_hex_u32(file.size);
+        print("  ");
+        println(file.name());
+        files += 1;
+    });
+    if files == 0 {
+        println("  no files");
+    }
+}
+
+fn cmd_fwcfg_load(fw: &fwcfg::FwCfg, name: &str, addr: usize) {
+    let Some(file) = fw.find_file(name) else {
+        println("error: no such file (see 'fwcfg list')");
+        return;
+    };
+    if file.size == 0 {
+        println("loaded 0 bytes");
+        return;
+    }
+
+    let end = addr.checked_add(file.size as usize - 1);
+    let Some(end) = end.filter(|&end| memory::is_in_ram(addr) && memory::is_in_ram(end)) else {
+        println("error: file does not fit in RAM at that address");
+        print_valid_address_ranges();
+        return;
+    };
+    if memory::ranges_overlap(addr, end, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: load into riscmon's memory not allowed");
+        return;
+    }
+
+    if let Err(e) = fw.load(&file, addr) {
+        print("error: ");
+        println(e);
+        return;
+    }
+
+    vars::set_loaded(addr, file.size as usize);
+    print("loaded ");
+    print_dec_u64(file.size as u64);
+    print(" bytes to ");
     print_hex_u32(addr as u32);
-    println(" ...");
+    print("..");
+    print_hex_u32(end as u32);
+    println("");
+    load_symbols_if_elf(addr, file.size as usize);
+}
 
-    unsafe {
-        // Call manually instead of casting addr to a function pointer because
-        // a normal Rust call would push a new stack frame. If the callee is
-        // untrusted it could clobber sp and break unwinding back to the REPL.
-        // We save sp in a callee-saved register before jalr and restore it
-        // after, ensuring the REPL stack remains intact regardless of the
-        // callee's actions.
-        //
-        // Use s2 because it's a callee-saved GPR (not the frame pointer or a
-        // reserved platform register) and Rust/LLVM do not reserve it, so it's
-        // safe to list in the asm constraints.
-        //
-        // Note: The called code must follow the RISC-V calling convention and
-        // preserve all callee-saved registers (s0–s11, fs0–fs11). If it does not,
-        // the REPL state will be corrupted on return.
-        core::arch::asm!(
-            "mv   s2, sp",       // stash riscmon's sp in s2
-            "jalr ra, {addr}",   // call the target; ra holds the return address
-            "mv   sp, s2",       // restore riscmon's sp from s2 on return
-            addr = in(reg) addr,
-            // indicate use of registers to the compiler so it doesn't clobber
-            out("s2") _,
-            out("ra") _,
-            out("t1") _, out("t2") _, out("t3") _, out("t4") _, out("t5") _, out("t6") _,
-            out("a0") _, out("a1") _, out("a2") _, out("a3") _,
-            out("a4") _, out("a5") _, out("a6") _, out("a7") _,
-        );
+fn cmd_sh(cmd: ShCommand) {
+    match cmd {
+        ShCommand::Load { path, addr } => cmd_sh_load(path, addr),
+        ShCommand::Save { path, addr, len } => cmd_sh_save(path, addr, len),
+        ShCommand::Exit { code } => {
+            if let Err(e) = semihost::exit(code as usize) {
+                print("error: ");
+                println(e);
+            }
+        }
+    }
+}
+
+fn cmd_sh_load(path: &str, addr: usize) {
+    if !memory::is_in_ram(addr) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+    if memory::ranges_overlap(addr, addr, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: load into riscmon's memory not allowed");
+        return;
+    }
+
+    match semihost::load(path, addr, memory::RAM_END_INCLUSIVE) {
+        Ok(0) => println("loaded 0 bytes"),
+        Ok(size) => {
+            vars::set_loaded(addr, size);
+            print("loaded ");
+            print_dec_u64(size as u64);
+            print(" bytes to ");
+            print_hex_u32(addr as u32);
+            print("..");
+            print_hex_u32((addr + size - 1) as u32);
+            println("");
+            load_symbols_if_elf(addr, size);
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_sh_save(path: &str, addr: usize, len: usize) {
+    if len == 0 {
+        println("error: no data");
+        return;
+    }
+    let end = addr.checked_add(len - 1);
+    let Some(end) = end.filter(|&end| memory::is_in_ram(addr) && memory::is_in_ram(end)) else {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    };
+
+    if let Err(e) = semihost::save(path, addr, len) {
+        print("error: ");
+        println(e);
+        return;
+    }
+
+    print("saved ");
+    print_dec_u64(len as u64);
+    print(" bytes from ");
+    print_hex_u32(addr as u32);
+    print("..");
+    print_hex_u32(end as u32);
+    println("");
+}
+
+fn cmd_pci(cmd: PciCommand) {
+    let Some(host) = pci::Host::find() else {
+        println("error: no PCIe host bridge");
+        return;
+    };
+
+    match cmd {
+        PciCommand::List => cmd_pci_list(&host),
+        PciCommand::Read { bdf, off, width } => {
+            print_pci_value(host.read(bdf, off, width), width);
+        }
+        PciCommand::Write {
+            bdf,
+            off,
+            width,
+            value,
+        } => {
+            host.write(bdf, off, width, value);
+            print_pci_value(host.read(bdf, off, width), width);
+        }
+        PciCommand::Assign => cmd_pci_assign(&host),
+    }
+}
+
+fn cmd_pci_list(host: &pci::Host) {
+    print("pcie @ ");
+    print_hex_u32(host.base as u32);
+    print(", buses ");
+    uart::print_hex_u8(host.bus_start);
+    print("..");
+    uart::print_hex_u8(host.bus_end);
+    println("");
+
+    let mut functions = 0;
+    host.for_each_function(|bdf| {
+        let class = host.class(bdf);
+        print_bdf(bdf);
+        print(" ");
+        uart::print_hex_u16(host.vendor(bdf));
+        print(":");
+        uart::print_hex_u16(host.device(bdf));
+        print(" ");
+        uart::print_hex_u8((class >> 16) as u8);
+        uart::print_hex_u16(class as u16);
+        print(" ");
+        println(pci::class_name(class));
+
+        host.bars(bdf, |bar| {
+            print("  bar");
+            print_dec_u64(bar.index as u64);
+            print(match bar.kind {
+                pci::BarKind::Io => " io    ",
+                pci::BarKind::Mem32 => " mem32 ",
+                pci::BarKind::Mem64 => " mem64 ",
+            });
+            match bar.addr {
+                0 => print("unassigned"),
+                a if a > u32::MAX as u64 => print_hex_u64(a),
+                a => print_hex_u32(a as u32),
+            }
+            print("  size ");
+            print_size(bar.size);
+            if bar.prefetchable {
+                print(", prefetchable");
+            }
+            let cpu = host.cpu_addr(bar);
+            if bar.addr != 0 && cpu != bar.addr {
+                print(" (cpu ");
+                print_hex_u64(cpu);
+                print(")");
+            }
+            println("");
+        });
+        functions += 1;
+    });
+    if functions == 0 {
+        println("  no devices");
+    }
+}
+
+fn cmd_pci_assign(host: &pci::Host) {
+    match host.assign() {
+        Ok((bars, last_bus)) => {
+            print("assigned ");
+            print_dec_u64(bars as u64);
+            print(" BAR(s), buses ");
+            uart::print_hex_u8(host.bus_start);
+            print("..");
+            uart::print_hex_u8(last_bus);
+            println("");
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_date(set: Option<u64>) {
+    if let Some(ns) = set
+        && let Err(e) = rtc::set_ns(ns)
+    {
+        print("error: ");
+        println(e);
+        return;
+    }
+
+    match rtc::now_ns() {
+        Some(ns) => {
+            print_timestamp(ns);
+            println("");
+        }
+        None => println("error: no Goldfish RTC"),
+    }
+}
+
+fn cmd_rand(cmd: RandCommand) {
+    let (start, end) = match cmd {
+        RandCommand::Seed { seed } => {
+            random::seed(seed);
+            println(match seed {
+                Some(_) => "fills now come from the seeded PRNG",
+                None => "fills now come from virtio-rng (or an unseeded PRNG without one)",
+            });
+            return;
+        }
+        RandCommand::Fill { start, end } => (start, end),
+    };
+
+    if end < start {
+        println("error: end < start");
+        return;
+    }
+    if !memory::is_in_ram(start) || !memory::is_in_ram(end) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+    if memory::ranges_overlap(start, end, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: fill of riscmon's memory not allowed");
+        return;
+    }
+
+    let len = end - start + 1;
+    match random::fill(start, len) {
+        Ok(source) => {
+            print("filled ");
+            print_dec_u64(len as u64);
+            print(" bytes at ");
+            print_hex_u32(start as u32);
+            print("..");
+            print_hex_u32(end as u32);
+            println(match source {
+                random::Source::Device => " from virtio-rng",
+                random::Source::Prng if random::is_seeded() => " from the seeded PRNG",
+                random::Source::Prng => " from the PRNG (no virtio-rng device)",
+            });
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+fn cmd_irq(cmd: IrqCommand) {
+    let Some(plic) = plic::get() else {
+        println("error: no PLIC");
+        return;
+    };
+
+    let source = match cmd {
+        IrqCommand::List => return cmd_irq_list(&plic),
+        IrqCommand::Threshold { value } if value > plic::MAX_PRIORITY => {
+            println("error: threshold out of range (0..7)");
+            return;
+        }
+        IrqCommand::Threshold { value } => return plic.set_threshold(value),
+        IrqCommand::Enable { source, .. } | IrqCommand::Disable { source } => source,
+    };
+    if source == 0 || source > plic.sources {
+        print("error: no such source (1..");
+        print_dec_u64(plic.sources as u64);
+        println(")");
+        return;
+    }
+
+    match cmd {
+        IrqCommand::Enable { priority, .. } if priority == 0 || priority > plic::MAX_PRIORITY => {
+            println("error: priority out of range (1..7)");
+        }
+        IrqCommand::Enable { priority, .. } => {
+            plic.set_priority(source, priority);
+            plic.set_enabled(source, true);
+        }
+        _ => plic.set_enabled(source, false),
+    }
+}
+
+fn cmd_irq_list(plic: &plic::Plic) {
+    print("plic @ ");
+    print_hex_u32(plic.base as u32);
+    print(", ");
+    print_dec_u64(plic.sources as u64);
+    print(" sources, context ");
+    print_dec_u64(plic.context as u64);
+    print(" (hart ");
+    print_dec_u64(system::boot_hart() as u64);
+    print(", ");
+    print(csr::NAME);
+    print("), threshold ");
+    print_dec_u64(plic.threshold() as u64);
+    println("");
+
+    let mut listed = 0;
+    for source in 1..=plic.sources {
+        let (priority, enabled) = (plic.priority(source), plic.is_enabled(source));
+        let (pending, count) = (plic.is_pending(source), plic::count(source));
+        if priority == 0 && !enabled && !pending && count == 0 {
+            continue;
+        }
+        print("  source ");
+        print_dec_u64(source as u64);
+        print(": priority ");
+        print_dec_u64(priority as u64);
+        print(if enabled { ", enabled" } else { ", disabled" });
+        if pending {
+            print(", pending");
+        }
+        print(", ");
+        print_dec_u64(count as u64);
+        println(" claimed");
+        listed += 1;
+    }
+    if listed == 0 {
+        println("  no active sources");
+    }
+}
+
+fn cmd_memtest(start: usize, end: usize, pattern: u64, test: Option<memtest::Test>, passes: u32) {
+    if end < start {
+        println("error: end < start");
+        return;
+    }
+    if !memory::is_in_ram(start) || !memory::is_in_ram(end) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+    if !start.is_multiple_of(8) || !(end - start + 1).is_multiple_of(8) {
+        println("error: START and END+1 must be 8-byte aligned");
+        return;
+    }
+    if memory::ranges_overlap(start, end, memory::RAM_BASE, STACK_TOP - 1) {
+        println("error: test of riscmon's memory not allowed");
+        return;
+    }
+
+    let words = (end - start + 1) / 8;
+    let single;
+    let tests: &[memtest::Test] = match test {
+        Some(t) => {
+            single = [t];
+            &single
+        }
+        None => &memtest::Test::ALL,
+    };
+
+    print("testing ");
+    print_hex_u32(start as u32);
+    print("..");
+    print_hex_u32(end as u32);
+    print(" (");
+    print_dec_u64(words as u64);
+    println(" words), Ctrl+C to stop");
+
+    let mut pass = 0u32;
+    while passes == 0 || pass < passes {
+        pass += 1;
+        print("pass ");
+        print_dec_u64(pass as u64);
+        print(":");
+        for &t in tests {
+            print(" ");
+            print(t.name());
+            match memtest::run(t, start, words, pattern) {
+                Ok(()) => print(" ok"),
+                Err(memtest::Stop::Interrupted) => {
+                    println(" interrupted");
+                    return;
+                }
+                Err(memtest::Stop::Failed(f)) => {
+                    println(" FAILED");
+                    print("error: ");
+                    print(t.name());
+                    print(" failed at ");
+                    print_hex_u32(f.addr as u32);
+                    print(": expected ");
+                    print_hex_u64(f.expected);
+                    print(", read ");
+                    print_hex_u64(f.actual);
+                    println("");
+                    return;
+                }
+            }
+        }
+        println("");
+    }
+    print("memtest passed (");
+    print_dec_u64(pass as u64);
+    println(" pass(es))");
+}
+
+fn cmd_checksum(kind: checksum::Kind, start: usize, end: usize) {
+    if end < start {
+        println("error: end < start");
+        return;
+    }
+    if !memory::is_in_ram(start) || !memory::is_in_ram(end) {
+        println("error: address out of range");
+        print_valid_address_ranges();
+        return;
+    }
+
+    let mut hasher = checksum::Hasher::new(kind);
+    let mut addr = start;
+    loop {
+        if uart::poll_break() {
+            println("interrupted");
+            return;
+        }
+        let chunk_end = end.min(addr.saturating_add(CHECKSUM_CHUNK - 1));
+        let data = unsafe { core::slice::from_raw_parts(addr as *const u8, chunk_end - addr + 1) };
+        hasher.update(data);
+        if chunk_end == end {
+            break;
+        }
+        addr = chunk_end + 1;
+    }
+
+    let (digest, len) = hasher.finish();
+    print(kind.name());
+    print(" ");
+    print_hex_u32(start as u32);
+    print("..");
+    print_hex_u32(end as u32);
+    print(" (");
+    print_dec_u64((end - start + 1) as u64);
+    print(" bytes): ");
+    for &b in &digest[..len] {
+        uart::print_hex_u8(b);
+    }
+    println("");
+}
+
+fn cmd_calc(value: u64) {
+    print("hex    ");
+    print_hex_u64(value);
+    println("");
+    print("dec    ");
+    print_dec_u64(value);
+    println("");
+    print("signed ");
+    if (value as i64) < 0 {
+        print("-");
+    }
+    print_dec_u64((value as i64).unsigned_abs());
+    println("");
+    print("bin    ");
+    let bits = (u64::BITS - value.leading_zeros()).max(1);
+    for i in (0..bits).rev() {
+        print(if value >> i & 1 != 0 { "1" } else { "0" });
+    }
+    println("");
+}
+
+fn cmd_set(name: &str, value: u64) {
+    if let Err(e) = vars::set(name, value) {
+        print("error: ");
+        println(e);
+    }
+}
+
+fn cmd_unset(name: &str) {
+    if let Err(e) = vars::unset(name) {
+        print("error: ");
+        println(e);
+    }
+}
+
+fn cmd_vars() {
+    vars::for_each(|name, value, builtin| {
+        print(name);
+        for _ in name.len()..vars::MAX_NAME {
+            print(" ");
+        }
+        print(" ");
+        print_hex_u64(value);
+        println(if builtin { "  (built-in)" } else { "" });
+    });
+}
+
+fn cmd_sym(cmd: SymCommand) {
+    match cmd {
+        SymCommand::Summary => {
+            print_dec_u64(symbols::count() as u64);
+            println(" symbols (sym list to show them)");
+        }
+        SymCommand::List => symbols::for_each(|name, addr, _| {
+            print_hex_u32(addr as u32);
+            print("  ");
+            println(name);
+        }),
+        SymCommand::Clear => symbols::clear(),
+        SymCommand::Paste => {
+            symbols::set_pasting(true);
+            println("paste ADDR NAME or nm-style ADDR TYPE NAME lines; end with an empty line");
+        }
+        SymCommand::Load { addr } => {
+            if !memory::is_in_ram(addr) {
+                println("error: address out of range");
+                print_valid_address_ranges();
+                return;
+            }
+            let len = memory::RAM_END_INCLUSIVE - addr + 1;
+            let image = unsafe { core::slice::from_raw_parts(addr as *const u8, len) };
+            print_symbols_loaded(symbols::load_elf(image));
+        }
+        SymCommand::Lookup(arg) => {
+            if let Some(addr) = symbols::find(arg) {
+                print(arg);
+                print(" = ");
+                print_hex_u32(addr as u32);
+                println("");
+                return;
+            }
+            match expr::eval(arg) {
+                Ok(addr) => {
+                    print_hex_u32(addr as u32);
+                    match symbols::lookup(addr as usize) {
+                        Some(_) => symbols::print_annotation(addr as usize),
+                        None => print(" (no symbol)"),
+                    }
+                    println("");
+                }
+                Err(e) => {
+                    print("error: ");
+                    println(e);
+                }
+            }
+        }
+    }
+}
+
+fn cmd_history() {
+    for n in history::oldest()..=history::newest() {
+        let Some(line) = history::get(n) else {
+            continue;
+        };
+        print("  ");
+        print_dec_u64(n as u64);
+        for _ in n.ilog10()..4 {
+            print(" ");
+        }
+        for &b in line {
+            uart::putc(b);
+        }
+        println("");
+    }
+}
+
+fn cmd_continue() {
+    match trap::resume() {
+        Some(exit) => report_exit(exit),
+        None => println("error: no stopped program to continue"),
     }
+}
+
+fn cmd_regs() {
+    let frame = trap::saved_frame();
 
-    println("returned from jump");
+    print("pc   ");
+    print_hex_u64(frame.pc as u64);
+    symbols::print_annotation(frame.pc);
+    print("  cause ");
+    println(trap::cause_name(frame.cause));
+
+    for (i, name) in trap::REG_NAMES.iter().enumerate() {
+        print(name);
+        for _ in name.len()..5 {
+            print(" ");
+        }
+        print_hex_u64(frame.regs[i] as u64);
+        match i % 4 {
+            3 => println(""),
+            _ => print("  "),
+        }
+    }
 }
 
 // -----------------------------------------------------------------------------
 // Helpers
 // -----------------------------------------------------------------------------
 
+// Take one line pasted after `sym paste`: `ADDR NAME`, or `ADDR TYPE NAME`
+// as printed by `nm`. An empty line (or Ctrl+C) ends pasting.
+fn sym_paste_line(line: &str) {
+    if line.is_empty() {
+        symbols::set_pasting(false);
+        print_dec_u64(symbols::count() as u64);
+        println(" symbols");
+        return;
+    }
+
+    let mut t = line.split_whitespace();
+    let fields = match (t.next(), t.next(), t.next(), t.next()) {
+        (Some(addr), Some(name), None, None) => Some((addr, name)),
+        (Some(addr), Some(kind), Some(name), None) if kind.len() == 1 => Some((addr, name)),
+        _ => None,
+    };
+    let Some((addr, name)) = fields.and_then(|(a, n)| Some((hex::parse_hex_usize(a)?, n))) else {
+        println("error: expected ADDR [TYPE] NAME (an empty line ends pasting)");
+        return;
+    };
+    if let Err(e) = symbols::add(name, addr, 0) {
+        print("error: ");
+        println(e);
+    }
+}
+
+// After a file is loaded, take the symbols from it if it is an ELF file.
+fn load_symbols_if_elf(addr: usize, size: usize) {
+    let image = unsafe { core::slice::from_raw_parts(addr as *const u8, size) };
+    if elf::is_elf(image) {
+        print_symbols_loaded(symbols::load_elf(image));
+    }
+}
+
+fn print_symbols_loaded(result: Result<usize, &'static str>) {
+    match result {
+        Ok(n) => {
+            print("loaded ");
+            print_dec_u64(n as u64);
+            println(" symbols");
+        }
+        Err(e) => {
+            print("error: ");
+            println(e);
+        }
+    }
+}
+
+// Print one decoded PMP entry.
+#[cfg(not(feature = "supervisor"))]
+fn print_pmp_entry(index: usize, cfg: u8, addr: usize, prev_addr: usize) {
+    print("pmp");
+    print_dec_u64(index as u64);
+    print(match index {
+        0..=9 => ":  ",
+        _ => ": ",
+    });
+    print(match cfg & pmp::A_MASK {
+        pmp::A_OFF => "OFF  ",
+        pmp::A_TOR => "TOR  ",
+        pmp::A_NA4 => "NA4  ",
+        _ => "NAPOT",
+    });
+    print(" ");
+    for (bit, c) in [
+        (pmp::R, b'r'),
+        (pmp::W, b'w'),
+        (pmp::X, b'x'),
+        (pmp::L, b'l'),
+    ] {
+        uart::putc(if cfg & bit != 0 { c } else { b'-' });
+    }
+    print("  addr ");
+    print_hex_u64(addr as u64);
+    if let Some((start, end)) = pmp::decode_range(cfg, addr, prev_addr) {
+        print("  ");
+        print_hex_u64(start as u64);
+        print("..");
+        print_hex_u64(end as u64);
+    }
+    println("");
+}
+
+// Parse PMP permissions: letters from `rwxl`, or `-` for none.
+#[cfg(not(feature = "supervisor"))]
+fn parse_pmp_perms(s: &str) -> Option<u8> {
+    let mut perms = 0;
+    for c in s.bytes() {
+        perms |= match c {
+            b'r' => pmp::R,
+            b'w' => pmp::W,
+            b'x' => pmp::X,
+            b'l' => pmp::L,
+            b'-' => 0,
+            _ => return None,
+        };
+    }
+    Some(perms)
+}
+
+// Print a PTE's flags as `rwxugad`.
+fn print_flags(pte: u64) {
+    for c in paging::flag_string(pte) {
+        uart::putc(c);
+    }
+}
+
+// Print a PCI address as BUS:DEV.FN.
+fn print_bdf(bdf: pci::Bdf) {
+    uart::print_hex_u8(bdf.bus);
+    print(":");
+    uart::print_hex_u8(bdf.dev);
+    print(".");
+    print_dec_u64(bdf.func as u64);
+}
+
+// Print a config space value with as many digits as its width.
+fn print_pci_value(v: u32, width: usize) {
+    match width {
+        1 => uart::print_hex_u8(v as u8),
+        2 => uart::print_hex_u16(v as u16),
+        _ => print_hex_u32(v),
+    }
+    println("");
+}
+
+// Print a byte count in the largest unit that divides it.
+fn print_size(size: u64) {
+    let (n, unit) = match size {
+        s if s >= 1 << 30 && s % (1 << 30) == 0 => (s >> 30, " GiB"),
+        s if s >= 1 << 20 && s % (1 << 20) == 0 => (s >> 20, " MiB"),
+        s if s >= 1 << 10 && s % (1 << 10) == 0 => (s >> 10, " KiB"),
+        s => (s, " bytes"),
+    };
+    print_dec_u64(n);
+    print(unit);
+}
+
+// Parse a PCI address: BUS:DEV.FN, bus and device in hex.
+fn parse_bdf(s: &str) -> Option<pci::Bdf> {
+    let (bus, rest) = s.split_once(':')?;
+    let (dev, func) = rest.split_once('.')?;
+    let (bus, dev, func) = (
+        u8::from_str_radix(bus, 16).ok()?,
+        u8::from_str_radix(dev, 16).ok()?,
+        func.parse::<u8>().ok()?,
+    );
+    (dev < 32 && func < 8).then_some(pci::Bdf { bus, dev, func })
+}
+
+// Print an RTC time as an ISO 8601 UTC timestamp with milliseconds.
+fn print_timestamp(ns: u64) {
+    let dt = rtc::DateTime::from_unix_ns(ns);
+    print_dec_padded(dt.year as u64, 4);
+    print("-");
+    print_dec_padded(dt.month as u64, 2);
+    print("-");
+    print_dec_padded(dt.day as u64, 2);
+    print("T");
+    print_dec_padded(dt.hour as u64, 2);
+    print(":");
+    print_dec_padded(dt.minute as u64, 2);
+    print(":");
+    print_dec_padded(dt.second as u64, 2);
+    print(".");
+    print_dec_padded(dt.nanos as u64 / 1_000_000, 3);
+    print("Z");
+}
+
+// Print `label` and the wall-clock time, so timed runs can be matched up
+// with host logs. Says nothing without an RTC.
+fn print_run_time(label: &str) {
+    if let Some(ns) = rtc::now_ns() {
+        print(label);
+        print_timestamp(ns);
+        println("");
+    }
+}
+
+// Print a decimal value with leading zeros to at least `width` digits.
+fn print_dec_padded(v: u64, width: u32) {
+    let mut digits = 1;
+    while digits < 20 && v >= 10u64.pow(digits) {
+        digits += 1;
+    }
+    for _ in digits..width {
+        print("0");
+    }
+    print_dec_u64(v);
+}
+
+// Tell the user how a jumped-to program handed control back.
+fn report_exit(exit: trap::Exit) {
+    let frame = trap::saved_frame();
+
+    match exit {
+        trap::Exit::Returned => println("returned from jump"),
+        trap::Exit::Break => {
+            print("break at ");
+            print_hex_u32(frame.pc as u32);
+            symbols::print_annotation(frame.pc);
+            println(" (use 'continue' to resume, 'regs' to inspect)");
+        }
+        trap::Exit::Reset => println("payload requested a reboot"),
+        trap::Exit::Exited(code) => {
+            print("program exited with code ");
+            print_dec_u64(code as u64);
+            println("");
+        }
+        trap::Exit::Ecall => {
+            print("ecall at ");
+            print_hex_u32(frame.pc.wrapping_sub(4) as u32);
+            symbols::print_annotation(frame.pc.wrapping_sub(4));
+            print(" (a7 ");
+            print_hex_u64(frame.regs[17] as u64);
+            print(", a0 ");
+            print_hex_u64(frame.regs[10] as u64);
+            println(") (use 'continue' to resume, 'regs' to inspect)");
+        }
+        trap::Exit::Timeout => {
+            print("timeout: program stopped at ");
+            print_hex_u32(frame.pc as u32);
+            symbols::print_annotation(frame.pc);
+            println("");
+        }
+        trap::Exit::Fault => {
+            print("fault: ");
+            print(trap::cause_name(frame.cause));
+            print(" at ");
+            print_hex_u32(frame.pc as u32);
+            symbols::print_annotation(frame.pc);
+            print(" (tval ");
+            print_hex_u64(frame.tval as u64);
+            println(")");
+        }
+    }
+}
+
 const MAX_DUMP_BYTES: usize = 256;
+#[cfg(not(feature = "supervisor"))]
+const UJUMP_WINDOW_SIZE: usize = 1024 * 1024;
 const MAX_WRITE_BYTES: usize = 32;
+// Bytes checksummed between checks for Ctrl+C.
+const CHECKSUM_CHUNK: usize = 64 * 1024;
 
 fn parse_write_bytes(data_s: &str) -> Result<([u8; MAX_WRITE_BYTES], usize), ()> {
     let mut bytes = [0u8; MAX_WRITE_BYTES];
@@ -0,0 +1,247 @@
+use crate::{
+    repl::{runner::get_current_addr, symbols, vars},
+    trap,
+};
+
+// -----------------------------------------------------------------------------
+// Expression Evaluator
+// -----------------------------------------------------------------------------
+
+// Integer expressions over u64 with C precedence, lowest first:
+//
+//   |   ^   &   << >>   + -   * / %   unary - ~ +
+//
+// Operands are numbers, `.` (the current address), register names from the
+// last stopped program's frame, variables, symbols, and parenthesized
+// expressions.
+// Numbers are hex unless prefixed: `0x` hex, `#` or `0d` decimal, `0b` binary.
+// A name that is not a register or variable but is made of hex digits
+// (`beef`) is a number, even if a symbol has that name.
+// Arithmetic wraps; shifting by 64 or more gives 0.
+
+/// Evaluate an expression, or describe why it is invalid.
+pub(crate) fn eval(s: &str) -> Result<u64, &'static str> {
+    let mut p = Parser {
+        s: s.as_bytes(),
+        pos: 0,
+    };
+    let v = p.or()?;
+    p.skip_spaces();
+    match p.pos == p.s.len() {
+        true => Ok(v),
+        false => Err("unexpected character"),
+    }
+}
+
+/// Evaluate an address or value argument, for parsers that report their own
+/// usage errors.
+pub(crate) fn parse_usize(s: &str) -> Option<usize> {
+    eval(s).ok().map(|v| v as usize)
+}
+
+/// Split a two-part argument such as `START.END` or `BASE+SIZE` at the first
+/// of `seps` that follows an operand outside parentheses, so each side can be
+/// an expression: `buf.buf+ff` splits after `buf`, `.+10` after the `.`, and
+/// `(buf+10)+20` at the `+` outside the parentheses.
+pub(crate) fn split_pair<'a>(s: &'a str, seps: &[u8]) -> Option<(&'a str, u8, &'a str)> {
+    let bytes = s.as_bytes();
+    let mut depth = 0usize;
+    let mut after_operand = false;
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'(' => depth += 1,
+            b')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && after_operand && seps.contains(&b) => {
+                return Some((&s[..i], b, &s[i + 1..]));
+            }
+            _ => {}
+        }
+        if !b.is_ascii_whitespace() {
+            after_operand = b.is_ascii_alphanumeric() || matches!(b, b'_' | b')' | b'.');
+        }
+    }
+    None
+}
+
+// Resolve a name to a value.
+fn lookup(name: &str) -> Option<u64> {
+    let frame = trap::saved_frame();
+    let reg = match name {
+        "pc" => return Some(frame.pc as u64),
+        "fp" => 8,
+        _ => match trap::REG_NAMES.iter().position(|&r| r == name) {
+            Some(reg) => reg,
+            None if name.bytes().all(|b| b.is_ascii_hexdigit()) => return vars::get(name),
+            None => return vars::get(name).or_else(|| symbols::find(name).map(|a| a as u64)),
+        },
+    };
+    Some(frame.regs[reg] as u64)
+}
+
+// -----------------------------------------------------------------------------
+// Parser
+// -----------------------------------------------------------------------------
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn or(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.xor()?;
+        while self.eat("|") {
+            v |= self.xor()?;
+        }
+        Ok(v)
+    }
+
+    fn xor(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.and()?;
+        while self.eat("^") {
+            v ^= self.and()?;
+        }
+        Ok(v)
+    }
+
+    fn and(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.shift()?;
+        while self.eat("&") {
+            v &= self.shift()?;
+        }
+        Ok(v)
+    }
+
+    fn shift(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.add()?;
+        loop {
+            if self.eat("<<") {
+                let n = self.add()?;
+                v = if n < 64 { v << n } else { 0 };
+            } else if self.eat(">>") {
+                let n = self.add()?;
+                v = if n < 64 { v >> n } else { 0 };
+            } else {
+                return Ok(v);
+            }
+        }
+    }
+
+    fn add(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.mul()?;
+        loop {
+            if self.eat("+") {
+                v = v.wrapping_add(self.mul()?);
+            } else if self.eat("-") {
+                v = v.wrapping_sub(self.mul()?);
+            } else {
+                return Ok(v);
+            }
+        }
+    }
+
+    fn mul(&mut self) -> Result<u64, &'static str> {
+        let mut v = self.unary()?;
+        loop {
+            if self.eat("*") {
+                v = v.wrapping_mul(self.unary()?);
+            } else if self.eat("/") {
+                v = v.checked_div(self.unary()?).ok_or("division by zero")?;
+            } else if self.eat("%") {
+                v = v.checked_rem(self.unary()?).ok_or("division by zero")?;
+            } else {
+                return Ok(v);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<u64, &'static str> {
+        if self.eat("-") {
+            Ok(self.unary()?.wrapping_neg())
+        } else if self.eat("~") {
+            Ok(!self.unary()?)
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<u64, &'static str> {
+        if self.eat("(") {
+            let v = self.or()?;
+            return match self.eat(")") {
+                true => Ok(v),
+                false => Err("missing ')'"),
+            };
+        }
+        if self.eat(".") {
+            return Ok(get_current_addr() as u64);
+        }
+        if self.eat("#") {
+            return number(self.word(), 10);
+        }
+
+        let word = self.word();
+        let lower = |p: &str| word.len() > 2 && word[..2].eq_ignore_ascii_case(p);
+        match word.bytes().next() {
+            None => Err("expected a number or name"),
+            Some(b'0') if lower("0x") => number(&word[2..], 16),
+            Some(b'0') if lower("0d") => number(&word[2..], 10),
+            Some(b'0') if lower("0b") => number(&word[2..], 2),
+            Some(b'0'..=b'9') => number(word, 16),
+            Some(_) => match lookup(word) {
+                Some(v) => Ok(v),
+                None if word.bytes().all(|b| b.is_ascii_hexdigit()) => number(word, 16),
+                None => Err("unknown name"),
+            },
+        }
+    }
+
+    // Skip spaces, then consume `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_spaces();
+        match self.s[self.pos..].starts_with(token.as_bytes()) {
+            true => {
+                self.pos += token.len();
+                true
+            }
+            false => false,
+        }
+    }
+
+    // Skip spaces, then consume a run of letters, digits and underscores.
+    fn word(&mut self) -> &str {
+        self.skip_spaces();
+        let start = self.pos;
+        while self
+            .s
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'_')
+        {
+            self.pos += 1;
+        }
+        // Only ASCII was consumed, so this cannot fail.
+        core::str::from_utf8(&self.s[start..self.pos]).unwrap_or("")
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.s.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+fn number(digits: &str, radix: u32) -> Result<u64, &'static str> {
+    if digits.is_empty() {
+        return Err("expected digits");
+    }
+    u64::from_str_radix(digits, radix).map_err(|e| match e.kind() {
+        core::num::IntErrorKind::PosOverflow => "number too large",
+        _ => "invalid digit",
+    })
+}
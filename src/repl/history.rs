@@ -0,0 +1,67 @@
+use crate::repl::runner::LINE_BUF_CAP;
+
+// -----------------------------------------------------------------------------
+// Command History
+// -----------------------------------------------------------------------------
+
+// A ring of the most recent command lines. Entries are numbered from 1 in the
+// order they were entered, and keep their numbers as older ones drop out, so
+// `!N` always means the same command.
+
+/// Lines kept.
+pub(crate) const HISTORY_LEN: usize = 32;
+
+struct History {
+    lines: [[u8; LINE_BUF_CAP]; HISTORY_LEN],
+    lens: [usize; HISTORY_LEN],
+    // Lines ever added (the number of the newest).
+    total: usize,
+}
+
+static mut HISTORY: History = History {
+    lines: [[0; LINE_BUF_CAP]; HISTORY_LEN],
+    lens: [0; HISTORY_LEN],
+    total: 0,
+};
+
+/// Add a line, unless it repeats the newest one.
+pub(crate) fn push(line: &[u8]) {
+    let h = history();
+    if line.is_empty() || get(h.total) == Some(line) {
+        return;
+    }
+    let slot = h.total % HISTORY_LEN;
+    let len = line.len().min(LINE_BUF_CAP);
+    h.lines[slot][..len].copy_from_slice(&line[..len]);
+    h.lens[slot] = len;
+    h.total += 1;
+}
+
+/// Entry `n`, if it is still kept.
+pub(crate) fn get(n: usize) -> Option<&'static [u8]> {
+    let h = history();
+    if n == 0 || n > h.total || n + HISTORY_LEN <= h.total {
+        return None;
+    }
+    let slot = (n - 1) % HISTORY_LEN;
+    Some(&h.lines[slot][..h.lens[slot]])
+}
+
+/// Number of the newest entry (0 = none yet).
+pub(crate) fn newest() -> usize {
+    history().total
+}
+
+/// Number of the oldest entry still kept.
+pub(crate) fn oldest() -> usize {
+    history().total.saturating_sub(HISTORY_LEN - 1).max(1)
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+fn history() -> &'static mut History {
+    let h = &raw mut HISTORY;
+    unsafe { &mut *h }
+}
@@ -1,5 +1,6 @@
 use crate::{
     memory,
+    repl::symbols,
     uart::{print, print_hex_u8, print_hex_u32, println, putc},
 };
 
@@ -91,6 +92,7 @@ fn dump_memory(start: usize, end: usize, format: DumpFormat) {
             a += 1;
         }
 
+        symbols::print_annotation(addr);
         println("");
 
         // Move to the next line start.
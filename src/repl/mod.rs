@@ -1,5 +1,9 @@
 mod commands;
+mod expr;
+mod history;
 mod meminfo;
 mod runner;
+mod symbols;
+mod vars;
 
 pub(crate) use runner::run;
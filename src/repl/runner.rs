@@ -1,8 +1,8 @@
 use crate::{
-    INFO_BANNER, memory,
-    repl::commands::handle_command,
+    INFO_BANNER, memory, plic,
+    repl::{commands::handle_command, history, symbols},
     uart,
-    uart::{print, println},
+    uart::{print, println, putc},
 };
 use core::sync::atomic::{AtomicUsize, Ordering};
 
@@ -40,7 +40,30 @@ pub(crate) fn run() -> ! {
     let mut line_buf = [0u8; LINE_BUF_CAP];
     loop {
         prompt();
-        let n = read_line(&mut line_buf);
+        let mut n = read_line(&mut line_buf);
+
+        // `!N` re-runs history entry N, echoing it first.
+        if let Some(num) = line_buf[..n].strip_prefix(b"!") {
+            let entry = core::str::from_utf8(num)
+                .ok()
+                .and_then(|num| num.parse::<usize>().ok())
+                .and_then(history::get);
+            let Some(entry) = entry else {
+                println("error: no such history entry (see 'history')");
+                continue;
+            };
+            line_buf[..entry.len()].copy_from_slice(entry);
+            n = entry.len();
+            for &b in entry {
+                putc(b);
+            }
+            println("");
+        }
+
+        // Pasted symbols are data, not commands worth recalling.
+        if !symbols::pasting() {
+            history::push(&line_buf[..n]);
+        }
         handle_command(&line_buf[..n]);
     }
 }
@@ -49,61 +72,244 @@ pub(crate) fn run() -> ! {
 // REPL Line Editor
 // -----------------------------------------------------------------------------
 
-const LINE_BUF_CAP: usize = 128;
+/// Longest input line.
+pub(crate) const LINE_BUF_CAP: usize = 128;
 
 // Print the REPL prompt.
 fn prompt() {
     print("> ");
 }
 
-// Read a line of input into a buffer.
+// Wait for the next input byte, polling the PLIC meanwhile so device
+// interrupts are counted.
+fn next_byte() -> u8 {
+    loop {
+        if let Some(b) = uart::try_getc() {
+            return b;
+        }
+        plic::poll();
+    }
+}
+
+// An editing action, from a control byte or an ANSI escape sequence.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Key {
+    Char(u8),
+    Enter,
+    Cancel,
+    Backspace,
+    Delete,
+    Insert,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    Ignored,
+}
+
+// Read a line of input into a buffer, with cursor movement, insertion and
+// deletion anywhere in the line, and history recall:
+//
+//   Left/Right, Ctrl+B/F      move by one character
+//   Home/End, Ctrl+A/E        move to the start/end of the line
+//   Up/Down, Ctrl+P/N         recall older/newer history entries
+//   Backspace, Delete/Ctrl+D  delete before/at the cursor
+//   Ctrl+K/U                  delete to the end/start of the line
+//   Ctrl+W                    delete the word before the cursor
+//   Insert                    toggle between inserting and overwriting
 //
 // Returns the number of bytes read (excluding newline). Will return
 // 0 if the line was cancelled via Ctrl+C, or should otherwise be ignored.
 fn read_line(buf: &mut [u8]) -> usize {
-    let mut len = 0;
+    let mut line = Line {
+        buf,
+        len: 0,
+        cursor: 0,
+    };
+    let mut overwrite = false;
+
+    // The history entry shown; newest + 1 is the line being typed, which is
+    // kept in `draft` while older entries are shown.
+    let mut shown = history::newest() + 1;
+    let mut draft = [0u8; LINE_BUF_CAP];
+    let mut draft_len = 0;
 
     loop {
-        let b = uart::getc();
-        match b {
-            0x03 => {
-                // Matched: Ctrl+C (ETX).
-                //
+        match read_key() {
+            Key::Cancel => {
                 // Cancel the current input line and return 0 to treat it as an
                 // empty command.
                 print("^C\r\n");
                 return 0;
             }
-            b'\r' | b'\n' => {
-                // Matched: Enter (CR or LF).
-                //
-                // Finish editing, print a newline, and return the number of
-                // bytes currently in the buffer.
+            Key::Enter => {
                 print("\r\n");
-                return len;
+                return line.len;
+            }
+            Key::Char(b) => {
+                let end = match overwrite && line.cursor < line.len {
+                    true => line.cursor + 1,
+                    false => line.cursor,
+                };
+                line.splice(line.cursor, end, &[b]);
+            }
+            Key::Backspace if line.cursor > 0 => line.splice(line.cursor - 1, line.cursor, &[]),
+            Key::Delete if line.cursor < line.len => line.splice(line.cursor, line.cursor + 1, &[]),
+            Key::Insert => overwrite = !overwrite,
+            Key::Left => line.move_to(line.cursor.saturating_sub(1)),
+            Key::Right => line.move_to((line.cursor + 1).min(line.len)),
+            Key::Home => line.move_to(0),
+            Key::End => line.move_to(line.len),
+            Key::KillToEnd => line.splice(line.cursor, line.len, &[]),
+            Key::KillToStart => line.splice(0, line.cursor, &[]),
+            Key::KillWord => {
+                let mut start = line.cursor;
+                while start > 0 && line.buf[start - 1] == b' ' {
+                    start -= 1;
+                }
+                while start > 0 && line.buf[start - 1] != b' ' {
+                    start -= 1;
+                }
+                line.splice(start, line.cursor, &[]);
             }
-            0x08 | 0x7f => {
-                // Matched: Backspace (0x08) or Delete (0x7f).
-                //
-                // Delete one byte from the input (if any) and echo the erase.
-                if len > 0 {
-                    len -= 1;
-                    print("\x08 \x08");
+            Key::Up if shown > history::oldest() => {
+                if shown > history::newest() {
+                    draft[..line.len].copy_from_slice(&line.buf[..line.len]);
+                    draft_len = line.len;
                 }
+                shown -= 1;
+                line.splice(0, line.len, history::get(shown).unwrap_or(&[]));
             }
-            _ => {
-                // Matched: any other input byte.
-                //
-                // Accept printable ASCII into the line buffer (with echo);
-                // ignore control bytes and ignore extra bytes once the buffer is full.
-                if b >= 0x20 && b <= 0x7e {
-                    if len < buf.len() {
-                        buf[len] = b;
-                        len += 1;
-                        uart::putc(b);
+            Key::Down if shown <= history::newest() => {
+                shown += 1;
+                match history::get(shown) {
+                    Some(entry) => line.splice(0, line.len, entry),
+                    None => line.splice(0, line.len, &draft[..draft_len]),
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Read one key press, decoding control bytes and escape sequences.
+fn read_key() -> Key {
+    key_for(next_byte())
+}
+
+// The key a byte starts.
+fn key_for(b: u8) -> Key {
+    match b {
+        0x03 => Key::Cancel,
+        b'\r' | b'\n' => Key::Enter,
+        0x08 | 0x7f => Key::Backspace,
+        0x01 => Key::Home,
+        0x02 => Key::Left,
+        0x04 => Key::Delete,
+        0x05 => Key::End,
+        0x06 => Key::Right,
+        0x0b => Key::KillToEnd,
+        0x0e => Key::Down,
+        0x10 => Key::Up,
+        0x15 => Key::KillToStart,
+        0x17 => Key::KillWord,
+        0x1b => read_escape(),
+        b @ 0x20..=0x7e => Key::Char(b),
+        _ => Key::Ignored,
+    }
+}
+
+// Decode the rest of an escape sequence: `ESC [ <number> <final>` (CSI) or
+// `ESC O <final>` (SS3), as sent by VT100-style terminals.
+fn read_escape() -> Key {
+    match next_byte() {
+        b'[' => {
+            let mut param = 0u32;
+            loop {
+                match next_byte() {
+                    b @ b'0'..=b'9' => {
+                        param = param.saturating_mul(10).saturating_add((b - b'0') as u32)
                     }
+                    b';' => {}
+                    b @ 0x40..=0x7e => return escape_key(b, param),
+                    _ => return Key::Ignored,
                 }
             }
         }
+        b'O' => escape_key(next_byte(), 0),
+        // A lone ESC (or Alt+key): take what follows as typed.
+        b => key_for(b),
+    }
+}
+
+fn escape_key(last: u8, param: u32) -> Key {
+    match (last, param) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', 1 | 7) => Key::Home,
+        (b'F', _) | (b'~', 4 | 8) => Key::End,
+        (b'~', 2) => Key::Insert,
+        (b'~', 3) => Key::Delete,
+        _ => Key::Ignored,
+    }
+}
+
+// The line being edited. The terminal's cursor is kept at `cursor` columns
+// past the prompt.
+struct Line<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    cursor: usize,
+}
+
+impl Line<'_> {
+    // Move the cursor to `pos`: back with backspaces, forward by reprinting.
+    fn move_to(&mut self, pos: usize) {
+        while self.cursor > pos {
+            putc(0x08);
+            self.cursor -= 1;
+        }
+        while self.cursor < pos {
+            putc(self.buf[self.cursor]);
+            self.cursor += 1;
+        }
+    }
+
+    // Replace `start..end` with `with`, redraw the rest of the line, and
+    // leave the cursor after the inserted bytes. Ignored if the result would
+    // not fit.
+    fn splice(&mut self, start: usize, end: usize, with: &[u8]) {
+        let new_len = self.len - (end - start) + with.len();
+        if new_len > self.buf.len() {
+            return;
+        }
+
+        self.move_to(start);
+        self.buf.copy_within(end..self.len, start + with.len());
+        self.buf[start..start + with.len()].copy_from_slice(with);
+        let old_len = self.len;
+        self.len = new_len;
+
+        // Print the new tail, blank out whatever is left of the old one, and
+        // come back.
+        for &b in &self.buf[start..new_len] {
+            putc(b);
+        }
+        let blank = old_len.saturating_sub(new_len);
+        for _ in 0..blank {
+            putc(b' ');
+        }
+        for _ in 0..blank {
+            putc(0x08);
+        }
+        self.cursor = new_len;
+        self.move_to(start + with.len());
     }
 }
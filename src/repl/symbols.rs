@@ -0,0 +1,198 @@
+use crate::{
+    elf, hex,
+    uart::{print, putc},
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// -----------------------------------------------------------------------------
+// Symbol Table
+// -----------------------------------------------------------------------------
+
+// Names for addresses in the payload, from an ELF file's symbol table or
+// pasted `ADDR NAME` lines. Kept sorted by address so an address can be shown
+// as `name+off`; names live in one pool, reclaimed only by `clear`.
+
+/// Most symbols kept.
+pub(crate) const MAX_SYMBOLS: usize = 4096;
+
+// Bytes of symbol names kept.
+const NAME_POOL_SIZE: usize = 256 * 1024;
+
+#[derive(Copy, Clone)]
+struct Sym {
+    addr: usize,
+    // 0 = unknown: the symbol runs up to the next one, if any.
+    size: usize,
+    name_off: usize,
+    name_len: usize,
+}
+
+struct Table {
+    syms: [Sym; MAX_SYMBOLS],
+    count: usize,
+    names: [u8; NAME_POOL_SIZE],
+    names_used: usize,
+}
+
+static mut TABLE: Table = Table {
+    syms: [Sym {
+        addr: 0,
+        size: 0,
+        name_off: 0,
+        name_len: 0,
+    }; MAX_SYMBOLS],
+    count: 0,
+    names: [0; NAME_POOL_SIZE],
+    names_used: 0,
+};
+
+// Whether input lines are being taken as `ADDR NAME` pairs.
+static PASTING: AtomicBool = AtomicBool::new(false);
+
+/// Forget every symbol.
+pub(crate) fn clear() {
+    let t = table();
+    t.count = 0;
+    t.names_used = 0;
+}
+
+/// Number of symbols held.
+pub(crate) fn count() -> usize {
+    table().count
+}
+
+/// Add a symbol, keeping the table sorted. Adding one already held (same
+/// name and address) does nothing.
+pub(crate) fn add(name: &str, addr: usize, size: usize) -> Result<(), &'static str> {
+    let t = table();
+    if t.syms[..t.count]
+        .iter()
+        .any(|s| s.addr == addr && t.name(s) == name)
+    {
+        return Ok(());
+    }
+    if t.count == MAX_SYMBOLS || t.names_used + name.len() > NAME_POOL_SIZE {
+        return Err("symbol table full");
+    }
+
+    let name_off = t.names_used;
+    t.names[name_off..name_off + name.len()].copy_from_slice(name.as_bytes());
+    t.names_used += name.len();
+
+    let i = t.syms[..t.count].partition_point(|s| s.addr <= addr);
+    t.syms.copy_within(i..t.count, i + 1);
+    t.syms[i] = Sym {
+        addr,
+        size,
+        name_off,
+        name_len: name.len(),
+    };
+    t.count += 1;
+    Ok(())
+}
+
+/// Replace the table with the symbols of the ELF file at `image`, returning
+/// how many were loaded. The table is left alone if the file cannot be read
+/// or its symbols do not fit.
+pub(crate) fn load_elf(image: &[u8]) -> Result<usize, &'static str> {
+    let mut names = 0;
+    let count = elf::for_each_symbol(image, |sym| {
+        names += sym.name.len();
+        Ok(())
+    })?;
+    if count > MAX_SYMBOLS || names > NAME_POOL_SIZE {
+        return Err("symbol table full");
+    }
+
+    clear();
+    elf::for_each_symbol(image, |sym| {
+        add(sym.name, sym.value as usize, sym.size as usize)
+    })?;
+    Ok(self::count())
+}
+
+/// The address of the first symbol called `name`.
+pub(crate) fn find(name: &str) -> Option<usize> {
+    let t = table();
+    t.syms[..t.count]
+        .iter()
+        .find(|s| t.name(s) == name)
+        .map(|s| s.addr)
+}
+
+/// The symbol `addr` falls in and the offset into it: the nearest one at or
+/// below `addr`, preferring one with a size where several share an address.
+/// A sized symbol covers its size; one of unknown size covers up to the next
+/// symbol, so the last of those covers nothing past its own address.
+pub(crate) fn lookup(addr: usize) -> Option<(&'static str, usize)> {
+    let t = table();
+    let i = t.syms[..t.count].partition_point(|s| s.addr <= addr);
+    let base = t.syms[..i].last()?.addr;
+    let first = t.syms[..i].partition_point(|s| s.addr < base);
+    let same = &t.syms[first..i];
+    let sym = same.iter().find(|s| s.size != 0).unwrap_or(&same[0]);
+    let off = addr - base;
+    let covered = match sym.size {
+        0 => off == 0 || i < t.count,
+        size => off < size,
+    };
+    match covered {
+        true => Some((t.name(sym), off)),
+        false => None,
+    }
+}
+
+/// Visit every symbol in address order.
+pub(crate) fn for_each(mut f: impl FnMut(&str, usize, usize)) {
+    let t = table();
+    for s in &t.syms[..t.count] {
+        f(t.name(s), s.addr, s.size);
+    }
+}
+
+/// Whether input lines are being taken as pasted symbols.
+pub(crate) fn pasting() -> bool {
+    PASTING.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_pasting(on: bool) {
+    PASTING.store(on, Ordering::Relaxed);
+}
+
+/// Print ` <name+off>` for `addr`, or nothing if no symbol covers it.
+pub(crate) fn print_annotation(addr: usize) {
+    let Some((name, off)) = lookup(addr) else {
+        return;
+    };
+    print(" <");
+    print(name);
+    if off != 0 {
+        print("+");
+        print_hex_trimmed(off);
+    }
+    print(">");
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+impl Table {
+    fn name(&self, s: &Sym) -> &str {
+        // Names were copied in from `&str`s whole.
+        core::str::from_utf8(&self.names[s.name_off..s.name_off + s.name_len]).unwrap_or("?")
+    }
+}
+
+fn table() -> &'static mut Table {
+    let t = &raw mut TABLE;
+    unsafe { &mut *t }
+}
+
+// Print an offset in hex without leading zeros.
+fn print_hex_trimmed(v: usize) {
+    let digits = (v.max(1).ilog2() / 4 + 1) as usize;
+    for i in (0..digits).rev() {
+        putc(hex::hex_digit(((v >> (i * 4)) & 0xf) as u8));
+    }
+}
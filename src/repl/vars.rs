@@ -0,0 +1,162 @@
+use crate::{STACK_BOTTOM, STACK_TOP, memory, repl::runner::FINDME, trap};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// -----------------------------------------------------------------------------
+// Monitor Variables
+// -----------------------------------------------------------------------------
+
+// Named values usable in any expression. Built-ins are read-only and come from
+// riscmon's layout and the last file loaded; the rest are set by the user and
+// kept until reboot.
+
+/// Most user variables.
+pub(crate) const MAX_VARS: usize = 32;
+
+/// Longest variable name.
+pub(crate) const MAX_NAME: usize = 24;
+
+// Built-in names, in the order `vars` lists them.
+const BUILTINS: [&str; 7] = [
+    "ram_base",
+    "ram_end",
+    "stack_bottom",
+    "stack_top",
+    "findme",
+    "entry",
+    "load_size",
+];
+
+#[derive(Copy, Clone)]
+struct Var {
+    name: [u8; MAX_NAME],
+    len: usize,
+    value: u64,
+}
+
+impl Var {
+    fn name(&self) -> &str {
+        // Names are validated ASCII.
+        core::str::from_utf8(&self.name[..self.len]).unwrap_or("")
+    }
+}
+
+static mut VARS: [Option<Var>; MAX_VARS] = [None; MAX_VARS];
+
+// Where the last tftp, fwcfg or sh load put its file.
+static LOADED: AtomicBool = AtomicBool::new(false);
+static LOAD_ADDR: AtomicUsize = AtomicUsize::new(0);
+static LOAD_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+/// The value of a built-in or user variable.
+pub(crate) fn get(name: &str) -> Option<u64> {
+    if let Some(v) = builtin(name) {
+        return v;
+    }
+    vars()
+        .iter()
+        .flatten()
+        .find(|v| v.name() == name)
+        .map(|v| v.value)
+}
+
+/// Set a user variable, creating it if needed.
+pub(crate) fn set(name: &str, value: u64) -> Result<(), &'static str> {
+    check_name(name)?;
+    let vars = vars();
+    if let Some(var) = vars.iter_mut().flatten().find(|v| v.name() == name) {
+        var.value = value;
+        return Ok(());
+    }
+    let slot = vars
+        .iter_mut()
+        .find(|v| v.is_none())
+        .ok_or("too many variables")?;
+    let mut var = Var {
+        name: [0; MAX_NAME],
+        len: name.len(),
+        value,
+    };
+    var.name[..name.len()].copy_from_slice(name.as_bytes());
+    *slot = Some(var);
+    Ok(())
+}
+
+/// Remove a user variable.
+pub(crate) fn unset(name: &str) -> Result<(), &'static str> {
+    if BUILTINS.contains(&name) {
+        return Err("built-in variables cannot be unset");
+    }
+    let slot = vars()
+        .iter_mut()
+        .find(|v| v.is_some_and(|v| v.name() == name))
+        .ok_or("no such variable")?;
+    *slot = None;
+    Ok(())
+}
+
+/// Visit every defined variable, built-ins first, with whether it is built
+/// in.
+pub(crate) fn for_each(mut f: impl FnMut(&str, u64, bool)) {
+    for name in BUILTINS {
+        if let Some(Some(value)) = builtin(name) {
+            f(name, value, true);
+        }
+    }
+    for var in vars().iter().flatten() {
+        f(var.name(), var.value, false);
+    }
+}
+
+/// Record where a loader put a file, for `entry` and `load_size`.
+pub(crate) fn set_loaded(addr: usize, size: usize) {
+    LOAD_ADDR.store(addr, Ordering::Relaxed);
+    LOAD_SIZE.store(size, Ordering::Relaxed);
+    LOADED.store(true, Ordering::Relaxed);
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+fn vars() -> &'static mut [Option<Var>; MAX_VARS] {
+    let vars = &raw mut VARS;
+    unsafe { &mut *vars }
+}
+
+// `None` if `name` is not a built-in; `Some(None)` if it is one without a
+// value yet (nothing loaded).
+fn builtin(name: &str) -> Option<Option<u64>> {
+    let loaded = LOADED.load(Ordering::Relaxed);
+    let value = match name {
+        "ram_base" => memory::RAM_BASE,
+        "ram_end" => memory::RAM_END_INCLUSIVE,
+        "stack_bottom" => STACK_BOTTOM,
+        "stack_top" => STACK_TOP,
+        "findme" => FINDME.as_ptr() as usize,
+        "entry" if loaded => LOAD_ADDR.load(Ordering::Relaxed),
+        "load_size" if loaded => LOAD_SIZE.load(Ordering::Relaxed),
+        "entry" | "load_size" => return Some(None),
+        _ => return None,
+    };
+    Some(Some(value as u64))
+}
+
+// A name must look like an identifier and not be mistaken for a number,
+// register or built-in in an expression.
+fn check_name(name: &str) -> Result<(), &'static str> {
+    let mut bytes = name.bytes();
+    let valid = bytes
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+        && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    if !valid || name.len() > MAX_NAME {
+        return Err("invalid name (letters, digits and _, at most 24, not starting with a digit)");
+    }
+    if name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err("name would read as a hex number");
+    }
+    if BUILTINS.contains(&name) || name == "pc" || name == "fp" || trap::REG_NAMES.contains(&name) {
+        return Err("name is a register or built-in");
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use crate::fdt;
+
+// -----------------------------------------------------------------------------
+// Goldfish Real-Time Clock
+// -----------------------------------------------------------------------------
+
+// A 64-bit count of nanoseconds since the Unix epoch, which QEMU starts at the
+// host's current time (UTC). Reading the low half latches the high half;
+// writing the low half sets the clock from it and the high half written
+// before.
+
+// QEMU virt's RTC, when the device tree does not say.
+const DEFAULT_BASE: usize = 0x0010_1000;
+
+// Register offsets.
+const REG_TIME_LOW: usize = 0x00;
+const REG_TIME_HIGH: usize = 0x04;
+
+/// Nanoseconds per second.
+pub(crate) const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+// Latest year `DateTime` handles, well within a u64 of nanoseconds (2554).
+const MAX_YEAR: u32 = 2500;
+
+/// The RTC's base address: from the device tree, or QEMU virt's address if
+/// there is no device tree.
+pub(crate) fn find() -> Option<usize> {
+    match fdt::get() {
+        Some(fdt) => Some(fdt.reg(fdt.find_compatible("google,goldfish-rtc")?, 0)?.0 as usize),
+        None => Some(DEFAULT_BASE),
+    }
+}
+
+/// Nanoseconds since the Unix epoch, or `None` without an RTC.
+pub(crate) fn now_ns() -> Option<u64> {
+    let base = find()?;
+    let (low, high) = unsafe {
+        let low = ((base + REG_TIME_LOW) as *const u32).read_volatile();
+        let high = ((base + REG_TIME_HIGH) as *const u32).read_volatile();
+        (low, high)
+    };
+    Some(((high as u64) << 32) | low as u64)
+}
+
+/// Set the clock to `ns` nanoseconds since the Unix epoch.
+pub(crate) fn set_ns(ns: u64) -> Result<(), &'static str> {
+    let base = find().ok_or("no Goldfish RTC")?;
+    unsafe {
+        ((base + REG_TIME_HIGH) as *mut u32).write_volatile((ns >> 32) as u32);
+        ((base + REG_TIME_LOW) as *mut u32).write_volatile(ns as u32);
+    }
+    Ok(())
+}
+
+/// A UTC date and time (proleptic Gregorian calendar, 1970 onwards).
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) struct DateTime {
+    pub(crate) year: u32,
+    /// 1..=12.
+    pub(crate) month: u8,
+    /// 1..=31.
+    pub(crate) day: u8,
+    pub(crate) hour: u8,
+    pub(crate) minute: u8,
+    pub(crate) second: u8,
+    pub(crate) nanos: u32,
+}
+
+impl DateTime {
+    /// The date and time `ns` nanoseconds after the Unix epoch.
+    pub(crate) fn from_unix_ns(ns: u64) -> DateTime {
+        let secs = ns / NANOS_PER_SEC;
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let rem = secs % SECS_PER_DAY;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u8,
+            minute: (rem / 60 % 60) as u8,
+            second: (rem % 60) as u8,
+            nanos: (ns % NANOS_PER_SEC) as u32,
+        }
+    }
+
+    /// Nanoseconds since the Unix epoch, or `None` if any field is out of
+    /// range.
+    pub(crate) fn to_unix_ns(self) -> Option<u64> {
+        let valid = (1970..=MAX_YEAR).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+            && (self.nanos as u64) < NANOS_PER_SEC;
+        if !valid {
+            return None;
+        }
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * SECS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+        Some(secs * NANOS_PER_SEC + self.nanos as u64)
+    }
+
+    /// Parse `YYYY-MM-DDTHH:MM:SS`, optionally followed by `Z`.
+    pub(crate) fn parse(s: &str) -> Option<DateTime> {
+        let s = s.strip_suffix('Z').unwrap_or(s);
+        let (date, time) = s.split_once('T')?;
+        let mut date = date.split('-');
+        let mut time = time.split(':');
+        let field = |it: &mut core::str::Split<'_, char>, digits: usize| {
+            it.next()
+                .filter(|f| f.len() == digits && f.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|f| f.parse::<u32>().ok())
+        };
+
+        let dt = DateTime {
+            year: field(&mut date, 4)?,
+            month: field(&mut date, 2)? as u8,
+            day: field(&mut date, 2)? as u8,
+            hour: field(&mut time, 2)? as u8,
+            minute: field(&mut time, 2)? as u8,
+            second: field(&mut time, 2)? as u8,
+            nanos: 0,
+        };
+        if date.next().is_some() || time.next().is_some() {
+            return None;
+        }
+        dt.to_unix_ns().map(|_| dt)
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The calendar arithmetic follows Howard Hinnant's `days_from_civil` and
+// `civil_from_days`, using years that start on 1 March so the leap day falls
+// at the end. Only dates from 1970 on are needed, so everything is unsigned.
+
+// Days from 1970-01-01 to the given date.
+fn days_from_civil(year: u32, month: u8, day: u8) -> u64 {
+    let (month, day) = (month as u64, day as u64);
+    let year = year as u64 - (month <= 2) as u64;
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// The date `days` days after 1970-01-01.
+fn civil_from_days(days: u64) -> (u32, u8, u8) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (yoe + era * 400) as u32 + (month <= 2) as u32;
+    (year, month, day)
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
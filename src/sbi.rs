@@ -0,0 +1,136 @@
+// -----------------------------------------------------------------------------
+// Supervisor Binary Interface (SBI)
+// -----------------------------------------------------------------------------
+
+// Extension and function ids, shared by the client calls below and by the
+// firmware side riscmon implements for the payloads it boots (`firmware`).
+
+// Base extension: used to probe for the others.
+pub(crate) const EID_BASE: usize = 0x10;
+pub(crate) const FID_GET_SPEC_VERSION: usize = 0;
+pub(crate) const FID_GET_IMPL_ID: usize = 1;
+pub(crate) const FID_GET_IMPL_VERSION: usize = 2;
+pub(crate) const FID_PROBE_EXTENSION: usize = 3;
+pub(crate) const FID_GET_MVENDORID: usize = 4;
+pub(crate) const FID_GET_MARCHID: usize = 5;
+pub(crate) const FID_GET_MIMPID: usize = 6;
+
+// Legacy extensions (SBI v0.1). These return their result in a0 only.
+pub(crate) const EID_LEGACY_SET_TIMER: usize = 0x00;
+pub(crate) const EID_LEGACY_PUTCHAR: usize = 0x01;
+pub(crate) const EID_LEGACY_GETCHAR: usize = 0x02;
+pub(crate) const EID_LEGACY_CLEAR_IPI: usize = 0x03;
+pub(crate) const EID_LEGACY_SEND_IPI: usize = 0x04;
+pub(crate) const EID_LEGACY_REMOTE_FENCE_I: usize = 0x05;
+pub(crate) const EID_LEGACY_REMOTE_SFENCE_VMA: usize = 0x06;
+pub(crate) const EID_LEGACY_REMOTE_SFENCE_VMA_ASID: usize = 0x07;
+pub(crate) const EID_LEGACY_SHUTDOWN: usize = 0x08;
+
+// Debug console extension ("DBCN").
+pub(crate) const EID_DBCN: usize = 0x4442_434e;
+pub(crate) const FID_DBCN_WRITE: usize = 0;
+pub(crate) const FID_DBCN_READ: usize = 1;
+pub(crate) const FID_DBCN_WRITE_BYTE: usize = 2;
+
+// Timer extension ("TIME").
+pub(crate) const EID_TIME: usize = 0x5449_4d45;
+pub(crate) const FID_SET_TIMER: usize = 0;
+
+// IPI extension ("sPI").
+pub(crate) const EID_IPI: usize = 0x0073_5049;
+pub(crate) const FID_SEND_IPI: usize = 0;
+
+// Remote fence extension ("RFNC").
+pub(crate) const EID_RFENCE: usize = 0x5246_4e43;
+
+// Hart state management extension ("HSM").
+const EID_HSM: usize = 0x0048_534d;
+const FID_HART_GET_STATUS: usize = 2;
+
+// System reset extension ("SRST").
+pub(crate) const EID_SRST: usize = 0x5352_5354;
+pub(crate) const FID_SYSTEM_RESET: usize = 0;
+
+/// SRST reset types.
+pub(crate) const RESET_SHUTDOWN: usize = 0;
+pub(crate) const RESET_COLD_REBOOT: usize = 1;
+pub(crate) const RESET_WARM_REBOOT: usize = 2;
+
+/// SRST reset reasons.
+pub(crate) const REASON_NONE: usize = 0;
+pub(crate) const REASON_FAILURE: usize = 1;
+
+/// Standard SBI error codes.
+pub(crate) const SUCCESS: isize = 0;
+pub(crate) const ERR_NOT_SUPPORTED: isize = -2;
+pub(crate) const ERR_INVALID_PARAM: isize = -3;
+
+/// Result of an SBI call: an error code (0 on success) and a value.
+#[derive(Copy, Clone)]
+pub(crate) struct SbiRet {
+    pub(crate) error: isize,
+    pub(crate) value: usize,
+}
+
+/// Issue an SBI call to the firmware running below us.
+pub(crate) fn call(eid: usize, fid: usize, args: [usize; 3]) -> SbiRet {
+    let error: isize;
+    let value: usize;
+    unsafe {
+        core::arch::asm!(
+            "ecall",
+            inlateout("a0") args[0] => error,
+            inlateout("a1") args[1] => value,
+            in("a2") args[2],
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    SbiRet { error, value }
+}
+
+/// Whether the firmware implements extension `eid`.
+pub(crate) fn probe_extension(eid: usize) -> bool {
+    let ret = call(EID_BASE, FID_PROBE_EXTENSION, [eid, 0, 0]);
+    ret.error == 0 && ret.value != 0
+}
+
+/// Program the next timer event (clears any pending timer interrupt).
+pub(crate) fn set_timer(deadline: u64) {
+    call(EID_TIME, FID_SET_TIMER, [deadline as usize, 0, 0]);
+}
+
+/// Query a hart's HSM state, returning its name.
+pub(crate) fn hart_status(hartid: usize) -> Option<&'static str> {
+    let ret = call(EID_HSM, FID_HART_GET_STATUS, [hartid, 0, 0]);
+    if ret.error != 0 {
+        return None;
+    }
+
+    Some(match ret.value {
+        0 => "started",
+        1 => "stopped",
+        2 => "start pending",
+        3 => "stop pending",
+        4 => "suspended",
+        5 => "suspend pending",
+        6 => "resume pending",
+        _ => "unknown",
+    })
+}
+
+/// Ask the firmware to shut down or reboot. Only returns if it refused.
+///
+/// `failure` reports a system failure as the reset reason, which QEMU turns
+/// into a non-zero exit status.
+pub(crate) fn system_reset(reset_type: usize, failure: bool) {
+    let reason = match failure {
+        true => REASON_FAILURE,
+        false => REASON_NONE,
+    };
+    call(EID_SRST, FID_SYSTEM_RESET, [reset_type, reason, 0]);
+
+    if reset_type == RESET_SHUTDOWN {
+        call(EID_LEGACY_SHUTDOWN, 0, [0; 3]);
+    }
+}
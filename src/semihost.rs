@@ -0,0 +1,126 @@
+use crate::trap;
+use core::arch::global_asm;
+
+// -----------------------------------------------------------------------------
+// RISC-V Semihosting
+// -----------------------------------------------------------------------------
+
+// A semihosting call is an `ebreak` between two marker instructions; QEMU
+// started with `-semihosting-config enable=on` performs the operation in a0
+// (arguments in a block at a1) on the host and returns the result in a0.
+// Without it the `ebreak` is an ordinary breakpoint, which `trap::probe`
+// catches.
+
+// Operations (from the Arm semihosting specification RISC-V adopts).
+const SYS_OPEN: usize = 0x01;
+const SYS_CLOSE: usize = 0x02;
+const SYS_WRITE: usize = 0x05;
+const SYS_READ: usize = 0x06;
+const SYS_FLEN: usize = 0x0c;
+const SYS_EXIT: usize = 0x18;
+
+// `SYS_OPEN` modes (indices into the fopen mode strings).
+const MODE_READ_BINARY: usize = 1;
+const MODE_WRITE_BINARY: usize = 5;
+
+// `SYS_EXIT` reason for a normal application exit.
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x20026;
+
+// Longest host path accepted.
+const MAX_PATH: usize = 255;
+
+// Returned by calls that fail.
+const FAILED: usize = usize::MAX;
+
+const NOT_ENABLED: &str = "semihosting not enabled (-semihosting-config enable=on)";
+
+unsafe extern "C" {
+    fn semihost_call(op: usize, args: usize) -> usize;
+}
+
+// The call sequence. The three instructions must be uncompressed and on one
+// page, hence the alignment.
+global_asm!(
+    ".pushsection .text.semihost, \"ax\"",
+    ".balign 16",
+    ".global semihost_call",
+    "semihost_call:",
+    ".option push",
+    ".option norvc",
+    "slli zero, zero, 0x1f",
+    "ebreak",
+    "srai zero, zero, 7",
+    ".option pop",
+    "ret",
+    ".popsection",
+);
+
+/// Copy the host file at `path` into memory at `addr`, writing nothing beyond
+/// `limit` (inclusive). Returns the file size.
+pub(crate) fn load(path: &str, addr: usize, limit: usize) -> Result<usize, &'static str> {
+    let handle = open(path, MODE_READ_BINARY)?;
+    let result = read(handle, addr, limit);
+    call(SYS_CLOSE, &[handle])?;
+    result
+}
+
+/// Write `len` bytes of memory at `addr` to the host file at `path`, replacing
+/// it.
+pub(crate) fn save(path: &str, addr: usize, len: usize) -> Result<(), &'static str> {
+    let handle = open(path, MODE_WRITE_BINARY)?;
+    // Returns the number of bytes *not* written.
+    let result = match call(SYS_WRITE, &[handle, addr, len])? {
+        0 => Ok(()),
+        _ => Err("short write"),
+    };
+    call(SYS_CLOSE, &[handle])?;
+    result
+}
+
+/// End the QEMU run with exit status `code`. Only returns if semihosting is
+/// not enabled.
+pub(crate) fn exit(code: usize) -> Result<(), &'static str> {
+    call(SYS_EXIT, &[ADP_STOPPED_APPLICATION_EXIT, code])?;
+    Err("the host ignored the exit request")
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Open a host file, returning its handle.
+fn open(path: &str, mode: usize) -> Result<usize, &'static str> {
+    if path.is_empty() || path.len() > MAX_PATH || path.contains('\0') {
+        return Err("invalid path");
+    }
+    let mut name = [0u8; MAX_PATH + 1];
+    name[..path.len()].copy_from_slice(path.as_bytes());
+
+    match call(SYS_OPEN, &[name.as_ptr() as usize, mode, path.len()])? {
+        FAILED => Err("cannot open the host file"),
+        handle => Ok(handle),
+    }
+}
+
+// Read all of an open file into memory at `addr`, up to `limit`.
+fn read(handle: usize, addr: usize, limit: usize) -> Result<usize, &'static str> {
+    let len = call(SYS_FLEN, &[handle])?;
+    if len == FAILED {
+        return Err("cannot get the file size");
+    }
+    match addr.checked_add(len.saturating_sub(1)) {
+        Some(end) if end <= limit => {}
+        _ => return Err("file too large for the space at ADDR"),
+    }
+
+    // Returns the number of bytes *not* read.
+    match call(SYS_READ, &[handle, addr, len])? {
+        0 => Ok(len),
+        _ => Err("short read"),
+    }
+}
+
+// Make a semihosting call with the given argument block.
+fn call(op: usize, args: &[usize]) -> Result<usize, &'static str> {
+    trap::probe(|| unsafe { semihost_call(op, args.as_ptr() as usize) }).ok_or(NOT_ENABLED)
+}
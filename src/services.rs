@@ -0,0 +1,167 @@
+use crate::{
+    csr, paging,
+    trap::{self, TrapFrame},
+    uart,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use riscmon_abi as abi;
+
+// -----------------------------------------------------------------------------
+// Service Calls
+// -----------------------------------------------------------------------------
+
+// Programs launched with `jump` or `ujump` reach riscmon through `ecall` (see
+// the `riscmon-abi` crate for the call numbers), so they can use the console
+// without knowing which UART the board has.
+
+// The `mcycle` CSR: the hart's cycle counter.
+const CSR_MCYCLE: usize = 0xb00;
+
+// Memory the running program may hand to a call (inclusive bounds).
+static WINDOW_START: AtomicUsize = AtomicUsize::new(0);
+static WINDOW_END: AtomicUsize = AtomicUsize::new(0);
+
+/// What the trap handler should do after a service call.
+pub(crate) enum Service {
+    /// Return to the program.
+    Resume,
+    /// The program exited; its code is in a0.
+    Exit,
+    /// Stop the program so it can be inspected and resumed with `continue`.
+    Stop,
+    /// The user pressed Ctrl+C while the program waited for input; `continue`
+    /// repeats the call.
+    Break,
+    /// The watchdog expired while the program waited for input.
+    Timeout,
+}
+
+/// Limit the buffers the next program may pass in to `start..=end`.
+pub(crate) fn set_window(start: usize, end: usize) {
+    WINDOW_START.store(start, Ordering::Relaxed);
+    WINDOW_END.store(end, Ordering::Relaxed);
+}
+
+/// Service an ecall from a launched program, writing the result into its
+/// registers and stepping past the `ecall`.
+///
+/// Unknown call numbers stop the program like `MONITOR` does, leaving a0 as is.
+pub(crate) fn handle_ecall(frame: &mut TrapFrame) -> Service {
+    let num = frame.regs[17];
+    let (a0, a1) = (frame.regs[10], frame.regs[11]);
+    frame.pc += 4;
+
+    let ret = match num {
+        abi::PUTC => {
+            uart::putc(a0 as u8);
+            0
+        }
+        abi::GETC => match getc() {
+            Ok(b) => b as usize,
+            Err(stop) => {
+                frame.pc -= 4;
+                return stop;
+            }
+        },
+        abi::PUTS => puts(a0, a1, translated(frame)),
+        abi::PUT_HEX => {
+            uart::print_hex_u64(a0 as u64);
+            0
+        }
+        abi::CYCLES => csr::read!(CSR_MCYCLE),
+        abi::EXIT => return Service::Exit,
+        // abi::MONITOR, and anything unknown.
+        _ => return Service::Stop,
+    };
+
+    frame.regs[10] = ret;
+    Service::Resume
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Wait for a console byte. The timer tick cannot interrupt the trap handler,
+// so check for Ctrl+C and the watchdog here instead.
+fn getc() -> Result<u8, Service> {
+    loop {
+        if uart::poll_break() {
+            return Err(Service::Break);
+        }
+        if let Some(b) = uart::try_getc() {
+            return Ok(b);
+        }
+        if trap::watchdog_expired() {
+            return Err(Service::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+}
+
+// Print a buffer, if it lies within the program's window. A `translated`
+// address is virtual, and is walked through the page tables a page at a time.
+fn puts(addr: usize, len: usize, translated: bool) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    if addr.checked_add(len - 1).is_none() {
+        return abi::ERROR;
+    }
+
+    // Check the whole buffer before printing any of it.
+    if for_each_piece(addr, len, translated, |_| {}).is_err() {
+        return abi::ERROR;
+    }
+    let _ = for_each_piece(addr, len, translated, |bytes| {
+        for b in bytes {
+            uart::putc(*b);
+        }
+    });
+    0
+}
+
+// Whether the program that made the call sees memory through page tables: a
+// user-mode program started with `pt satp on`.
+fn translated(frame: &TrapFrame) -> bool {
+    frame.status & csr::STATUS_PP == csr::STATUS_PP_U && paging::active_satp() != 0
+}
+
+// Call `f` with the buffer's bytes, one physically contiguous piece at a time,
+// or fail if any of them is not readable by the program.
+fn for_each_piece(
+    addr: usize,
+    len: usize,
+    translated: bool,
+    mut f: impl FnMut(&[u8]),
+) -> Result<(), ()> {
+    let mut done = 0;
+    while done < len {
+        let va = addr + done;
+        let (pa, n) = match translated {
+            false => (va, len - done),
+            true => {
+                let n = (paging::PAGE_SIZE - va % paging::PAGE_SIZE).min(len - done);
+                (user_pa(va).ok_or(())?, n)
+            }
+        };
+        if pa < WINDOW_START.load(Ordering::Relaxed)
+            || pa + (n - 1) > WINDOW_END.load(Ordering::Relaxed)
+        {
+            return Err(());
+        }
+        f(unsafe { core::slice::from_raw_parts(pa as *const u8, n) });
+        done += n;
+    }
+    Ok(())
+}
+
+// The physical address a user-mode load from `va` reaches, if the tables let
+// user mode read it.
+fn user_pa(va: usize) -> Option<usize> {
+    let walk = paging::walk(va)?;
+    let pa = walk.pa?;
+    let leaf = walk.steps[walk.len - 1].pte;
+    let readable = paging::PTE_U | paging::PTE_R;
+    (leaf & readable == readable).then_some(pa)
+}
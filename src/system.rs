@@ -1,17 +1,148 @@
+#[cfg(not(feature = "supervisor"))]
+use crate::fdt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
 // -----------------------------------------------------------------------------
 // Memory-Mapped Input/Output (MMIO)
 // -----------------------------------------------------------------------------
 
-// QEMU "virt" exposes a "finisher" MMIO device used to end emulation.
-// Writing 0x5555 makes QEMU exit ("power off").
+// QEMU "virt" exposes a "finisher" MMIO device (SiFive test device) used to end
+// emulation: 0x5555 powers off, 0x7777 resets, and `(code << 16) | 0x3333`
+// exits QEMU with a failure status.
+//
+// Under SBI firmware the device belongs to the firmware, so the SBI system
+// reset extension is used instead.
+#[cfg(not(feature = "supervisor"))]
 const QEMU_FINISHER: usize = 0x0010_0000;
+#[cfg(not(feature = "supervisor"))]
+const FINISHER_PASS: u32 = 0x5555;
+#[cfg(not(feature = "supervisor"))]
+const FINISHER_RESET: u32 = 0x7777;
+#[cfg(not(feature = "supervisor"))]
+const FINISHER_FAIL: u32 = 0x3333;
 
 /// Power off the system.
+///
+/// In machine mode a `syscon-poweroff` node in the device tree takes precedence
+/// over the QEMU finisher.
 pub(crate) fn poweroff() -> ! {
-    unsafe {
-        (QEMU_FINISHER as *mut u32).write_volatile(0x5555);
+    #[cfg(not(feature = "supervisor"))]
+    if !syscon_write("syscon-poweroff") {
+        finisher_write(FINISHER_PASS);
+    }
+    #[cfg(feature = "supervisor")]
+    crate::sbi::system_reset(crate::sbi::RESET_SHUTDOWN, false);
+
+    halt()
+}
+
+/// Reset the system.
+///
+/// In machine mode a `syscon-reboot` node in the device tree takes precedence
+/// over the QEMU finisher.
+pub(crate) fn reboot() -> ! {
+    #[cfg(not(feature = "supervisor"))]
+    if !syscon_write("syscon-reboot") {
+        finisher_write(FINISHER_RESET);
+    }
+    #[cfg(feature = "supervisor")]
+    crate::sbi::system_reset(crate::sbi::RESET_COLD_REBOOT, false);
+
+    halt()
+}
+
+/// End emulation with exit status `code`.
+///
+/// QEMU exits with status `code` (0 = pass; the host keeps the low 8 bits).
+/// Under SBI firmware only pass/fail can be reported (a shutdown with a
+/// failure reason).
+pub(crate) fn exit(code: u16) -> ! {
+    #[cfg(not(feature = "supervisor"))]
+    match code {
+        0 => finisher_write(FINISHER_PASS),
+        _ => finisher_write(((code as u32) << 16) | FINISHER_FAIL),
     }
+    #[cfg(feature = "supervisor")]
+    crate::sbi::system_reset(crate::sbi::RESET_SHUTDOWN, code != 0);
+
+    halt()
+}
+
+// Spin forever once the hardware has been asked to stop (or failed to).
+fn halt() -> ! {
     loop {
         core::hint::spin_loop();
     }
 }
+
+// Write to the QEMU finisher (at the `sifive,test0` node if the device tree has
+// one).
+#[cfg(not(feature = "supervisor"))]
+fn finisher_write(value: u32) {
+    let base = fdt::get()
+        .and_then(|fdt| fdt.reg(fdt.find_compatible("sifive,test0")?, 0))
+        .map_or(QEMU_FINISHER, |(addr, _)| addr as usize);
+    unsafe { (base as *mut u32).write_volatile(value) };
+}
+
+// Perform a `syscon-poweroff`/`syscon-reboot` action described by the device
+// tree: update the bits under `mask` at `offset` into the `regmap` register
+// block to `value`. Returns false if the device tree has no usable node.
+#[cfg(not(feature = "supervisor"))]
+fn syscon_write(compat: &str) -> bool {
+    let Some(fdt) = fdt::get() else {
+        return false;
+    };
+    let action = || {
+        let node = fdt.find_compatible(compat)?;
+        let regmap = fdt.find_phandle(fdt.property_u32(node, "regmap")?)?;
+        let (base, _) = fdt.reg(regmap, 0)?;
+        let offset = fdt.property_u32(node, "offset")?;
+        // As in Linux: the older binding has only `mask`, which is then the
+        // value written to the whole register.
+        let (value, mask) = match (
+            fdt.property_u32(node, "value"),
+            fdt.property_u32(node, "mask"),
+        ) {
+            (Some(value), mask) => (value, mask.unwrap_or(u32::MAX)),
+            (None, Some(mask)) => (mask, u32::MAX),
+            (None, None) => return None,
+        };
+        Some(((base as usize) + offset as usize, value, mask))
+    };
+    let Some((addr, value, mask)) = action() else {
+        return false;
+    };
+    let reg = addr as *mut u32;
+    unsafe { reg.write_volatile((reg.read_volatile() & !mask) | (value & mask)) };
+    true
+}
+
+// -----------------------------------------------------------------------------
+// Harts
+// -----------------------------------------------------------------------------
+
+// The hart running the monitor.
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+/// Record the hart id passed in a0 at boot.
+pub(crate) fn set_boot_hart(hartid: usize) {
+    BOOT_HART.store(hartid, Ordering::Relaxed);
+}
+
+/// The hart running the monitor.
+pub(crate) fn boot_hart() -> usize {
+    BOOT_HART.load(Ordering::Relaxed)
+}
+
+/// The state of a hart as reported by the SBI HSM extension, or None if the
+/// hart does not exist (or, in machine mode, there is no firmware to ask).
+pub(crate) fn hart_status(hartid: usize) -> Option<&'static str> {
+    #[cfg(feature = "supervisor")]
+    return crate::sbi::hart_status(hartid);
+    #[cfg(not(feature = "supervisor"))]
+    {
+        let _ = hartid;
+        None
+    }
+}
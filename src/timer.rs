@@ -0,0 +1,89 @@
+use crate::csr;
+
+// -----------------------------------------------------------------------------
+// Core Local Interruptor (CLINT)
+// -----------------------------------------------------------------------------
+
+// QEMU virt machine CLINT base address (memory-mapped I/O).
+//
+// Only used in machine mode; under SBI firmware the timer is programmed through
+// the SBI TIME extension and read from the `time` CSR instead.
+#[cfg(not(feature = "supervisor"))]
+const CLINT_BASE: usize = 0x0200_0000;
+
+// Offset (in bytes) from CLINT_BASE for the per-hart mtimecmp registers.
+//
+// Each hart has one 64-bit compare register, indexed by hart id.
+#[cfg(not(feature = "supervisor"))]
+const CLINT_MTIMECMP: usize = 0x4000;
+
+// Offset (in bytes) from CLINT_BASE for the free-running mtime counter.
+#[cfg(not(feature = "supervisor"))]
+const CLINT_MTIME: usize = 0xbff8;
+
+// The `mhartid` CSR: this hart's id.
+#[cfg(not(feature = "supervisor"))]
+const CSR_MHARTID: usize = 0xf14;
+
+// The `time` CSR: a read-only shadow of mtime available to supervisor mode.
+#[cfg(feature = "supervisor")]
+const CSR_TIME: usize = 0xc01;
+
+// Interrupt enable bit (mie.MTIE or sie.STIE) for the monitor's timer.
+const IE_TIMER: usize = 1 << csr::IRQ_TIMER;
+
+/// Frequency of `mtime` on the QEMU virt machine (10 MHz).
+pub(crate) const TICKS_PER_SEC: u64 = 10_000_000;
+
+// -----------------------------------------------------------------------------
+// Timer functions
+// -----------------------------------------------------------------------------
+
+/// Read the current value of the `mtime` counter.
+pub(crate) fn now() -> u64 {
+    #[cfg(not(feature = "supervisor"))]
+    let t = unsafe { ((CLINT_BASE + CLINT_MTIME) as *const u64).read_volatile() };
+    #[cfg(feature = "supervisor")]
+    let t = csr::read!(CSR_TIME) as u64;
+    t
+}
+
+/// Convert milliseconds to `mtime` ticks.
+pub(crate) fn ms_to_ticks(ms: u64) -> u64 {
+    ms.saturating_mul(TICKS_PER_SEC / 1000)
+}
+
+/// Arm the timer interrupt to fire once `mtime` reaches `deadline`.
+///
+/// This only sets the timer interrupt enable bit; the interrupt is taken once
+/// the global enable in the status CSR is also set (which happens when a
+/// program is entered via `trap::enter`).
+pub(crate) fn arm(deadline: u64) {
+    set_compare(deadline);
+    csr::set!(csr::IE, IE_TIMER);
+}
+
+/// Disarm the timer interrupt.
+pub(crate) fn disarm() {
+    csr::clear!(csr::IE, IE_TIMER);
+    set_compare(u64::MAX);
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Program this hart's timer compare value.
+#[cfg(not(feature = "supervisor"))]
+fn set_compare(deadline: u64) {
+    let hart = csr::read!(CSR_MHARTID);
+    unsafe {
+        ((CLINT_BASE + CLINT_MTIMECMP + hart * 8) as *mut u64).write_volatile(deadline);
+    }
+}
+
+// Program this hart's timer compare value.
+#[cfg(feature = "supervisor")]
+fn set_compare(deadline: u64) {
+    crate::sbi::set_timer(deadline);
+}
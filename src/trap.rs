@@ -0,0 +1,684 @@
+use crate::{
+    csr, timer, uart,
+    uart::{print, print_hex_u64, println},
+};
+#[cfg(not(feature = "supervisor"))]
+use crate::{firmware, memory, paging, pmp, services};
+use core::arch::{global_asm, naked_asm};
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+
+// -----------------------------------------------------------------------------
+// Trap Frame
+// -----------------------------------------------------------------------------
+
+/// Register state of a program captured when it trapped into the monitor.
+///
+/// The layout is shared with the assembly below: `regs[i]` holds `x{i}` at
+/// offset `8 * i`, followed by the trap CSRs.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub(crate) struct TrapFrame {
+    pub(crate) regs: [usize; 32],
+    pub(crate) pc: usize,
+    pub(crate) status: usize,
+    pub(crate) cause: usize,
+    pub(crate) tval: usize,
+}
+
+const _: () = assert!(core::mem::offset_of!(TrapFrame, pc) == 256);
+const _: () = assert!(core::mem::offset_of!(TrapFrame, status) == 264);
+const _: () = assert!(core::mem::offset_of!(TrapFrame, cause) == 272);
+const _: () = assert!(core::mem::offset_of!(TrapFrame, tval) == 280);
+
+/// ABI names of the integer registers, indexed by register number.
+pub(crate) const REG_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+// -----------------------------------------------------------------------------
+// Program Exit
+// -----------------------------------------------------------------------------
+
+/// Why control came back to the monitor from a jumped-to program.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Exit {
+    /// The program returned through `ra`.
+    Returned,
+    /// The user pressed Ctrl+C; the program can be resumed with `continue`.
+    Break,
+    /// The program raised an exception it cannot be resumed from.
+    Fault,
+    /// The watchdog fired before the program returned.
+    Timeout,
+    /// A booted supervisor-mode payload asked the SBI for a reboot.
+    Reset,
+    /// The program asked to return to the monitor (or made an unknown
+    /// service call); it can be resumed with `continue`.
+    Ecall,
+    /// The program ended through the `EXIT` service call with this code.
+    Exited(usize),
+}
+
+// Exit reasons as passed in a0 through the assembly below.
+const EXIT_RESUME: usize = 0;
+const EXIT_RETURNED: usize = 1;
+const EXIT_BREAK: usize = 2;
+const EXIT_FAULT: usize = 3;
+const EXIT_TIMEOUT: usize = 4;
+const EXIT_RESET: usize = 5;
+const EXIT_ECALL: usize = 6;
+const EXIT_EXITED: usize = 7;
+
+// How often the break-in tick polls the UART for Ctrl+C.
+const BREAK_POLL_MS: u64 = 10;
+
+// -----------------------------------------------------------------------------
+// Trap State
+// -----------------------------------------------------------------------------
+
+// Size of the stack the Rust trap handler runs on.
+//
+// A program may trap with any sp (or none at all), so the handler never runs on
+// the interrupted stack.
+const TRAP_STACK_SIZE: usize = 4 * 1024;
+
+#[repr(C, align(16))]
+struct TrapStack([u8; TRAP_STACK_SIZE]);
+
+static mut TRAP_STACK: TrapStack = TrapStack([0; TRAP_STACK_SIZE]);
+
+// The most recently trapped program's registers. The scratch CSR points here.
+static mut FRAME: TrapFrame = TrapFrame {
+    regs: [0; 32],
+    pc: 0,
+    status: 0,
+    cause: 0,
+    tval: 0,
+};
+
+// The monitor's callee-saved registers (ra, sp, s0..s11) at the time it
+// entered a program, restored when control comes back.
+static mut MONITOR_CTX: [usize; 14] = [0; 14];
+
+// Whether a program (rather than the monitor itself) is currently running.
+static GUEST_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// How the current (or last stopped) program was entered.
+static GUEST_KIND: AtomicU8 = AtomicU8::new(KIND_JUMP);
+
+// Program kinds: `jump`ed to in the monitor's own mode, `boot`ed as a
+// supervisor-mode payload with riscmon acting as its SBI firmware, or
+// `ujump`ed to in user mode inside a PMP sandbox.
+const KIND_JUMP: u8 = 0;
+#[cfg(not(feature = "supervisor"))]
+const KIND_SUPERVISOR: u8 = 1;
+#[cfg(not(feature = "supervisor"))]
+const KIND_USER: u8 = 2;
+
+// Exception codes the trap handler acts on.
+const EXC_FETCH_ACCESS: usize = 1;
+const EXC_ILLEGAL_INSTRUCTION: usize = 2;
+const EXC_BREAKPOINT: usize = 3;
+#[cfg(not(feature = "supervisor"))]
+const EXC_ECALL_U: usize = 8;
+#[cfg(not(feature = "supervisor"))]
+const EXC_ECALL_S: usize = 9;
+#[cfg(not(feature = "supervisor"))]
+const EXC_ECALL_M: usize = 11;
+
+// Whether the last program stopped at a point it can be resumed from.
+static RESUMABLE: AtomicBool = AtomicBool::new(false);
+
+// Whether the monitor is running a `probe`, and whether it faulted.
+static PROBING: AtomicBool = AtomicBool::new(false);
+static PROBE_FAULTED: AtomicBool = AtomicBool::new(false);
+
+// The mtime value at which the watchdog aborts the running program (0 = none).
+static WATCHDOG_DEADLINE: AtomicU64 = AtomicU64::new(0);
+
+// The mtime value at which the break-in tick next polls the UART.
+static NEXT_POLL: AtomicU64 = AtomicU64::new(0);
+
+// -----------------------------------------------------------------------------
+// Trap API
+// -----------------------------------------------------------------------------
+
+/// Install the trap vector. Must be called once at boot.
+pub(crate) fn init() {
+    timer::disarm();
+    csr::write!(csr::SCRATCH, &raw mut FRAME as usize);
+    csr::write!(csr::TVEC, trap_vector as *const () as usize);
+}
+
+/// Run the program at `addr` until it returns, faults or is interrupted.
+///
+/// The program is entered in the monitor's own mode with interrupts enabled, so the
+/// break-in tick can stop it when the user presses Ctrl+C. If `timeout_ms` is
+/// given, the program is aborted once it has run for that long.
+pub(crate) fn enter(addr: usize, timeout_ms: Option<u64>) -> Exit {
+    let deadline = match timeout_ms {
+        Some(ms) => timer::now().saturating_add(timer::ms_to_ticks(ms)).max(1),
+        None => 0,
+    };
+    WATCHDOG_DEADLINE.store(deadline, Ordering::Relaxed);
+    GUEST_KIND.store(KIND_JUMP, Ordering::Relaxed);
+    #[cfg(not(feature = "supervisor"))]
+    services::set_window(memory::RAM_BASE, memory::RAM_END_INCLUSIVE);
+
+    arm_next_tick();
+    GUEST_ACTIVE.store(true, Ordering::Relaxed);
+    // a0 carries the entry address, as it always has for `jump`.
+    let reason = unsafe { enter_guest(addr, csr::STATUS_PP, addr, 0, 0) };
+    finish(reason)
+}
+
+/// Boot a supervisor-mode payload at `addr` with `a0 = hartid`, `a1 = dtb`,
+/// servicing its SBI calls until it returns, faults, reboots or is interrupted.
+///
+/// If translation is on (`pt satp on`), the payload starts with the `pt` tables
+/// loaded into satp.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) fn enter_supervisor(addr: usize, hartid: usize, dtb: usize) -> Exit {
+    WATCHDOG_DEADLINE.store(0, Ordering::Relaxed);
+    GUEST_KIND.store(KIND_SUPERVISOR, Ordering::Relaxed);
+    firmware::prepare();
+    paging::activate(paging::satp());
+
+    arm_next_tick();
+    GUEST_ACTIVE.store(true, Ordering::Relaxed);
+    let reason = unsafe { enter_guest(addr, csr::STATUS_PP_S, hartid, dtb, 0) };
+    finish(reason)
+}
+
+/// Run the program at `addr` in user mode, with access to nothing but the
+/// RAM window `base..base + size` (which must be NAPOT-encodable).
+///
+/// The program starts with a0 = `addr` and sp at the top of the window. Any
+/// access outside the window traps back to the monitor. If translation is on
+/// (`pt satp on`), `addr` is virtual and the tables must lie in the window.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) fn enter_user(addr: usize, base: usize, size: usize) -> Exit {
+    WATCHDOG_DEADLINE.store(0, Ordering::Relaxed);
+    GUEST_KIND.store(KIND_USER, Ordering::Relaxed);
+    services::set_window(base, base + (size - 1));
+    pmp::set_napot(0, base, size, pmp::RWX);
+    pmp::clear(1);
+    paging::activate(paging::satp());
+
+    arm_next_tick();
+    GUEST_ACTIVE.store(true, Ordering::Relaxed);
+    let reason = unsafe { enter_guest(addr, csr::STATUS_PP_U, addr, 0, base + size) };
+    finish(reason)
+}
+
+/// Resume the program stopped by the last break, if any.
+///
+/// A resumed program runs without a watchdog.
+pub(crate) fn resume() -> Option<Exit> {
+    if !RESUMABLE.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    WATCHDOG_DEADLINE.store(0, Ordering::Relaxed);
+    #[cfg(not(feature = "supervisor"))]
+    if GUEST_KIND.load(Ordering::Relaxed) == KIND_SUPERVISOR {
+        firmware::prepare();
+    }
+
+    arm_next_tick();
+    GUEST_ACTIVE.store(true, Ordering::Relaxed);
+    let reason = unsafe { resume_guest() };
+    Some(finish(reason))
+}
+
+/// Run `f`, which uses CSRs the hart may not implement or a semihosting
+/// `ebreak` the host may not intercept, returning None if any of them raised
+/// an illegal-instruction or breakpoint exception.
+///
+/// Faulting instructions are skipped (leaving their destination unchanged).
+/// The registers saved from the last stopped program are preserved, so a
+/// probe does not disturb `regs` or `continue`.
+pub(crate) fn probe<T>(f: impl FnOnce() -> T) -> Option<T> {
+    let saved = saved_frame();
+    PROBE_FAULTED.store(false, Ordering::Relaxed);
+    PROBING.store(true, Ordering::Relaxed);
+    let v = f();
+    PROBING.store(false, Ordering::Relaxed);
+    unsafe { (&raw mut FRAME).write(saved) };
+
+    (!PROBE_FAULTED.load(Ordering::Relaxed)).then_some(v)
+}
+
+/// Whether the running program's watchdog (`jump ADDR timeout MS`) has
+/// expired.
+#[cfg(not(feature = "supervisor"))]
+pub(crate) fn watchdog_expired() -> bool {
+    let deadline = WATCHDOG_DEADLINE.load(Ordering::Relaxed);
+    deadline != 0 && timer::now() >= deadline
+}
+
+/// Return a copy of the registers captured at the last trap.
+pub(crate) fn saved_frame() -> TrapFrame {
+    unsafe { (&raw const FRAME).read() }
+}
+
+/// Describe a trap cause (`mcause`/`scause`) value.
+pub(crate) fn cause_name(cause: usize) -> &'static str {
+    if cause & csr::CAUSE_INTERRUPT != 0 {
+        return match cause & !csr::CAUSE_INTERRUPT {
+            1 => "supervisor software interrupt",
+            3 => "machine software interrupt",
+            5 => "supervisor timer interrupt",
+            7 => "machine timer interrupt",
+            9 => "supervisor external interrupt",
+            11 => "machine external interrupt",
+            _ => "unknown interrupt",
+        };
+    }
+
+    match cause {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store address misaligned",
+        7 => "store access fault",
+        8 => "environment call from U-mode",
+        9 => "environment call from S-mode",
+        11 => "environment call from M-mode",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store page fault",
+        _ => "unknown exception",
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Trap Handler
+// -----------------------------------------------------------------------------
+
+// Handle a trap. Called from `trap_vector` on the trap stack.
+//
+// Returns EXIT_RESUME to continue the interrupted code, or another exit reason
+// to hand control back to the monitor.
+extern "C" fn trap_handler(frame: &mut TrapFrame) -> usize {
+    if !GUEST_ACTIVE.load(Ordering::Relaxed) {
+        // CSR instructions and the semihosting `ebreak` are never compressed,
+        // so skipping one is +4.
+        let probed = matches!(frame.cause, EXC_ILLEGAL_INSTRUCTION | EXC_BREAKPOINT);
+        if PROBING.load(Ordering::Relaxed) && probed {
+            PROBE_FAULTED.store(true, Ordering::Relaxed);
+            frame.pc += 4;
+            return EXIT_RESUME;
+        }
+        monitor_fault(frame);
+    }
+
+    if frame.cause == csr::CAUSE_INTERRUPT | csr::IRQ_TIMER {
+        let now = timer::now();
+        let deadline = WATCHDOG_DEADLINE.load(Ordering::Relaxed);
+        if deadline != 0 && now >= deadline {
+            return EXIT_TIMEOUT;
+        }
+
+        #[cfg(not(feature = "supervisor"))]
+        firmware::timer_tick(now);
+
+        arm_next_tick();
+        return match uart::poll_break() {
+            true => EXIT_BREAK,
+            false => EXIT_RESUME,
+        };
+    }
+
+    // A program running in a less privileged mode cannot execute the monitor's
+    // `guest_returned` landing pad, so returning through ra faults there.
+    if frame.cause == EXC_FETCH_ACCESS && frame.pc == guest_returned as *const () as usize {
+        return EXIT_RETURNED;
+    }
+
+    // Programs launched with `jump` or `ujump` call the monitor's services.
+    #[cfg(not(feature = "supervisor"))]
+    if matches!(
+        (frame.cause, GUEST_KIND.load(Ordering::Relaxed)),
+        (EXC_ECALL_M, KIND_JUMP) | (EXC_ECALL_U, KIND_USER)
+    ) {
+        let outcome = services::handle_ecall(frame);
+        return match outcome {
+            services::Service::Resume => EXIT_RESUME,
+            services::Service::Exit => EXIT_EXITED,
+            services::Service::Stop => EXIT_ECALL,
+            services::Service::Break => EXIT_BREAK,
+            services::Service::Timeout => EXIT_TIMEOUT,
+        };
+    }
+
+    #[cfg(not(feature = "supervisor"))]
+    if frame.cause == EXC_ECALL_S && GUEST_KIND.load(Ordering::Relaxed) == KIND_SUPERVISOR {
+        let outcome = firmware::handle_ecall(frame);
+        arm_next_tick();
+        return match outcome {
+            firmware::Ecall::Resume => EXIT_RESUME,
+            firmware::Ecall::Reboot => EXIT_RESET,
+        };
+    }
+
+    EXIT_FAULT
+}
+
+// Report a trap taken by the monitor itself and halt.
+//
+// The monitor runs with interrupts disabled, so this is always an exception
+// and there is no sane state to return to.
+fn monitor_fault(frame: &TrapFrame) -> ! {
+    print("\r\nmonitor trap: ");
+    println(cause_name(frame.cause));
+    print("pc ");
+    print_hex_u64(frame.pc as u64);
+    print(" tval ");
+    print_hex_u64(frame.tval as u64);
+    println("");
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Schedule the next timer interrupt: the break-in poll, or the watchdog or a
+// booted payload's own timer event if either is due sooner.
+fn arm_next_tick() {
+    // The poll keeps its own schedule, so a payload making frequent SBI calls
+    // (which re-arm the timer) cannot postpone it indefinitely.
+    let now = timer::now();
+    let mut next = NEXT_POLL.load(Ordering::Relaxed);
+    if next <= now {
+        next = now + timer::ms_to_ticks(BREAK_POLL_MS);
+        NEXT_POLL.store(next, Ordering::Relaxed);
+    }
+    if let deadline @ 1.. = WATCHDOG_DEADLINE.load(Ordering::Relaxed) {
+        next = next.min(deadline);
+    }
+    #[cfg(not(feature = "supervisor"))]
+    if let Some(deadline) = firmware::timer_deadline() {
+        next = next.min(deadline);
+    }
+    timer::arm(next);
+}
+
+// Common bookkeeping once control is back in the monitor.
+fn finish(reason: usize) -> Exit {
+    timer::disarm();
+    GUEST_ACTIVE.store(false, Ordering::Relaxed);
+    #[cfg(not(feature = "supervisor"))]
+    if GUEST_KIND.load(Ordering::Relaxed) == KIND_SUPERVISOR {
+        firmware::teardown();
+    }
+
+    let exit = match reason {
+        EXIT_RETURNED => Exit::Returned,
+        EXIT_BREAK => Exit::Break,
+        EXIT_TIMEOUT => Exit::Timeout,
+        EXIT_RESET => Exit::Reset,
+        EXIT_ECALL => Exit::Ecall,
+        EXIT_EXITED => Exit::Exited(saved_frame().regs[10]),
+        _ => Exit::Fault,
+    };
+    RESUMABLE.store(matches!(exit, Exit::Break | Exit::Ecall), Ordering::Relaxed);
+
+    exit
+}
+
+// -----------------------------------------------------------------------------
+// Context Switching
+// -----------------------------------------------------------------------------
+
+// Save the monitor's callee-saved registers and mret (or sret) into a program
+// in the privilege mode given by `pp` (a value of the status CSR's
+// previous-privilege field), passing `arg0`/`arg1` in a0/a1. A non-zero `sp`
+// replaces the stack pointer; otherwise the program runs on the monitor's.
+//
+// The program's ra points at `guest_returned`, so a normal return lands back
+// in the monitor no matter what the callee did to sp or the s registers.
+#[unsafe(naked)]
+unsafe extern "C" fn enter_guest(
+    entry: usize,
+    pp: usize,
+    arg0: usize,
+    arg1: usize,
+    sp: usize,
+) -> usize {
+    naked_asm!(
+        "la   t0, {ctx}",
+        "sd   ra, 0(t0)",
+        "sd   sp, 8(t0)",
+        "sd   s0, 16(t0)",
+        "sd   s1, 24(t0)",
+        "sd   s2, 32(t0)",
+        "sd   s3, 40(t0)",
+        "sd   s4, 48(t0)",
+        "sd   s5, 56(t0)",
+        "sd   s6, 64(t0)",
+        "sd   s7, 72(t0)",
+        "sd   s8, 80(t0)",
+        "sd   s9, 88(t0)",
+        "sd   s10, 96(t0)",
+        "sd   s11, 104(t0)",
+        "csrw {epc}, a0",         // program entry
+        "li   t0, {pp_mask}",     // return into mode `pp` with interrupts on
+        "csrc {status_csr}, t0",
+        "csrs {status_csr}, a1",
+        "li   t0, {pie}",
+        "csrs {status_csr}, t0",
+        "mv   a0, a2",
+        "mv   a1, a3",
+        "beqz a4, 1f",
+        "mv   sp, a4",
+        "1:",
+        "la   ra, {returned}",    // the program returns here
+        csr::xret!(),
+        ctx = sym MONITOR_CTX,
+        returned = sym guest_returned,
+        epc = const csr::EPC,
+        pp_mask = const csr::STATUS_PP,
+        pie = const csr::STATUS_PIE,
+        status_csr = const csr::STATUS,
+    );
+}
+
+// Save the monitor's callee-saved registers and restore the trapped program.
+#[unsafe(naked)]
+unsafe extern "C" fn resume_guest() -> usize {
+    naked_asm!(
+        "la   t0, {ctx}",
+        "sd   ra, 0(t0)",
+        "sd   sp, 8(t0)",
+        "sd   s0, 16(t0)",
+        "sd   s1, 24(t0)",
+        "sd   s2, 32(t0)",
+        "sd   s3, 40(t0)",
+        "sd   s4, 48(t0)",
+        "sd   s5, 56(t0)",
+        "sd   s6, 64(t0)",
+        "sd   s7, 72(t0)",
+        "sd   s8, 80(t0)",
+        "sd   s9, 88(t0)",
+        "sd   s10, 96(t0)",
+        "sd   s11, 104(t0)",
+        "tail {restore}",
+        ctx = sym MONITOR_CTX,
+        restore = sym restore_frame,
+    );
+}
+
+// Landing pad for a program returning through ra.
+#[unsafe(naked)]
+unsafe extern "C" fn guest_returned() {
+    naked_asm!(
+        "csrci {status}, {ie}",
+        "li   a0, {reason}",
+        "tail {exit}",
+        status = const csr::STATUS,
+        ie = const csr::STATUS_IE,
+        reason = const EXIT_RETURNED,
+        exit = sym exit_to_monitor,
+    );
+}
+
+// Restore the monitor's saved context and return the reason in a0 to the
+// caller of `enter_guest` / `resume_guest`.
+#[unsafe(naked)]
+unsafe extern "C" fn exit_to_monitor() {
+    naked_asm!(
+        "la   t0, {ctx}",
+        "ld   ra, 0(t0)",
+        "ld   sp, 8(t0)",
+        "ld   s0, 16(t0)",
+        "ld   s1, 24(t0)",
+        "ld   s2, 32(t0)",
+        "ld   s3, 40(t0)",
+        "ld   s4, 48(t0)",
+        "ld   s5, 56(t0)",
+        "ld   s6, 64(t0)",
+        "ld   s7, 72(t0)",
+        "ld   s8, 80(t0)",
+        "ld   s9, 88(t0)",
+        "ld   s10, 96(t0)",
+        "ld   s11, 104(t0)",
+        "ret",
+        ctx = sym MONITOR_CTX,
+    );
+}
+
+// Restore every register from the trap frame (pointed to by the scratch CSR)
+// and return into the interrupted code.
+#[unsafe(naked)]
+unsafe extern "C" fn restore_frame() {
+    naked_asm!(
+        "csrr t6, {scratch}",
+        "ld   t0, 256(t6)",
+        "csrw {epc}, t0",
+        "ld   t0, 264(t6)",
+        "csrw {status}, t0",
+        "ld   x1, 8(t6)",
+        "ld   x2, 16(t6)",
+        "ld   x3, 24(t6)",
+        "ld   x4, 32(t6)",
+        "ld   x5, 40(t6)",
+        "ld   x6, 48(t6)",
+        "ld   x7, 56(t6)",
+        "ld   x8, 64(t6)",
+        "ld   x9, 72(t6)",
+        "ld   x10, 80(t6)",
+        "ld   x11, 88(t6)",
+        "ld   x12, 96(t6)",
+        "ld   x13, 104(t6)",
+        "ld   x14, 112(t6)",
+        "ld   x15, 120(t6)",
+        "ld   x16, 128(t6)",
+        "ld   x17, 136(t6)",
+        "ld   x18, 144(t6)",
+        "ld   x19, 152(t6)",
+        "ld   x20, 160(t6)",
+        "ld   x21, 168(t6)",
+        "ld   x22, 176(t6)",
+        "ld   x23, 184(t6)",
+        "ld   x24, 192(t6)",
+        "ld   x25, 200(t6)",
+        "ld   x26, 208(t6)",
+        "ld   x27, 216(t6)",
+        "ld   x28, 224(t6)",
+        "ld   x29, 232(t6)",
+        "ld   x30, 240(t6)",
+        "ld   x31, 248(t6)",
+        csr::xret!(),
+        scratch = const csr::SCRATCH,
+        epc = const csr::EPC,
+        status = const csr::STATUS,
+    );
+}
+
+unsafe extern "C" {
+    fn trap_vector();
+}
+
+// The trap vector. The trap vector CSR requires 4-byte alignment, which a naked function
+// cannot guarantee when compressed instructions are enabled, so it is written
+// with global_asm instead.
+//
+// The scratch CSR always holds the address of FRAME. The interrupted sp is
+// swapped into it just long enough to free up a register for the frame base.
+global_asm!(
+    ".pushsection .text.trap, \"ax\"",
+    ".balign 4",
+    ".global trap_vector",
+    "trap_vector:",
+    "csrrw sp, {scratch}, sp",
+    "sd   x1, 8(sp)",
+    "sd   x3, 24(sp)",
+    "sd   x4, 32(sp)",
+    "sd   x5, 40(sp)",
+    "sd   x6, 48(sp)",
+    "sd   x7, 56(sp)",
+    "sd   x8, 64(sp)",
+    "sd   x9, 72(sp)",
+    "sd   x10, 80(sp)",
+    "sd   x11, 88(sp)",
+    "sd   x12, 96(sp)",
+    "sd   x13, 104(sp)",
+    "sd   x14, 112(sp)",
+    "sd   x15, 120(sp)",
+    "sd   x16, 128(sp)",
+    "sd   x17, 136(sp)",
+    "sd   x18, 144(sp)",
+    "sd   x19, 152(sp)",
+    "sd   x20, 160(sp)",
+    "sd   x21, 168(sp)",
+    "sd   x22, 176(sp)",
+    "sd   x23, 184(sp)",
+    "sd   x24, 192(sp)",
+    "sd   x25, 200(sp)",
+    "sd   x26, 208(sp)",
+    "sd   x27, 216(sp)",
+    "sd   x28, 224(sp)",
+    "sd   x29, 232(sp)",
+    "sd   x30, 240(sp)",
+    "sd   x31, 248(sp)",
+    "csrr t0, {scratch}", // interrupted sp
+    "sd   t0, 16(sp)",
+    "csrw {scratch}, sp", // scratch = &FRAME again
+    "csrr t0, {epc}",
+    "sd   t0, 256(sp)",
+    "csrr t0, {status}",
+    "sd   t0, 264(sp)",
+    "csrr t0, {cause}",
+    "sd   t0, 272(sp)",
+    "csrr t0, {tval}",
+    "sd   t0, 280(sp)",
+    "mv   a0, sp",
+    "la   sp, {stack}",
+    "li   t0, {stack_size}",
+    "add  sp, sp, t0",
+    "call {handler}",
+    "bnez a0, 1f",
+    "tail {restore}",
+    "1:",
+    "tail {exit}",
+    ".popsection",
+    stack = sym TRAP_STACK,
+    stack_size = const TRAP_STACK_SIZE,
+    handler = sym trap_handler,
+    restore = sym restore_frame,
+    exit = sym exit_to_monitor,
+    scratch = const csr::SCRATCH,
+    epc = const csr::EPC,
+    status = const csr::STATUS,
+    cause = const csr::CAUSE,
+    tval = const csr::TVAL,
+);
@@ -1,60 +1,57 @@
-use crate::hex;
+use crate::{console, fdt, hex};
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 
 // -----------------------------------------------------------------------------
-// UART register offsets and bitfields (16550-compatible)
+// UART setup
 // -----------------------------------------------------------------------------
 
-// QEMU virt machine UART base address (memory-mapped I/O).
-//
-// QEMU exposes a 16550-compatible UART at this address.
-const UART_BASE: usize = 0x1000_0000;
-
-// Offset (in bytes) from UART_BASE for the RHR/THR register.
-//
-// 16550 quirk: offset 0 is *multiplexed*.
-// - read  @ +0 => RHR (Receive Holding Register: next received byte)
-// - write @ +0 => THR (Transmit Holding Register: byte to send)
-const UART_RHR_THR: usize = 0x00;
-
-// Offset (in bytes) from UART_BASE for the LSR (Line Status Register).
-//
-// We poll LSR bits to implement blocking RX/TX without interrupts.
-const UART_LSR: usize = 0x05;
+/// Select and initialize the console driver (see `console::init`).
+pub(crate) fn init() {
+    console::init(fdt::get());
+}
 
-// LSR bit mask: DR (Data Ready). When set, RHR has at least one byte to read.
-const LSR_DATA_READY: u8 = 1 << 0;
+/// Reprogram the console baud rate. Returns false if the rate cannot be
+/// derived from the UART input clock or the console has no baud control.
+pub(crate) fn set_baud(baud: u32) -> bool {
+    console::active().set_baud(baud)
+}
 
-// LSR bit mask: THRE (THR Empty). When set, THR can accept the next TX byte.
-const LSR_THR_EMPTY: u8 = 1 << 5;
+/// The currently programmed baud rate, if the console has one.
+pub(crate) fn baud() -> Option<u32> {
+    console::active().baud()
+}
 
 // -----------------------------------------------------------------------------
 // UART I/O functions
 // -----------------------------------------------------------------------------
 
-/// Read a single byte from UART (blocking).
+/// Read a single byte from UART if one is available (non-blocking).
 ///
-/// This is a polled implementation: we spin until LSR indicates RX data is ready.
-pub(crate) fn getc() -> u8 {
-    while unsafe { ((UART_BASE + UART_LSR) as *const u8).read_volatile() } & LSR_DATA_READY == 0 {
-        core::hint::spin_loop();
-    }
-
-    unsafe { ((UART_BASE + UART_RHR_THR) as *const u8).read_volatile() }
+/// Used by the REPL while it polls for other work, and to service console
+/// reads from launched programs and booted payloads (machine mode only).
+/// Bytes held back by [`poll_break`] are returned first.
+pub(crate) fn try_getc() -> Option<u8> {
+    pending_pop().or_else(|| console::active().try_getc())
 }
 
-/// Write a single byte to UART (blocking).
+/// Check whether Ctrl+C (ETX) has been received, without blocking.
 ///
-/// We spin until LSR indicates THR is empty, then write the byte to THR.
-pub(crate) fn putc(c: u8) {
-    while unsafe { ((UART_BASE + UART_LSR) as *const u8).read_volatile() } & LSR_THR_EMPTY == 0 {
-        core::hint::spin_loop();
+/// Used by the break-in timer tick while a jumped-to program runs. Reading the
+/// receiver is destructive, so any other byte received is held back for the
+/// next [`try_getc`] rather than dropped.
+pub(crate) fn poll_break() -> bool {
+    while let Some(b) = console::active().try_getc() {
+        if b == 0x03 {
+            return true;
+        }
+        pending_push(b);
     }
+    false
+}
 
-    let uart = (UART_BASE + UART_RHR_THR) as *mut u8;
-    unsafe {
-        // Volatile is required for MMIO: the compiler must not optimize this away.
-        uart.write_volatile(c);
-    }
+/// Write a single byte to UART (blocking).
+pub(crate) fn putc(c: u8) {
+    console::active().putc(c);
 }
 
 /// Print a string to UART.
@@ -80,6 +77,12 @@ pub(crate) fn print_hex_u8(v: u8) {
     putc(hex::hex_digit(v & 0x0f));
 }
 
+/// Print a 16-bit value as four lowercase hex digits (no prefix).
+pub(crate) fn print_hex_u16(v: u16) {
+    print_hex_u8((v >> 8) as u8);
+    print_hex_u8(v as u8);
+}
+
 /// Print a 32-bit value as eight lowercase hex digits (no prefix).
 pub(crate) fn print_hex_u32(v: u32) {
     for shift in (0..32).step_by(4).rev() {
@@ -88,7 +91,71 @@ pub(crate) fn print_hex_u32(v: u32) {
     }
 }
 
+/// Print a 64-bit value as sixteen lowercase hex digits (no prefix).
+pub(crate) fn print_hex_u64(v: u64) {
+    for shift in (0..64).step_by(4).rev() {
+        let nibble = ((v >> shift) & 0x0f) as u8;
+        putc(hex::hex_digit(nibble));
+    }
+}
+
+/// Print an unsigned value in decimal.
+pub(crate) fn print_dec_u64(v: u64) {
+    let mut digits = [0u8; 20];
+    let mut n = 0;
+    let mut v = v;
+    loop {
+        digits[n] = b'0' + (v % 10) as u8;
+        n += 1;
+        v /= 10;
+        if v == 0 {
+            break;
+        }
+    }
+    for &d in digits[..n].iter().rev() {
+        putc(d);
+    }
+}
+
 /// Clear the user's terminal via ANSI escape sequences.
 pub(crate) fn clear_screen() {
     print("\x1b[2J\x1b[H");
 }
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Capacity of the held-back input buffer (see `poll_break`).
+const PENDING_CAP: usize = 16;
+
+// Bytes received by `poll_break` that were not Ctrl+C, in arrival order.
+//
+// Only the trap handler pushes and only the REPL pops, and the two never run
+// concurrently on a single hart, so relaxed atomics are sufficient.
+static PENDING: [AtomicU8; PENDING_CAP] = [const { AtomicU8::new(0) }; PENDING_CAP];
+static PENDING_HEAD: AtomicUsize = AtomicUsize::new(0);
+static PENDING_LEN: AtomicUsize = AtomicUsize::new(0);
+
+// Append a byte to the held-back input (dropped once full).
+fn pending_push(b: u8) {
+    let len = PENDING_LEN.load(Ordering::Relaxed);
+    if len == PENDING_CAP {
+        return;
+    }
+    let idx = (PENDING_HEAD.load(Ordering::Relaxed) + len) % PENDING_CAP;
+    PENDING[idx].store(b, Ordering::Relaxed);
+    PENDING_LEN.store(len + 1, Ordering::Relaxed);
+}
+
+// Take the oldest held-back input byte, if any.
+fn pending_pop() -> Option<u8> {
+    let len = PENDING_LEN.load(Ordering::Relaxed);
+    if len == 0 {
+        return None;
+    }
+    let head = PENDING_HEAD.load(Ordering::Relaxed);
+    PENDING_HEAD.store((head + 1) % PENDING_CAP, Ordering::Relaxed);
+    PENDING_LEN.store(len - 1, Ordering::Relaxed);
+    Some(PENDING[head].load(Ordering::Relaxed))
+}
@@ -0,0 +1,189 @@
+use super::{Device, ID_BLOCK, Queue};
+
+// -----------------------------------------------------------------------------
+// Virtio Block Device
+// -----------------------------------------------------------------------------
+
+// The first block device found is used. It is set up on first use and stays
+// set up (unless a request times out), so a disk attached to QEMU costs
+// nothing until `blk` is run.
+
+/// Bytes per sector; virtio-blk always addresses 512-byte sectors.
+pub(crate) const SECTOR_SIZE: usize = 512;
+
+// Request types.
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+
+// Request status values.
+const S_OK: u8 = 0;
+const S_UNSUPP: u8 = 2;
+
+// Feature bits.
+const F_RO: u64 = 1 << 5;
+const F_FLUSH: u64 = 1 << 9;
+
+// Offset of the capacity (in sectors) in the configuration space.
+const CONFIG_CAPACITY: usize = 0;
+
+// Most sectors moved by one request (64 KiB).
+const MAX_REQUEST_SECTORS: u64 = 128;
+
+// The header every request starts with.
+#[repr(C)]
+struct Header {
+    kind: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+// Driver state. Only touched from the REPL, one request at a time.
+struct Disk {
+    dev: Option<Device>,
+    features: u64,
+    queue: Queue,
+    header: Header,
+    status: u8,
+}
+
+static mut DISK: Disk = Disk {
+    dev: None,
+    features: 0,
+    queue: Queue::new(0),
+    header: Header {
+        kind: 0,
+        reserved: 0,
+        sector: 0,
+    },
+    status: 0,
+};
+
+/// The attached disk.
+pub(crate) struct Info {
+    /// The virtio-mmio device.
+    pub(crate) dev: Device,
+    /// Size in sectors.
+    pub(crate) sectors: u64,
+    /// Whether the device refuses writes.
+    pub(crate) read_only: bool,
+}
+
+/// Describe the attached disk.
+pub(crate) fn info() -> Result<Info, &'static str> {
+    let disk = disk()?;
+    let dev = disk.dev.ok_or("no virtio block device")?;
+    Ok(Info {
+        dev,
+        sectors: dev.config64(CONFIG_CAPACITY),
+        read_only: disk.features & F_RO != 0,
+    })
+}
+
+/// Read `count` sectors starting at `lba` into memory at `addr`.
+pub(crate) fn read(lba: u64, count: u64, addr: usize) -> Result<(), &'static str> {
+    transfer(disk()?, T_IN, lba, count, addr)
+}
+
+/// Write `count` sectors from memory at `addr` to the disk starting at `lba`,
+/// then flush them to the backing store.
+pub(crate) fn write(lba: u64, count: u64, addr: usize) -> Result<(), &'static str> {
+    let disk = disk()?;
+    if disk.features & F_RO != 0 {
+        return Err("disk is read-only");
+    }
+    transfer(disk, T_OUT, lba, count, addr)?;
+    match disk.features & F_FLUSH {
+        0 => Ok(()),
+        _ => request(disk, T_FLUSH, 0, 0, 0),
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The driver state, setting the device up if that has not happened yet.
+fn disk() -> Result<&'static mut Disk, &'static str> {
+    let disk = &raw mut DISK;
+    let disk = unsafe { &mut *disk };
+    if disk.dev.is_some() {
+        return Ok(disk);
+    }
+
+    let dev = Device::find(ID_BLOCK).ok_or("no virtio block device")?;
+    disk.features = dev.init(F_RO | F_FLUSH)?;
+    dev.setup_queue(&mut disk.queue)?;
+    dev.driver_ok();
+    disk.dev = Some(dev);
+    Ok(disk)
+}
+
+// Move `count` sectors between the disk and memory, in chunks the device is
+// sure to accept.
+fn transfer(
+    disk: &mut Disk,
+    kind: u32,
+    lba: u64,
+    count: u64,
+    addr: usize,
+) -> Result<(), &'static str> {
+    let capacity = disk.dev.map_or(0, |dev| dev.config64(CONFIG_CAPACITY));
+    match lba.checked_add(count) {
+        Some(end) if end <= capacity => {}
+        _ => return Err("beyond the end of the disk"),
+    }
+
+    let mut done = 0;
+    while done < count {
+        let n = (count - done).min(MAX_REQUEST_SECTORS);
+        let buf = addr + done as usize * SECTOR_SIZE;
+        request(disk, kind, lba + done, buf, n as usize * SECTOR_SIZE)?;
+        done += n;
+    }
+    Ok(())
+}
+
+// Issue one request and wait for it. `len` bytes at `buf` are the data (none
+// for a flush).
+fn request(
+    disk: &mut Disk,
+    kind: u32,
+    sector: u64,
+    buf: usize,
+    len: usize,
+) -> Result<(), &'static str> {
+    let dev = disk.dev.ok_or("no virtio block device")?;
+    disk.header = Header {
+        kind,
+        reserved: 0,
+        sector,
+    };
+    disk.status = 0xff;
+
+    let header = (
+        (&raw const disk.header) as usize,
+        size_of::<Header>(),
+        false,
+    );
+    let status = ((&raw mut disk.status) as usize, 1, true);
+    let done = match len {
+        0 => disk.queue.submit(&dev, &[header, status]),
+        _ => disk
+            .queue
+            .submit(&dev, &[header, (buf, len, kind == T_IN), status]),
+    };
+    if let Err(e) = done {
+        // The device may still own the chain and finish it later; resetting it
+        // takes the descriptors back, and the next request sets it up again.
+        dev.reset();
+        disk.dev = None;
+        return Err(e);
+    }
+
+    match unsafe { (&raw const disk.status).read_volatile() } {
+        S_OK => Ok(()),
+        S_UNSUPP => Err("request not supported by the device"),
+        _ => Err("I/O error"),
+    }
+}
@@ -0,0 +1,420 @@
+pub(crate) mod blk;
+pub(crate) mod net;
+pub(crate) mod rng;
+
+use crate::{fdt, timer};
+use core::sync::atomic::{Ordering, fence};
+
+// -----------------------------------------------------------------------------
+// Virtio MMIO Transport
+// -----------------------------------------------------------------------------
+
+// QEMU virt has eight virtio-mmio slots, 4 KiB apart. The device tree lists
+// them as `virtio,mmio` nodes; the fixed slots are the fallback without one.
+const MMIO_SLOTS_BASE: usize = 0x1000_1000;
+const MMIO_SLOT_SIZE: usize = 0x1000;
+const MMIO_SLOTS: usize = 8;
+
+// Register offsets (virtio spec 4.2.2, and 4.2.4 for the legacy interface).
+const REG_MAGIC: usize = 0x000;
+const REG_VERSION: usize = 0x004;
+const REG_DEVICE_ID: usize = 0x008;
+const REG_DEVICE_FEATURES: usize = 0x010;
+const REG_DEVICE_FEATURES_SEL: usize = 0x014;
+const REG_DRIVER_FEATURES: usize = 0x020;
+const REG_DRIVER_FEATURES_SEL: usize = 0x024;
+const REG_GUEST_PAGE_SIZE: usize = 0x028;
+const REG_QUEUE_SEL: usize = 0x030;
+const REG_QUEUE_NUM_MAX: usize = 0x034;
+const REG_QUEUE_NUM: usize = 0x038;
+const REG_QUEUE_ALIGN: usize = 0x03c;
+const REG_QUEUE_PFN: usize = 0x040;
+const REG_QUEUE_READY: usize = 0x044;
+const REG_QUEUE_NOTIFY: usize = 0x050;
+const REG_INTERRUPT_STATUS: usize = 0x060;
+const REG_INTERRUPT_ACK: usize = 0x064;
+const REG_STATUS: usize = 0x070;
+const REG_QUEUE_DESC: usize = 0x080;
+const REG_QUEUE_DRIVER: usize = 0x090;
+const REG_QUEUE_DEVICE: usize = 0x0a0;
+const REG_CONFIG: usize = 0x100;
+
+// "virt" in little-endian.
+const MAGIC: u32 = 0x7472_6976;
+
+// Device status bits.
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+const STATUS_FEATURES_OK: u32 = 8;
+
+/// Device IDs.
+pub(crate) const ID_NET: u32 = 1;
+pub(crate) const ID_BLOCK: u32 = 2;
+pub(crate) const ID_RNG: u32 = 4;
+
+// Offered only by (and required from drivers of) non-legacy devices.
+const F_VERSION_1: u64 = 1 << 32;
+
+// Descriptors per virtqueue. Requests are issued one at a time and only a few
+// receive buffers are posted, so a handful is plenty.
+const QUEUE_SIZE: usize = 8;
+
+// Descriptor flags.
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+// Page size (and used ring alignment) of the legacy interface.
+const LEGACY_PAGE_SIZE: usize = 4096;
+
+// How long `Queue::submit` waits for the device.
+const TIMEOUT_MS: u64 = 1000;
+
+/// A virtio-mmio device.
+#[derive(Copy, Clone)]
+pub(crate) struct Device {
+    /// MMIO base address.
+    pub(crate) base: usize,
+    /// Interface version: 1 (legacy, QEMU's default) or 2.
+    pub(crate) version: u32,
+}
+
+impl Device {
+    /// Find the first device with the given device ID.
+    pub(crate) fn find(id: u32) -> Option<Device> {
+        let matches =
+            |base: usize| Device::probe(base).filter(|_| read32(base, REG_DEVICE_ID) == id);
+        match fdt::get() {
+            Some(fdt) => {
+                fdt.search_compatible("virtio,mmio", |node| matches(fdt.reg(node, 0)?.0 as usize))
+            }
+            None => (0..MMIO_SLOTS).find_map(|i| matches(MMIO_SLOTS_BASE + i * MMIO_SLOT_SIZE)),
+        }
+    }
+
+    // A device at `base`, if the slot holds one.
+    fn probe(base: usize) -> Option<Device> {
+        if read32(base, REG_MAGIC) != MAGIC || read32(base, REG_DEVICE_ID) == 0 {
+            return None;
+        }
+        Some(Device {
+            base,
+            version: read32(base, REG_VERSION),
+        })
+    }
+
+    /// Reset the device and negotiate features, accepting those of `wanted` the
+    /// device offers. Returns the accepted set.
+    pub(crate) fn init(&self, wanted: u64) -> Result<u64, &'static str> {
+        if !matches!(self.version, 1 | 2) {
+            return Err("unsupported virtio-mmio version");
+        }
+
+        self.reset();
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE);
+        self.write(REG_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let offered = self.device_features();
+        let mut accepted = offered & wanted;
+        if self.version == 2 {
+            accepted |= offered & F_VERSION_1;
+        }
+        self.write(REG_DRIVER_FEATURES_SEL, 0);
+        self.write(REG_DRIVER_FEATURES, accepted as u32);
+        self.write(REG_DRIVER_FEATURES_SEL, 1);
+        self.write(REG_DRIVER_FEATURES, (accepted >> 32) as u32);
+
+        // Legacy devices have no FEATURES_OK handshake.
+        if self.version == 1 {
+            self.write(REG_GUEST_PAGE_SIZE, LEGACY_PAGE_SIZE as u32);
+            return Ok(accepted);
+        }
+
+        self.set_status(STATUS_FEATURES_OK);
+        if self.read(REG_STATUS) & STATUS_FEATURES_OK == 0 {
+            return Err("device rejected the negotiated features");
+        }
+        Ok(accepted)
+    }
+
+    /// Reset the device, which makes it drop its queues and whatever they
+    /// hold.
+    pub(crate) fn reset(&self) {
+        self.write(REG_STATUS, 0);
+    }
+
+    /// Hand `queue`'s memory to the device as its virtqueue `queue.index`.
+    pub(crate) fn setup_queue(&self, queue: &mut Queue) -> Result<(), &'static str> {
+        self.write(REG_QUEUE_SEL, queue.index as u32);
+        let max = self.read(REG_QUEUE_NUM_MAX) as usize;
+        if max == 0 {
+            return Err("device has no such queue");
+        }
+        if max < QUEUE_SIZE {
+            return Err("device queue too small");
+        }
+
+        queue.reset();
+        self.write(REG_QUEUE_NUM, QUEUE_SIZE as u32);
+        let desc = (&raw const queue.desc) as u64;
+        match self.version {
+            1 => {
+                self.write(REG_QUEUE_ALIGN, LEGACY_PAGE_SIZE as u32);
+                self.write(REG_QUEUE_PFN, (desc / LEGACY_PAGE_SIZE as u64) as u32);
+            }
+            _ => {
+                self.write64(REG_QUEUE_DESC, desc);
+                self.write64(REG_QUEUE_DRIVER, (&raw const queue.avail) as u64);
+                self.write64(REG_QUEUE_DEVICE, (&raw const queue.used) as u64);
+                self.write(REG_QUEUE_READY, 1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tell the device the driver is ready; queues are live from here on.
+    pub(crate) fn driver_ok(&self) {
+        self.set_status(STATUS_DRIVER_OK);
+    }
+
+    /// Read a byte of the device-specific configuration space.
+    pub(crate) fn config8(&self, off: usize) -> u8 {
+        unsafe { ((self.base + REG_CONFIG + off) as *const u8).read_volatile() }
+    }
+
+    /// Read a 32-bit field of the device-specific configuration space.
+    pub(crate) fn config32(&self, off: usize) -> u32 {
+        self.read(REG_CONFIG + off)
+    }
+
+    /// Read a 64-bit field of the device-specific configuration space.
+    pub(crate) fn config64(&self, off: usize) -> u64 {
+        self.config32(off) as u64 | (self.config32(off + 4) as u64) << 32
+    }
+
+    // All 64 feature bits the device offers.
+    fn device_features(&self) -> u64 {
+        self.write(REG_DEVICE_FEATURES_SEL, 0);
+        let low = self.read(REG_DEVICE_FEATURES) as u64;
+        self.write(REG_DEVICE_FEATURES_SEL, 1);
+        low | (self.read(REG_DEVICE_FEATURES) as u64) << 32
+    }
+
+    // Add bits to the device status.
+    fn set_status(&self, bits: u32) {
+        self.write(REG_STATUS, self.read(REG_STATUS) | bits);
+    }
+
+    fn read(&self, off: usize) -> u32 {
+        read32(self.base, off)
+    }
+
+    fn write(&self, off: usize, value: u32) {
+        unsafe { ((self.base + off) as *mut u32).write_volatile(value) };
+    }
+
+    // Write a 64-bit value to a low/high register pair.
+    fn write64(&self, off: usize, value: u64) {
+        self.write(off, value as u32);
+        self.write(off + 4, (value >> 32) as u32);
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Virtqueues
+// -----------------------------------------------------------------------------
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Desc {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+struct Avail {
+    flags: u16,
+    idx: u16,
+    ring: [u16; QUEUE_SIZE],
+    used_event: u16,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+#[repr(C)]
+struct Used {
+    flags: u16,
+    idx: u16,
+    ring: [UsedElem; QUEUE_SIZE],
+    avail_event: u16,
+}
+
+// Padding that puts the used ring on the page after the descriptors and
+// available ring, as the legacy interface requires.
+const USED_PAD: usize =
+    LEGACY_PAGE_SIZE - core::mem::size_of::<[Desc; QUEUE_SIZE]>() - core::mem::size_of::<Avail>();
+
+/// A split virtqueue, driven by polling.
+///
+/// The rings live inside the struct, so it must stay put (in a static) once
+/// handed to a device.
+#[repr(C, align(4096))]
+pub(crate) struct Queue {
+    desc: [Desc; QUEUE_SIZE],
+    avail: Avail,
+    _pad: [u8; USED_PAD],
+    used: Used,
+    // Queue index on the device.
+    index: u16,
+    // Bitmap of free descriptors.
+    free: u16,
+    // The used ring index up to which chains have been collected.
+    last_used: u16,
+}
+
+impl Queue {
+    /// An empty queue, to become the device's virtqueue `index`.
+    pub(crate) const fn new(index: u16) -> Queue {
+        Queue {
+            desc: [Desc {
+                addr: 0,
+                len: 0,
+                flags: 0,
+                next: 0,
+            }; QUEUE_SIZE],
+            avail: Avail {
+                flags: 0,
+                idx: 0,
+                ring: [0; QUEUE_SIZE],
+                used_event: 0,
+            },
+            _pad: [0; USED_PAD],
+            used: Used {
+                flags: 0,
+                idx: 0,
+                ring: [UsedElem { id: 0, len: 0 }; QUEUE_SIZE],
+                avail_event: 0,
+            },
+            index,
+            free: 0,
+            last_used: 0,
+        }
+    }
+
+    // Forget all posted chains (the device has just been reset).
+    fn reset(&mut self) {
+        unsafe {
+            (&raw mut self.avail.idx).write_volatile(0);
+            (&raw mut self.used.idx).write_volatile(0);
+        }
+        self.free = ((1u32 << QUEUE_SIZE) - 1) as u16;
+        self.last_used = 0;
+    }
+
+    /// Post a chain of buffers `(addr, len, device_writes)` and notify the
+    /// device. Returns the chain's head descriptor, or None if too few
+    /// descriptors are free.
+    pub(crate) fn push(&mut self, dev: &Device, bufs: &[(usize, usize, bool)]) -> Option<u16> {
+        if bufs.is_empty() || bufs.len() > self.free.count_ones() as usize {
+            return None;
+        }
+
+        // Fill the chain back to front so each descriptor knows its successor.
+        let mut next = 0u16;
+        for (i, &(addr, len, device_writes)) in bufs.iter().enumerate().rev() {
+            let d = self.free.trailing_zeros() as u16;
+            self.free &= !(1 << d);
+            let mut flags = if device_writes { DESC_F_WRITE } else { 0 };
+            if i + 1 < bufs.len() {
+                flags |= DESC_F_NEXT;
+            }
+            self.desc[d as usize] = Desc {
+                addr: addr as u64,
+                len: len as u32,
+                flags,
+                next,
+            };
+            next = d;
+        }
+
+        let idx = unsafe { (&raw const self.avail.idx).read_volatile() };
+        self.avail.ring[idx as usize % QUEUE_SIZE] = next;
+        // The descriptors must be visible before the index, and the index
+        // before the notification.
+        fence(Ordering::SeqCst);
+        unsafe { (&raw mut self.avail.idx).write_volatile(idx.wrapping_add(1)) };
+        fence(Ordering::SeqCst);
+        dev.write(REG_QUEUE_NOTIFY, self.index as u32);
+        Some(next)
+    }
+
+    /// Collect the next chain the device has finished with, freeing its
+    /// descriptors. Returns its head and the number of bytes the device wrote.
+    pub(crate) fn pop_used(&mut self, dev: &Device) -> Option<(u16, u32)> {
+        let idx = unsafe { (&raw const self.used.idx).read_volatile() };
+        if idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+
+        let elem = unsafe {
+            (&raw const self.used.ring[self.last_used as usize % QUEUE_SIZE]).read_volatile()
+        };
+        self.last_used = self.last_used.wrapping_add(1);
+
+        let mut d = elem.id as usize % QUEUE_SIZE;
+        loop {
+            self.free |= 1 << d;
+            if self.desc[d].flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            d = self.desc[d].next as usize % QUEUE_SIZE;
+        }
+
+        // Nothing takes virtio interrupts, but acknowledge them anyway so the
+        // line does not stay raised.
+        dev.write(REG_INTERRUPT_ACK, dev.read(REG_INTERRUPT_STATUS));
+        Some((elem.id as u16, elem.len))
+    }
+
+    /// The address of the first buffer of the chain at `head`. It stays
+    /// readable after `pop_used` has freed the chain.
+    pub(crate) fn buffer_addr(&self, head: u16) -> usize {
+        self.desc[head as usize % QUEUE_SIZE].addr as usize
+    }
+
+    /// Post a chain and wait for the device to finish with it. Returns the
+    /// number of bytes the device wrote.
+    pub(crate) fn submit(
+        &mut self,
+        dev: &Device,
+        bufs: &[(usize, usize, bool)],
+    ) -> Result<u32, &'static str> {
+        let head = self.push(dev, bufs).ok_or("virtqueue full")?;
+        let deadline = timer::now() + timer::ms_to_ticks(TIMEOUT_MS);
+        loop {
+            match self.pop_used(dev) {
+                Some((id, len)) if id == head => return Ok(len),
+                Some(_) => {}
+                None if timer::now() >= deadline => return Err("device timed out"),
+                None => core::hint::spin_loop(),
+            }
+        }
+    }
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// Read a 32-bit register of the device at `base`.
+fn read32(base: usize, off: usize) -> u32 {
+    unsafe { ((base + off) as *const u32).read_volatile() }
+}
@@ -0,0 +1,152 @@
+use super::{Device, ID_NET, Queue};
+
+// -----------------------------------------------------------------------------
+// Virtio Network Device
+// -----------------------------------------------------------------------------
+
+// The first network device found is used, set up on first use (and after a
+// timed-out send) like the block device. Frames are plain Ethernet frames; no
+// offloads are negotiated.
+
+/// Largest Ethernet frame (without FCS) sent or received.
+pub(crate) const FRAME_SIZE: usize = 1514;
+
+// Queue indices.
+const RX: u16 = 0;
+const TX: u16 = 1;
+
+// Feature bits.
+const F_MAC: u64 = 1 << 5;
+
+// Offset of the MAC address in the configuration space.
+const CONFIG_MAC: usize = 0;
+
+// Size of the header preceding each frame: 10 bytes for legacy devices, 12
+// (with `num_buffers`) otherwise.
+const HEADER_LEGACY: usize = 10;
+const HEADER_MAX: usize = 12;
+
+// Receive buffers kept posted; each takes two descriptors (header, frame).
+const RX_BUFFERS: usize = 4;
+
+// Address used if the device has none of its own (QEMU's default).
+const DEFAULT_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+// Driver state. Only touched from the REPL.
+struct Nic {
+    dev: Option<Device>,
+    header_len: usize,
+    mac: [u8; 6],
+    rx: Queue,
+    tx: Queue,
+    // Head descriptor of each posted receive buffer.
+    rx_heads: [u16; RX_BUFFERS],
+    rx_headers: [[u8; HEADER_MAX]; RX_BUFFERS],
+    rx_frames: [[u8; FRAME_SIZE]; RX_BUFFERS],
+    tx_header: [u8; HEADER_MAX],
+}
+
+static mut NIC: Nic = Nic {
+    dev: None,
+    header_len: 0,
+    mac: [0; 6],
+    rx: Queue::new(RX),
+    tx: Queue::new(TX),
+    rx_heads: [0; RX_BUFFERS],
+    rx_headers: [[0; HEADER_MAX]; RX_BUFFERS],
+    rx_frames: [[0; FRAME_SIZE]; RX_BUFFERS],
+    tx_header: [0; HEADER_MAX],
+};
+
+/// The device's MAC address.
+pub(crate) fn mac() -> Result<[u8; 6], &'static str> {
+    Ok(nic()?.mac)
+}
+
+/// Transmit one Ethernet frame, waiting until the device has taken it.
+pub(crate) fn send(frame: &[u8]) -> Result<(), &'static str> {
+    let nic = nic()?;
+    let dev = nic.dev.ok_or("no virtio network device")?;
+    let header = ((&raw const nic.tx_header) as usize, nic.header_len, false);
+    let sent = nic.tx.submit(
+        &dev,
+        &[header, (frame.as_ptr() as usize, frame.len(), false)],
+    );
+    if let Err(e) = sent {
+        // As for the block device: take the chain back by resetting, and set
+        // the device (and its receive buffers) up again on next use.
+        dev.reset();
+        nic.dev = None;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Take the next received frame, if any, and pass it to `f`.
+pub(crate) fn recv<T>(f: impl FnOnce(&[u8]) -> T) -> Result<Option<T>, &'static str> {
+    let nic = nic()?;
+    let dev = nic.dev.ok_or("no virtio network device")?;
+    let Some((head, len)) = nic.rx.pop_used(&dev) else {
+        return Ok(None);
+    };
+    let Some(i) = nic.rx_heads.iter().position(|&h| h == head) else {
+        // Still hand back whichever of ours it was, so receiving does not
+        // slowly run out of buffers.
+        let addr = nic.rx.buffer_addr(head);
+        if let Some(i) = (0..RX_BUFFERS).find(|&i| (&raw const nic.rx_headers[i]) as usize == addr)
+        {
+            post(nic, &dev, i);
+        }
+        return Err("unexpected receive buffer");
+    };
+
+    let len = (len as usize)
+        .saturating_sub(nic.header_len)
+        .min(FRAME_SIZE);
+    let v = f(&nic.rx_frames[i][..len]);
+    post(nic, &dev, i);
+    Ok(Some(v))
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The driver state, setting the device up if that has not happened yet.
+fn nic() -> Result<&'static mut Nic, &'static str> {
+    let nic = &raw mut NIC;
+    let nic = unsafe { &mut *nic };
+    if nic.dev.is_some() {
+        return Ok(nic);
+    }
+
+    let dev = Device::find(ID_NET).ok_or("no virtio network device")?;
+    let features = dev.init(F_MAC)?;
+    dev.setup_queue(&mut nic.rx)?;
+    dev.setup_queue(&mut nic.tx)?;
+    nic.header_len = match dev.version {
+        1 => HEADER_LEGACY,
+        _ => HEADER_MAX,
+    };
+    nic.mac = match features & F_MAC {
+        0 => DEFAULT_MAC,
+        _ => core::array::from_fn(|i| dev.config8(CONFIG_MAC + i)),
+    };
+    dev.driver_ok();
+
+    for i in 0..RX_BUFFERS {
+        post(nic, &dev, i);
+    }
+    nic.dev = Some(dev);
+    Ok(nic)
+}
+
+// Hand receive buffer `i` to the device.
+fn post(nic: &mut Nic, dev: &Device, i: usize) {
+    let header = ((&raw mut nic.rx_headers[i]) as usize, nic.header_len, true);
+    let frame = ((&raw mut nic.rx_frames[i]) as usize, FRAME_SIZE, true);
+    // There are exactly enough descriptors for every buffer.
+    if let Some(head) = nic.rx.push(dev, &[header, frame]) {
+        nic.rx_heads[i] = head;
+    }
+}
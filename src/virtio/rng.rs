@@ -0,0 +1,71 @@
+use super::{Device, ID_RNG, Queue};
+
+// -----------------------------------------------------------------------------
+// Virtio Entropy Device
+// -----------------------------------------------------------------------------
+
+// One queue of device-writable buffers, which the device fills with random
+// bytes from the host. It may return fewer bytes than asked for.
+
+// Most bytes asked for in one request.
+const MAX_REQUEST: usize = 64 * 1024;
+
+// Driver state. Only touched from the REPL, one request at a time.
+struct Rng {
+    dev: Option<Device>,
+    queue: Queue,
+}
+
+static mut RNG: Rng = Rng {
+    dev: None,
+    queue: Queue::new(0),
+};
+
+/// Whether there is an entropy device (setting it up if needed).
+pub(crate) fn available() -> bool {
+    rng().is_ok()
+}
+
+/// Fill `len` bytes of memory at `addr` with random bytes from the device.
+pub(crate) fn fill(addr: usize, len: usize) -> Result<(), &'static str> {
+    let rng = rng()?;
+    let dev = rng.dev.ok_or("no virtio entropy device")?;
+
+    let mut done = 0;
+    while done < len {
+        let n = (len - done).min(MAX_REQUEST);
+        match rng.queue.submit(&dev, &[(addr + done, n, true)]) {
+            Ok(0) => return Err("device returned no entropy"),
+            Ok(got) => done += (got as usize).min(n),
+            Err(e) => {
+                // The device may still own the buffer and fill it later;
+                // resetting it takes the buffer back, and the next request
+                // sets it up again.
+                dev.reset();
+                rng.dev = None;
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
+
+// -----------------------------------------------------------------------------
+// Helpers
+// -----------------------------------------------------------------------------
+
+// The driver state, setting the device up if that has not happened yet.
+fn rng() -> Result<&'static mut Rng, &'static str> {
+    let rng = &raw mut RNG;
+    let rng = unsafe { &mut *rng };
+    if rng.dev.is_some() {
+        return Ok(rng);
+    }
+
+    let dev = Device::find(ID_RNG).ok_or("no virtio entropy device")?;
+    dev.init(0)?;
+    dev.setup_queue(&mut rng.queue)?;
+    dev.driver_ok();
+    rng.dev = Some(dev);
+    Ok(rng)
+}
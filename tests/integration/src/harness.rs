@@ -25,6 +25,11 @@ pub struct QemuHarness {
 impl QemuHarness {
     /// Spawn QEMU with the given kernel binary and wait for the first `"> "` prompt.
     pub fn spawn(kernel: &str) -> Self {
+        Self::spawn_with_args(kernel, &[])
+    }
+
+    /// Like `spawn`, passing extra arguments to QEMU (e.g. to attach devices).
+    pub fn spawn_with_args(kernel: &str, extra: &[&str]) -> Self {
         let mut child = Command::new("qemu-system-riscv64")
             .args([
                 "-machine",
@@ -35,6 +40,7 @@ impl QemuHarness {
                 "-kernel",
                 kernel,
             ])
+            .args(extra)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::null())
@@ -84,6 +90,20 @@ impl QemuHarness {
         self.wait_for_prompt()
     }
 
+    /// Wait for QEMU to exit on its own and return its exit status
+    pub fn wait_exit(&mut self) -> Option<i32> {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().expect("failed to poll QEMU") {
+                return status.code();
+            }
+            if start.elapsed() >= TIMEOUT {
+                panic!("timed out waiting for QEMU to exit after {:?}", TIMEOUT);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
     /// Kill the VM
     pub fn kill(&mut self) {
         let _ = self.child.kill();
@@ -98,3 +98,1010 @@ fn test_unknown_command() {
         "expected 'unknown command' in output, got:\n{out}"
     );
 }
+
+#[test]
+fn test_break_and_continue() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing an infinite loop (j .) at 80200000");
+    q.send("80200000: 6f 00 00 00");
+    let _write_out = q.receive();
+    println!("jumping to the loop and breaking in with Ctrl+C");
+    q.send("jump 80200000");
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    q.send("\x03");
+    let out = q.receive();
+    println!("checking the monitor reports where the program stopped");
+    assert!(
+        out.contains("break at 80200000"),
+        "expected 'break at 80200000' in output, got:\n{out}"
+    );
+    println!("checking 'regs' shows the stopped pc");
+    q.send("regs");
+    let regs_out = q.receive();
+    assert!(
+        regs_out.contains("0000000080200000"),
+        "expected pc 0000000080200000 in regs output, got:\n{regs_out}"
+    );
+}
+
+#[test]
+fn test_jump_timeout() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing an infinite loop (j .) at 80200000");
+    q.send("80200000: 6f 00 00 00");
+    let _write_out = q.receive();
+    println!("jumping to the loop with a 100 ms watchdog");
+    q.send("jump 80200000 timeout 100");
+    let out = q.receive();
+    println!("checking the watchdog stopped the program");
+    assert!(
+        out.contains("timeout: program stopped at 80200000"),
+        "expected watchdog report in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_baud_default() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("sending 'baud' to query the programmed baud rate");
+    q.send("baud");
+    let out = q.receive();
+    println!("checking output reports the default rate");
+    assert!(
+        out.contains("baud: 115200"),
+        "expected 'baud: 115200' in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_info_console() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("sending 'info' command");
+    q.send("info");
+    let out = q.receive();
+    println!("checking the 16550 was picked from the device tree stdout-path");
+    assert!(
+        out.contains("console: ns16550 @ 10000000"),
+        "expected 'console: ns16550 @ 10000000' in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_boot_supervisor_payload() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing a payload that prints 'K' via SBI DBCN, then requests a reboot");
+    q.send("80200000: b7 48 42 44 9b 88 e8 34 13 08 20 00 13 05 b0 04 73 00 00 00");
+    let _write_out = q.receive();
+    q.send("80200014: b7 58 52 53 9b 88 48 35 13 08 00 00 13 05 10 00 93 05 00 00 73 00 00 00");
+    let _write_out = q.receive();
+    println!("booting the payload in supervisor mode");
+    q.send("boot 80200000");
+    let out = q.receive();
+    println!("checking the console call was serviced");
+    assert!(
+        out.contains("\nK"),
+        "expected payload output 'K' in output, got:\n{out}"
+    );
+    println!("checking the reboot request returned to the monitor");
+    assert!(
+        out.contains("payload requested a reboot"),
+        "expected reboot report in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_ujump_sandbox() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing a MONITOR service call (li a7, 7; ecall) at 80200000");
+    q.send("80200000: 93 08 70 00 73 00 00 00");
+    let _write_out = q.receive();
+    println!("running it in user mode");
+    q.send("ujump 80200000");
+    let out = q.receive();
+    println!("checking the ecall trapped back to the monitor");
+    assert!(
+        out.contains("ecall at 80200004"),
+        "expected 'ecall at 80200004' in output, got:\n{out}"
+    );
+    println!("writing code that loads from riscmon's image at 80000000");
+    q.send("80200000: 17 05 e0 ff 83 35 05 00");
+    let _write_out = q.receive();
+    println!("running it in user mode");
+    q.send("ujump 80200000");
+    let out = q.receive();
+    println!("checking PMP stopped the load and the faulting address is reported");
+    assert!(
+        out.contains("fault: load access fault at 80200004")
+            && out.contains("tval 0000000080000000"),
+        "expected load access fault report in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_getc_honours_watchdog() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing a program that waits in GETC (li a7, 2; ecall)");
+    q.send("80200000: 93 08 20 00 73 00 00 00");
+    let _write_out = q.receive();
+    println!("jumping to it with a watchdog");
+    q.send("jump 80200000 timeout 200");
+    let out = q.receive();
+    println!("checking the watchdog stopped the program inside GETC");
+    assert!(
+        out.contains("timeout: program stopped at 80200004"),
+        "expected a timeout at the GETC ecall, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_service_calls() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing a program that prints 'H' with PUTC and exits with code 3");
+    q.send("80200000: 93 08 10 00 13 05 80 04 73 00 00 00 93 08 60 00 13 05 30 00 73 00 00 00");
+    let _write_out = q.receive();
+    println!("jumping to the program");
+    q.send("jump 80200000");
+    let out = q.receive();
+    println!("checking the PUTC call was serviced");
+    assert!(
+        out.contains("\nH"),
+        "expected program output 'H' in output, got:\n{out}"
+    );
+    println!("checking the EXIT call reported its code");
+    assert!(
+        out.contains("program exited with code 3"),
+        "expected 'program exited with code 3' in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_exit_code() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("sending 'exit 3'");
+    q.send("exit 3");
+    println!("checking QEMU exits with status 3");
+    let status = q.wait_exit();
+    assert_eq!(
+        status,
+        Some(3),
+        "expected QEMU exit status 3, got {status:?}"
+    );
+}
+
+#[test]
+fn test_reboot() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("sending 'reboot'");
+    q.send("reboot");
+    let _out = q.receive();
+    println!("checking the monitor came back up after the reset");
+    q.send("@");
+    let out = q.receive();
+    assert!(
+        out.contains("80000000"),
+        "expected the reset default address 80000000 in output, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_page_table_map_and_walk() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("creating an Sv39 page table");
+    q.send("pt new sv39");
+    let out = q.receive();
+    assert!(
+        out.contains("page table: sv39"),
+        "expected 'page table: sv39' in output, got:\n{out}"
+    );
+    println!("identity-mapping 2 MiB at 80200000");
+    q.send("pt map 80200000 80200000 200000 rwxad");
+    let out = q.receive();
+    assert!(
+        out.contains("mapped 1 page(s)"),
+        "expected a single 2 MiB page to be mapped, got:\n{out}"
+    );
+    println!("walking an address inside the mapping");
+    q.send("pt walk 80201234");
+    let out = q.receive();
+    assert!(
+        out.contains("leaf rwx--ad") && out.contains("-> pa 0000000080201234"),
+        "expected the walk to reach the leaf and translate, got:\n{out}"
+    );
+    println!("dumping the mappings");
+    q.send("pt dump");
+    let out = q.receive();
+    assert!(
+        out.contains("va 0000000080200000 -> pa 0000000080200000  2M rwx--ad"),
+        "expected the 2 MiB mapping in the dump, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_ujump_translated_puts() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("mapping code at va 10000 and data at va 11000 into a 1 MiB window");
+    q.send("pt new sv39 80280000");
+    q.receive();
+    q.send("pt map 10000 80200000 1000 rxuad");
+    q.receive();
+    q.send("pt map 11000 80201000 1000 ruad");
+    q.receive();
+    q.send("pt satp on");
+    q.receive();
+    println!("writing 'Hi' and a program that PUTSes it from va 11000, then stops");
+    q.send("80201000: 48 69");
+    q.receive();
+    q.send("80200000: 37 15 01 00 93 05 20 00 93 08 30 00 73 00 00 00 93 08 70 00 73 00 00 00");
+    q.receive();
+    q.send("ujump 10000 80200000+100000");
+    let out = q.receive();
+    println!("checking PUTS read the buffer through the page tables");
+    assert!(
+        out.contains("Hi") && out.contains("ecall at 00010014"),
+        "expected 'Hi' from the translated buffer, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_pmp_set_and_show() {
+    println!("starting QEMU");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("listing the PMP entries");
+    q.send("pmp");
+    let out = q.receive();
+    assert!(
+        out.contains("pmp0:") && out.contains("entries implemented"),
+        "expected the PMP entries to be listed, got:\n{out}"
+    );
+    println!("protecting a 4 KiB range with entry 2");
+    q.send("pmp set 2 80400000+1000 r");
+    let out = q.receive();
+    assert!(
+        out.contains("NAPOT r---  addr 00000000201001ff  0000000080400000..0000000080400fff"),
+        "expected entry 2 to decode to the NAPOT range, got:\n{out}"
+    );
+    println!("rejecting a range that cannot be NAPOT-encoded");
+    q.send("pmp set 3 80400000+3000 r");
+    let out = q.receive();
+    assert!(
+        out.contains("error: range must be"),
+        "expected the unencodable range to be rejected, got:\n{out}"
+    );
+    println!("turning entry 2 off");
+    q.send("pmp set 2 off");
+    let out = q.receive();
+    assert!(
+        out.contains("pmp2:  OFF"),
+        "expected entry 2 to be off, got:\n{out}"
+    );
+    println!("refusing to lock an entry boot/ujump reprogram");
+    q.send("pmp set 1 80400000+1000 rwxl");
+    let out = q.receive();
+    assert!(
+        out.contains("cannot be locked"),
+        "expected locking entry 1 to be refused, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_blk_read_and_write() {
+    println!("creating a 64 KiB disk image with a marker in sector 1");
+    let image = std::env::temp_dir().join(format!("riscmon-blk-{}.img", std::process::id()));
+    let mut disk = vec![0u8; 64 * 1024];
+    disk[512..520].copy_from_slice(b"riscmon!");
+    std::fs::write(&image, &disk).expect("failed to create disk image");
+
+    println!("starting QEMU with the image attached");
+    let drive = format!("file={},format=raw,if=none,id=hd0", image.display());
+    let mut q = QemuHarness::spawn_with_args(
+        &kernel_path(),
+        &["-drive", &drive, "-device", "virtio-blk-device,drive=hd0"],
+    );
+    println!("checking the disk is found");
+    q.send("blk info");
+    let out = q.receive();
+    assert!(
+        out.contains("capacity: 128 sectors"),
+        "expected a 128-sector disk, got:\n{out}"
+    );
+    println!("reading sector 1 into memory");
+    q.send("blk read 1 1 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains("read 1 sector(s)"),
+        "expected the read to succeed, got:\n{out}"
+    );
+    q.send("80400000+8.as_str");
+    let out = q.receive();
+    assert!(
+        out.contains("riscmon!"),
+        "expected the marker in memory, got:\n{out}"
+    );
+    println!("writing memory back to sector 2");
+    q.send("80400000: de ad be ef");
+    let _ = q.receive();
+    q.send("blk write 2 1 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains("wrote 1 sector(s)"),
+        "expected the write to succeed, got:\n{out}"
+    );
+    q.kill();
+
+    println!("checking sector 2 in the image");
+    let disk = std::fs::read(&image).expect("failed to read disk image");
+    let _ = std::fs::remove_file(&image);
+    assert_eq!(&disk[1024..1032], b"\xde\xad\xbe\xefmon!");
+}
+
+#[test]
+fn test_tftp_download() {
+    println!("creating a TFTP directory with a 3000-byte file");
+    let dir = std::env::temp_dir().join(format!("riscmon-tftp-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create TFTP directory");
+    let data: Vec<u8> = (0..3000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(dir.join("payload.bin"), &data).expect("failed to write TFTP file");
+
+    println!("starting QEMU with user-mode networking serving it");
+    let netdev = format!("user,id=net0,tftp={}", dir.display());
+    let mut q = QemuHarness::spawn_with_args(
+        &kernel_path(),
+        &[
+            "-netdev",
+            &netdev,
+            "-device",
+            "virtio-net-device,netdev=net0",
+        ],
+    );
+    println!("downloading the file");
+    q.send("tftp 80400000 payload.bin");
+    let out = q.receive();
+    assert!(
+        out.contains("loaded 3000 bytes to 80400000..80400bb7"),
+        "expected the download to succeed, got:\n{out}"
+    );
+    println!("checking the bytes landed in memory");
+    q.send("80400bb4+4");
+    let out = q.receive();
+    assert!(
+        out.contains("80400bb4: eb ec ed ee"),
+        "expected the end of the file in memory, got:\n{out}"
+    );
+    println!("asking for a file that does not exist");
+    q.send("tftp 80400000 missing.bin");
+    let out = q.receive();
+    assert!(
+        out.contains("error: file not found"),
+        "expected the server's error, got:\n{out}"
+    );
+    q.kill();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_fwcfg_list_and_load() {
+    println!("creating a file to pass in through fw_cfg");
+    let file = std::env::temp_dir().join(format!("riscmon-fwcfg-{}.bin", std::process::id()));
+    std::fs::write(&file, b"hello from fw_cfg").expect("failed to write fw_cfg file");
+
+    println!("starting QEMU with the file attached");
+    let fw_cfg = format!("name=opt/riscmon/test.bin,file={}", file.display());
+    let mut q = QemuHarness::spawn_with_args(&kernel_path(), &["-fw_cfg", &fw_cfg]);
+    println!("listing the fw_cfg files");
+    q.send("fwcfg list");
+    let out = q.receive();
+    assert!(
+        out.contains("00000011  opt/riscmon/test.bin"),
+        "expected the 17-byte file in the listing, got:\n{out}"
+    );
+    println!("loading it into memory");
+    q.send("fwcfg load opt/riscmon/test.bin 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains("loaded 17 bytes to 80400000..80400010"),
+        "expected the load to succeed, got:\n{out}"
+    );
+    q.send("80400000+11.as_str");
+    let out = q.receive();
+    assert!(
+        out.contains("hello from fw_cfg"),
+        "expected the file contents in memory, got:\n{out}"
+    );
+    println!("asking for a file that does not exist");
+    q.send("fwcfg load opt/missing 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains("error: no such file"),
+        "expected a missing-file error, got:\n{out}"
+    );
+    q.kill();
+    let _ = std::fs::remove_file(&file);
+}
+
+#[test]
+fn test_semihosting_files_and_exit() {
+    let dir = std::env::temp_dir().join(format!("riscmon-sh-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("failed to create host directory");
+    let input = dir.join("in.bin");
+    let output = dir.join("out.bin");
+    std::fs::write(&input, b"from the host").expect("failed to write host file");
+
+    println!("starting QEMU with semihosting enabled");
+    let mut q = QemuHarness::spawn_with_args(
+        &kernel_path(),
+        &["-semihosting-config", "enable=on,target=native"],
+    );
+    println!("loading a host file");
+    q.send(&format!("sh load {} 80400000", input.display()));
+    let out = q.receive();
+    assert!(
+        out.contains("loaded 13 bytes to 80400000..8040000c"),
+        "expected the load to succeed, got:\n{out}"
+    );
+    println!("saving memory to a host file");
+    q.send("80400000: 52 49 53 43");
+    let _ = q.receive();
+    q.send(&format!("sh save {} 80400000 d", output.display()));
+    let out = q.receive();
+    assert!(
+        out.contains("saved 13 bytes from 80400000..8040000c"),
+        "expected the save to succeed, got:\n{out}"
+    );
+    assert_eq!(
+        std::fs::read(&output).expect("failed to read saved file"),
+        b"RISC the host"
+    );
+    println!("exiting with status 5");
+    q.send("sh exit 5");
+    assert_eq!(q.wait_exit(), Some(5));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    println!("starting QEMU without semihosting");
+    let mut q = QemuHarness::spawn(&kernel_path());
+    q.send("sh exit 5");
+    let out = q.receive();
+    assert!(
+        out.contains("error: semihosting not enabled"),
+        "expected semihosting to be reported missing, got:\n{out}"
+    );
+}
+
+#[test]
+fn test_pci_list_assign_and_config() {
+    println!("starting QEMU with a PCI device");
+    let mut q = QemuHarness::spawn_with_args(&kernel_path(), &["-device", "virtio-rng-pci"]);
+    println!("listing PCI functions before assignment");
+    q.send("lspci");
+    let out = q.receive();
+    assert!(
+        out.contains("00:00.0 1b36:0008 060000 bridge"),
+        "expected the host bridge, got:\n{out}"
+    );
+    assert!(
+        out.contains("00:01.0 1af4:1005"),
+        "expected the virtio-rng device, got:\n{out}"
+    );
+    assert!(
+        out.contains("unassigned"),
+        "expected unassigned BARs, got:\n{out}"
+    );
+    println!("assigning BARs");
+    q.send("pci assign");
+    let out = q.receive();
+    assert!(
+        out.contains("assigned 3 BAR(s), buses 00..00"),
+        "expected three BARs assigned, got:\n{out}"
+    );
+    q.send("lspci");
+    let out = q.receive();
+    assert!(
+        out.contains("bar1 mem32 40000000  size 4 KiB"),
+        "expected BAR1 at the start of the memory window, got:\n{out}"
+    );
+    println!("reading and writing config space");
+    q.send("pci read 00:01.0 0");
+    let out = q.receive();
+    assert!(out.contains("10051af4"), "expected the IDs, got:\n{out}");
+    q.send("pci read 00:01.0 4 h");
+    let out = q.receive();
+    assert!(
+        out.contains("0007"),
+        "expected decoding enabled, got:\n{out}"
+    );
+    q.send("pci write 00:01.0 3c 5a b");
+    let out = q.receive();
+    assert!(
+        out.contains("5a"),
+        "expected the interrupt line to stick, got:\n{out}"
+    );
+    q.send("pci read 00:01.0 3 w");
+    let out = q.receive();
+    assert!(
+        out.contains("error: offset must be below 1000 (hex) and aligned"),
+        "expected an alignment error, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_date_show_and_set() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("reading the RTC");
+    q.send("date");
+    let out = q.receive();
+    let line = out
+        .lines()
+        .find(|l| l.ends_with('Z'))
+        .unwrap_or_else(|| panic!("expected an ISO 8601 timestamp, got:\n{out}"));
+    let year: u32 = line[..4].parse().expect("expected a year");
+    assert!(year >= 2024, "expected the host's date, got:\n{out}");
+    assert_eq!(
+        &line[4..5],
+        "-",
+        "expected an ISO 8601 timestamp, got:\n{out}"
+    );
+    assert_eq!(
+        &line[10..11],
+        "T",
+        "expected an ISO 8601 timestamp, got:\n{out}"
+    );
+    println!("setting the RTC");
+    q.send("date set 2030-02-03T04:05:06Z");
+    let out = q.receive();
+    assert!(
+        out.contains("2030-02-03T04:05:0"),
+        "expected the new date, got:\n{out}"
+    );
+    println!("rejecting an impossible date");
+    q.send("date set 2031-02-29T00:00:00Z");
+    let out = q.receive();
+    assert!(
+        out.contains("error: invalid date"),
+        "expected an invalid-date error, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_rand_device_and_seeded_fills() {
+    println!("starting QEMU with a virtio-rng device");
+    let mut q = QemuHarness::spawn_with_args(&kernel_path(), &["-device", "virtio-rng-device"]);
+    println!("filling from the device");
+    q.send("rand 80400000 804000ff");
+    let out = q.receive();
+    assert!(
+        out.contains("filled 256 bytes at 80400000..804000ff from virtio-rng"),
+        "expected a device fill, got:\n{out}"
+    );
+    println!("filling twice from the same seed");
+    let mut fills = Vec::new();
+    for _ in 0..2 {
+        q.send("rand seed 1234");
+        q.receive();
+        q.send("rand 80400000 8040000f");
+        let out = q.receive();
+        assert!(
+            out.contains("from the seeded PRNG"),
+            "expected a PRNG fill, got:\n{out}"
+        );
+        q.send("80400000.8040000f");
+        let out = q.receive();
+        let line = out
+            .lines()
+            .find(|l| l.starts_with("80400000:"))
+            .unwrap_or_else(|| panic!("expected a dump, got:\n{out}"))
+            .to_string();
+        fills.push(line);
+    }
+    assert_eq!(
+        fills[0], fills[1],
+        "expected the same bytes from the same seed"
+    );
+    println!("refusing to fill riscmon's memory");
+    q.send("rand 80000000 800000ff");
+    let out = q.receive();
+    assert!(
+        out.contains("error: fill of riscmon's memory not allowed"),
+        "expected a refusal, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_irq_enable_and_list() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("listing PLIC sources");
+    q.send("irq");
+    let out = q.receive();
+    assert!(
+        out.contains("plic @ 0c000000, 95 sources, context 0 (hart 0, machine), threshold 0"),
+        "expected the PLIC and riscmon's context, got:\n{out}"
+    );
+    println!("enabling a source");
+    q.send("irq enable 5 3");
+    q.receive();
+    q.send("irq");
+    let out = q.receive();
+    assert!(
+        out.contains("source 5: priority 3, enabled, 0 claimed"),
+        "expected source 5 enabled, got:\n{out}"
+    );
+    println!("disabling it again");
+    q.send("irq disable 5");
+    q.receive();
+    q.send("irq");
+    let out = q.receive();
+    assert!(
+        out.contains("source 5: priority 3, disabled"),
+        "expected source 5 disabled, got:\n{out}"
+    );
+    println!("rejecting bad arguments");
+    q.send("irq enable 96");
+    let out = q.receive();
+    assert!(
+        out.contains("error: no such source (1..95)"),
+        "expected a range error, got:\n{out}"
+    );
+    q.send("irq threshold 8");
+    let out = q.receive();
+    assert!(
+        out.contains("error: threshold out of range"),
+        "expected a threshold error, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_memtest_passes_and_refusals() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("running every test once over 1 MiB");
+    q.send("memtest 80400000 804fffff");
+    let out = q.receive();
+    assert!(
+        out.contains("pass 1: walk ok addr ok march ok fill ok"),
+        "expected all tests to pass, got:\n{out}"
+    );
+    assert!(
+        out.contains("memtest passed (1 pass(es))"),
+        "expected a summary, got:\n{out}"
+    );
+    println!("running March C- three times");
+    q.send("memtest 80400000 80400fff march 3");
+    let out = q.receive();
+    assert!(
+        out.contains("pass 3: march ok") && out.contains("memtest passed (3 pass(es))"),
+        "expected three March C- passes, got:\n{out}"
+    );
+    println!("filling with a given pattern");
+    q.send("memtest 80400000 80400fff 1234abcd fill");
+    let out = q.receive();
+    assert!(
+        out.contains("pass 1: fill ok"),
+        "expected the fill to pass, got:\n{out}"
+    );
+    q.send("80400ff8+8");
+    let out = q.receive();
+    assert!(
+        out.contains("80400ff8: cd ab 34 12 00 00 00 00"),
+        "expected the pattern left in memory, got:\n{out}"
+    );
+    q.send("memtest 80400000 80400fff zz");
+    let out = q.receive();
+    assert!(
+        out.contains("error: invalid pattern"),
+        "expected a pattern error, got:\n{out}"
+    );
+    println!("refusing riscmon's memory and unaligned ranges");
+    q.send("memtest 80000000 80000fff");
+    let out = q.receive();
+    assert!(
+        out.contains("error: test of riscmon's memory not allowed"),
+        "expected a refusal, got:\n{out}"
+    );
+    q.send("memtest 80400001 80400fff");
+    let out = q.receive();
+    assert!(
+        out.contains("error: START and END+1 must be 8-byte aligned"),
+        "expected an alignment error, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_checksums_of_known_bytes() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("writing \"123456789\"");
+    q.send("80400000: 31 32 33 34 35 36 37 38 39");
+    q.receive();
+    for (cmd, digest) in [
+        ("crc32", "cbf43926"),
+        ("sum", "000001dd"),
+        ("adler32", "091e01de"),
+        (
+            "sha256",
+            "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225",
+        ),
+    ] {
+        println!("checking {cmd}");
+        q.send(&format!("{cmd} 80400000 80400008"));
+        let out = q.receive();
+        let expected = format!("{cmd} 80400000..80400008 (9 bytes): {digest}");
+        assert!(
+            out.contains(&expected),
+            "expected {expected:?}, got:\n{out}"
+        );
+    }
+    println!("checksumming more than a dump allows");
+    q.send("crc32 80400000 804fffff");
+    let out = q.receive();
+    assert!(
+        out.contains("crc32 80400000..804fffff (1048576 bytes): "),
+        "expected a 1 MiB checksum, got:\n{out}"
+    );
+    println!("rejecting a reversed range");
+    q.send("sha256 80400008 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains("error: end < start"),
+        "expected a range error, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_calc_and_expression_arguments() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("evaluating with precedence, prefixes and the current address");
+    q.send("@80400000");
+    q.receive();
+    q.send("calc . + #4096 * 2");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    0000000080402000") && out.contains("dec    2151686144"),
+        "expected . + 8192, got:\n{out}"
+    );
+    q.send("calc (0b1010 | 0d5) << 4");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    00000000000000f0") && out.contains("bin    11110000"),
+        "expected f0, got:\n{out}"
+    );
+    q.send("calc -1");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    ffffffffffffffff") && out.contains("signed -1"),
+        "expected -1, got:\n{out}"
+    );
+    println!("reporting invalid expressions");
+    q.send("calc 1/0");
+    let out = q.receive();
+    assert!(
+        out.contains("error: division by zero"),
+        "expected a division error, got:\n{out}"
+    );
+    q.send("calc nosuchname");
+    let out = q.receive();
+    assert!(
+        out.contains("error: unknown name"),
+        "expected a name error, got:\n{out}"
+    );
+    println!("using expressions as command arguments");
+    q.send(".: 31 32 33 34 35 36 37 38 39");
+    q.receive();
+    // The write moved `.` past the bytes.
+    q.send("@80400000");
+    q.receive();
+    q.send("crc32 . .+#8");
+    let out = q.receive();
+    assert!(
+        out.contains("crc32 80400000..80400008 (9 bytes): cbf43926"),
+        "expected the CRC of the written bytes, got:\n{out}"
+    );
+    println!("dumping ranges given as expressions");
+    q.send(".+(4*2)");
+    let out = q.receive();
+    assert!(
+        out.contains("80400000: 31 32 33 34 35 36 37 38") && !out.contains("38 39"),
+        "expected 8 bytes from ., got:\n{out}"
+    );
+    q.send("(.+2).(.+4).as_str");
+    let out = q.receive();
+    assert!(
+        out.contains("80400002: 345"),
+        "expected an ASCII dump of .+2 to .+4, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_variables_in_commands() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("setting and using a variable");
+    q.send("set buf = 80400000 + #16");
+    q.receive();
+    q.send("buf: 31 32 33 34 35 36 37 38 39");
+    q.receive();
+    q.send("crc32 buf buf+8");
+    let out = q.receive();
+    assert!(
+        out.contains("crc32 80400010..80400018 (9 bytes): cbf43926"),
+        "expected the CRC at buf, got:\n{out}"
+    );
+    println!("listing built-ins and user variables");
+    q.send("vars");
+    let out = q.receive();
+    assert!(
+        out.contains("stack_top") && out.contains("(built-in)"),
+        "expected built-ins, got:\n{out}"
+    );
+    assert!(
+        out.contains("buf                      0000000080400010"),
+        "expected buf, got:\n{out}"
+    );
+    assert!(
+        !out.contains("entry"),
+        "expected no entry before a load, got:\n{out}"
+    );
+    println!("rejecting reserved names");
+    q.send("set a0 = 1");
+    let out = q.receive();
+    assert!(
+        out.contains("error: name is a register or built-in"),
+        "expected a register refusal, got:\n{out}"
+    );
+    q.send("set cafe = 1");
+    let out = q.receive();
+    assert!(
+        out.contains("error: name would read as a hex number"),
+        "expected a hex refusal, got:\n{out}"
+    );
+    q.send("unset ram_base");
+    let out = q.receive();
+    assert!(
+        out.contains("error: built-in variables cannot be unset"),
+        "expected a built-in refusal, got:\n{out}"
+    );
+    println!("unsetting");
+    q.send("unset buf");
+    q.receive();
+    q.send("calc buf");
+    let out = q.receive();
+    assert!(
+        out.contains("error: unknown name"),
+        "expected buf to be gone, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_symbols_from_elf_and_paste() {
+    println!("starting QEMU with riscmon's own ELF attached");
+    let fw_cfg = format!("name=opt/riscmon/riscmon.elf,file={}", kernel_path());
+    let mut q = QemuHarness::spawn_with_args(&kernel_path(), &["-fw_cfg", &fw_cfg]);
+    q.send("fwcfg load opt/riscmon/riscmon.elf 80400000");
+    let out = q.receive();
+    assert!(
+        out.contains(" symbols") && !out.contains("error"),
+        "expected the ELF's symbols to load, got:\n{out}"
+    );
+    println!("looking symbols up by name and address");
+    q.send("sym _start");
+    let out = q.receive();
+    assert!(
+        out.contains("_start = 80000000"),
+        "expected _start at the load address, got:\n{out}"
+    );
+    q.send("sym _start+4");
+    let out = q.receive();
+    assert!(
+        out.contains("80000004 <_start+4>"),
+        "expected an offset into _start, got:\n{out}"
+    );
+    q.send("calc FINDME");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    00000000800"),
+        "expected FINDME inside riscmon, got:\n{out}"
+    );
+    println!("annotating dumps");
+    q.send("80000000+4");
+    let out = q.receive();
+    assert!(
+        out.contains("<_start>"),
+        "expected a dump annotation, got:\n{out}"
+    );
+    println!("pasting nm-style symbols");
+    q.send("sym clear");
+    q.receive();
+    q.send("sym paste");
+    q.receive();
+    q.send("0000000080400000 T payload_main");
+    q.receive();
+    q.send("80400100 helper");
+    q.receive();
+    q.send("");
+    let out = q.receive();
+    assert!(
+        out.contains("2 symbols"),
+        "expected two pasted symbols, got:\n{out}"
+    );
+    q.send("sym 80400104");
+    let out = q.receive();
+    assert!(
+        out.contains("80400104 <payload_main+104>"),
+        "expected payload_main to run up to helper, got:\n{out}"
+    );
+    q.send("sym 80400110");
+    let out = q.receive();
+    assert!(
+        out.contains("80400110 (no symbol)"),
+        "expected nothing past the last pasted symbol, got:\n{out}"
+    );
+    q.send("sym 80000000");
+    let out = q.receive();
+    assert!(
+        out.contains("80000000 (no symbol)"),
+        "expected no symbol below the pasted ones, got:\n{out}"
+    );
+    q.kill();
+}
+
+#[test]
+fn test_history_and_line_editing() {
+    let mut q = QemuHarness::spawn(&kernel_path());
+    println!("listing history");
+    q.send("calc 1+1");
+    q.receive();
+    q.send("history");
+    let out = q.receive();
+    assert!(
+        out.contains("  1    calc 1+1") && out.contains("  2    history"),
+        "expected numbered entries, got:\n{out}"
+    );
+    println!("re-running with !N");
+    q.send("!1");
+    let out = q.receive();
+    assert!(
+        out.contains("calc 1+1") && out.contains("hex    0000000000000002"),
+        "expected calc 1+1 to run again, got:\n{out}"
+    );
+    q.send("!99");
+    let out = q.receive();
+    assert!(
+        out.contains("error: no such history entry"),
+        "expected a missing-entry error, got:\n{out}"
+    );
+    println!("recalling with the Up arrow");
+    q.send("calc 7\x1b[A");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    0000000000000002"),
+        "expected the recalled command to run, got:\n{out}"
+    );
+    println!("editing in the middle of the line");
+    q.send("alc 5*3\x01c");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    000000000000000f"),
+        "expected Ctrl+A then an insert to give calc 5*3, got:\n{out}"
+    );
+    q.send("calc 1+2+3\x1b[D\x1b[D\x0b");
+    let out = q.receive();
+    assert!(
+        out.contains("hex    0000000000000003"),
+        "expected Ctrl+K to cut +3, got:\n{out}"
+    );
+    q.kill();
+}